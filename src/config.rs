@@ -6,29 +6,107 @@ use crate::importers::revolut::RevolutConfig;
 use crate::importers::{flatex_csv::FlatexCsvConfig, flatex_inv::FlatexPdfConfig};
 
 use crate::error::{ImportError, Result};
+use crate::hledger::format::is_valid_commodity_rule;
+use crate::hledger::output::TransactionState;
+use bigdecimal::{BigDecimal, RoundingMode};
+use chrono::FixedOffset;
 use homedir::get_my_home;
-use regex::RegexBuilder;
+use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::process::Command;
 use std::str::FromStr;
+use std::time::Duration;
 
 /// encapsulation of the application configuration
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct ImporterConfig {
     #[serde(default)]
     pub hledger: HledgerConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
     pub commodity_formatting_rules: Option<Vec<String>>,
+    #[serde(default)]
+    pub commodities: CommodityConfig,
     pub ibans: Vec<IbanMapping>,
     pub cards: Vec<CardMapping>,
     pub mapping: Vec<SimpleMapping>,
+    /// splits the offset posting of matching transactions across several accounts
+    #[serde(default)]
+    pub split: Vec<SplitRule>,
+    /// maps the IBAN of the counterparty to the offset account
+    #[serde(default)]
+    pub iban_mapping: Vec<PartnerIbanMapping>,
     #[serde(default)]
     pub categories: Vec<CategoryMapping>,
     pub creditor_and_debitor_mapping: Vec<CreditorDebitorMapping>,
     pub sepa: SepaConfig,
+    /// maps SEPA purpose codes (e.g. `SALA`, `RENT`) to hledger accounts
+    #[serde(default)]
+    pub sepa_purpose: HashMap<String, String>,
+    /// maps loan references (Erste `loanReference`) to hledger accounts, e.g. the liability of the loan
+    #[serde(default)]
+    pub loan_mapping: HashMap<String, String>,
+    /// maps names of standing orders (Erste `instructionName`) to hledger accounts
+    #[serde(default)]
+    pub standing_order_mapping: HashMap<String, String>,
     pub transfer_accounts: TransferAccounts,
+    /// regular expressions for transaction types or categories of cash withdrawals (e.g. `^ATM$`),
+    /// matching transactions are posted to the cash transfer account
+    #[serde(default)]
+    pub cash_withdrawals: Vec<String>,
+    /// overrides the transaction state derived by the importers, maps a status of the input file to a state
+    #[serde(default)]
+    pub state_rules: HashMap<String, TransactionState>,
+    /// date of the input file used as transaction date
+    #[serde(default)]
+    pub date_basis: DateBasis,
+    /// overrides `date_basis` for single importers, e.g. `cardcomplete = "value"`
+    #[serde(default)]
+    pub importer_date_basis: HashMap<String, DateBasis>,
+    /// prefix and suffix of the notes of single importers, e.g. `revolut = { note_prefix = "[Revolut] " }`
+    #[serde(default)]
+    pub importer_notes: HashMap<String, NoteAffixes>,
+    /// replaces the commodity of the input file for single importers, e.g. `revolut = "EUR"`
+    #[serde(default)]
+    pub override_commodity: HashMap<String, String>,
     #[serde(default)]
     pub filter: WordFilter,
     /// a fallback account can be set to balance postings that could not be assigned to any other account
     pub fallback_account: Option<String>,
+    /// asset account used if the own account (IBAN or card) of a transaction is not mapped,
+    /// otherwise such transactions are treated like transactions without postings (see `empty_postings`)
+    pub missing_asset_account: Option<String>,
+    /// account for the residual of conversions with explicit amounts, e.g. rounding differences of the exchange rate
+    pub conversion_difference_account: Option<String>,
+    /// transactions whose amount is smaller than this (absolute value) are dropped, e.g. interest of `0.001`
+    #[serde(default, deserialize_with = "decimal_option")]
+    pub min_abs_amount: Option<BigDecimal>,
+    /// account for the transactions below `min_abs_amount` instead of dropping them
+    pub dust_account: Option<String>,
+    /// accounts (or parent accounts) in the casing of the journal, postings whose accounts only differ
+    /// in casing are rewritten, e.g. `expenses:food` becomes `Expenses:Food`
+    #[serde(default)]
+    pub canonical_accounts: Vec<String>,
+    /// allowed commodities (after normalization), other commodities abort the import (if not empty)
+    #[serde(default)]
+    pub strict_commodities: Vec<String>,
+    /// book foreign card purchases in the original currency, priced in the home currency
+    #[serde(default)]
+    pub prefer_original_currency: bool,
+    /// defines what happens with transactions that end up without any postings
+    #[serde(default)]
+    pub empty_postings: EmptyPostingsHandling,
+    /// timezone used to derive the date of timestamps that carry a UTC offset
+    #[serde(default)]
+    pub timezone: TargetTimezone,
+    /// character encoding of CSV input files (e.g. `windows-1252`), UTF-8 is expected if not set
+    pub input_encoding: Option<String>,
+    /// reject CSV input files with missing or unexpected columns (e.g. after format changes of the bank)
+    #[serde(default)]
+    pub strict: bool,
+    /// file that keeps the state between two imports (located next to the configuration file by default)
+    pub state_file: Option<std::path::PathBuf>,
     #[cfg(feature = "revolut")]
     pub revolut: Option<RevolutConfig>,
     #[cfg(feature = "flatex")]
@@ -62,14 +140,131 @@ impl ImporterConfig {
     }
 
     pub fn load() -> Result<Self> {
-        let path = Self::path()?;
-        let config_str = std::fs::read_to_string(&path);
-        match config_str {
-            Ok(config_str) => match toml::from_str::<ImporterConfig>(&config_str) {
-                Ok(config) => Ok(config),
-                Err(parse_err) => Err(ImportError::ConfigParse(parse_err)),
-            },
-            Err(_) => Err(ImportError::ConfigRead(path)),
+        Self::load_from(&Self::path()?)
+    }
+
+    /// Loads the configuration file, including all files listed in its `include` key
+    /// (relative paths are resolved relative to the including file).
+    /// Arrays of included files are appended, tables are merged and scalar values must not conflict.
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        let config = load_table(path, &mut Vec::new())?;
+        let config = toml::Value::Table(config).try_into::<ImporterConfig>()?;
+        config.check_mapping_conflicts()?;
+        config.check_commodity_rules()?;
+        Ok(config)
+    }
+
+    /// Fails if a commodity formatting rule (global or of an importer section) is no amount with commodity,
+    /// which would otherwise only be reported by `hledger print`.
+    pub fn check_commodity_rules(&self) -> Result<()> {
+        let mut rules: Vec<(&str, &String)> = Vec::new();
+        if let Some(global) = &self.commodity_formatting_rules {
+            rules.extend(global.iter().map(|r| ("commodity_formatting_rules", r)));
+        }
+        #[cfg(feature = "revolut")]
+        if let Some(revolut) = &self.revolut {
+            rules.extend(
+                revolut
+                    .commodity_formatting_rules
+                    .iter()
+                    .map(|r| ("revolut", r)),
+            );
+        }
+        #[cfg(feature = "flatex")]
+        if let Some(flatex) = &self.flatex_csv {
+            rules.extend(
+                flatex
+                    .commodity_formatting_rules
+                    .iter()
+                    .map(|r| ("flatex_csv", r)),
+            );
+        }
+        #[cfg(feature = "flatex")]
+        if let Some(flatex) = &self.flatex_pdf {
+            rules.extend(
+                flatex
+                    .commodity_formatting_rules
+                    .iter()
+                    .map(|r| ("flatex_pdf", r)),
+            );
+        }
+        #[cfg(feature = "paypal")]
+        if let Some(paypal) = &self.paypal {
+            rules.extend(
+                paypal
+                    .commodity_formatting_rules
+                    .iter()
+                    .map(|r| ("paypal", r)),
+            );
+        }
+
+        match rules.into_iter().find(|(_, rule)| !is_valid_commodity_rule(rule)) {
+            Some((section, rule)) => Err(ImportError::InvalidConfig(format!(
+                "invalid commodity formatting rule \"{}\" in {}, expected an amount with commodity like \"1.000,00 EUR\"",
+                rule, section
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails if the same IBAN, card, creditor ID or mandate ID is mapped to different accounts.
+    /// Only the first of these mappings would ever be used.
+    /// Statuses of `state_rules` and commodity aliases are looked up ignoring letter case, so keys that
+    /// only differ in case (e.g. `pending` and `PENDING`) must not be mapped to different targets either.
+    pub fn check_mapping_conflicts(&self) -> Result<()> {
+        let mut conflicts = Vec::new();
+        conflicts.extend(mapping_conflicts(
+            "ibans",
+            self.ibans.iter().map(|m| (&m.iban, &m.account)),
+        ));
+        conflicts.extend(mapping_conflicts(
+            "cards",
+            self.cards.iter().map(|m| (&m.card, &m.account)),
+        ));
+        conflicts.extend(mapping_conflicts(
+            "sepa.creditors",
+            self.sepa
+                .creditors
+                .iter()
+                .map(|m| (&m.creditor_id, &m.account)),
+        ));
+        conflicts.extend(mapping_conflicts(
+            "sepa.mandates",
+            self.sepa
+                .mandates
+                .iter()
+                .map(|m| (&m.mandate_id, &m.account)),
+        ));
+        // sorted, so that the conflicts are reported in a stable order
+        let mut state_rules: Vec<(String, String)> = self
+            .state_rules
+            .iter()
+            .map(|(status, state)| (status.trim().to_lowercase(), format!("{:?}", state)))
+            .collect();
+        state_rules.sort();
+        conflicts.extend(mapping_conflicts(
+            "state_rules",
+            state_rules.iter().map(|(status, state)| (status, state)),
+        ));
+        let mut aliases: Vec<(String, &String)> = self
+            .commodities
+            .aliases
+            .iter()
+            .map(|(alias, commodity)| (alias.trim().to_lowercase(), commodity))
+            .collect();
+        aliases.sort();
+        conflicts.extend(mapping_conflicts(
+            "commodities.aliases",
+            aliases.iter().map(|(alias, commodity)| (alias, *commodity)),
+        ));
+
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(ImportError::InvalidConfig(format!(
+                "conflicting mappings: {}",
+                conflicts.join("; ")
+            )))
         }
     }
 
@@ -90,6 +285,29 @@ impl ImporterConfig {
             })
     }
 
+    pub fn match_partner_iban_opt(&self, iban: &Option<String>) -> Option<ImporterConfigTarget> {
+        match iban {
+            Some(iban) => self.match_partner_iban(iban),
+            None => None,
+        }
+    }
+
+    /// matches the IBAN of the counterparty (spaces and letter case are ignored)
+    pub fn match_partner_iban(&self, iban: &str) -> Option<ImporterConfigTarget> {
+        let normalize = |iban: &str| iban.replace(' ', "").to_uppercase();
+        let iban = normalize(iban);
+        if iban.is_empty() {
+            return None;
+        }
+        self.iban_mapping
+            .iter()
+            .find(|rule| normalize(&rule.iban) == iban)
+            .map(|rule| ImporterConfigTarget {
+                account: rule.account.clone(),
+                note: rule.note.clone(),
+            })
+    }
+
     pub fn identify_card_opt(&self, card_number: &Option<String>) -> Option<ImporterConfigTarget> {
         match card_number {
             Some(card_number) => self.identify_card(card_number),
@@ -97,6 +315,15 @@ impl ImporterConfig {
         }
     }
 
+    /// configured commodity of the given card
+    pub fn card_commodity_opt(&self, card_number: &Option<String>) -> Option<String> {
+        let card_number = card_number.as_ref()?;
+        self.cards
+            .iter()
+            .find(|rule| &rule.card == card_number)
+            .and_then(|rule| rule.commodity.clone())
+    }
+
     pub fn identify_card(&self, card_number: &str) -> Option<ImporterConfigTarget> {
         self.cards
             .iter()
@@ -120,58 +347,135 @@ impl ImporterConfig {
     pub fn match_sepa_creditor_opt(
         &self,
         sepa_creditor_id: &Option<String>,
-    ) -> Option<ImporterConfigTarget> {
+    ) -> Result<Option<ImporterConfigTarget>> {
         match sepa_creditor_id {
             Some(sepa_creditor_id) => self.match_sepa_creditor(sepa_creditor_id),
-            None => None,
+            None => Ok(None),
         }
     }
 
-    pub fn match_sepa_creditor(&self, sepa_creditor_id: &str) -> Option<ImporterConfigTarget> {
-        self.sepa
+    /// exact matches of the creditor ID take precedence over matches of the regular expression
+    pub fn match_sepa_creditor(
+        &self,
+        sepa_creditor_id: &str,
+    ) -> Result<Option<ImporterConfigTarget>> {
+        let exact = self
+            .sepa
             .creditors
             .iter()
-            .find(|rule| rule.creditor_id == sepa_creditor_id)
-            .map(|rule| ImporterConfigTarget {
+            .find(|rule| !rule.creditor_id.is_empty() && rule.creditor_id == sepa_creditor_id);
+        if let Some(rule) = exact {
+            return Ok(Some(ImporterConfigTarget {
                 account: rule.account.clone(),
                 note: rule.note.clone(),
-            })
+            }));
+        }
+
+        for rule in &self.sepa.creditors {
+            if matches_regex_opt(&rule.creditor_id_regex, sepa_creditor_id)? {
+                return Ok(Some(ImporterConfigTarget {
+                    account: rule.account.clone(),
+                    note: rule.note.clone(),
+                }));
+            }
+        }
+        Ok(None)
     }
 
     pub fn match_sepa_mandate_opt(
         &self,
         sepa_mandate_id: &Option<String>,
-    ) -> Option<ImporterConfigTarget> {
+    ) -> Result<Option<ImporterConfigTarget>> {
         match sepa_mandate_id {
             Some(sepa_mandate_id) => self.match_sepa_mandate(sepa_mandate_id),
-            None => None,
+            None => Ok(None),
         }
     }
 
-    pub fn match_sepa_mandate(&self, sepa_mandate_id: &str) -> Option<ImporterConfigTarget> {
-        self.sepa
+    /// exact matches of the mandate ID take precedence over matches of the regular expression
+    pub fn match_sepa_mandate(
+        &self,
+        sepa_mandate_id: &str,
+    ) -> Result<Option<ImporterConfigTarget>> {
+        let exact = self
+            .sepa
             .mandates
             .iter()
-            .find(|rule| rule.mandate_id == sepa_mandate_id)
-            .map(|rule| ImporterConfigTarget {
+            .find(|rule| !rule.mandate_id.is_empty() && rule.mandate_id == sepa_mandate_id);
+        if let Some(rule) = exact {
+            return Ok(Some(ImporterConfigTarget {
                 account: rule.account.clone(),
                 note: rule.note.clone(),
+            }));
+        }
+
+        for rule in &self.sepa.mandates {
+            if matches_regex_opt(&rule.mandate_id_regex, sepa_mandate_id)? {
+                return Ok(Some(ImporterConfigTarget {
+                    account: rule.account.clone(),
+                    note: rule.note.clone(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn match_sepa_purpose_opt(&self, purpose: &Option<String>) -> Option<ImporterConfigTarget> {
+        match purpose {
+            Some(purpose) => self.match_sepa_purpose(purpose),
+            None => None,
+        }
+    }
+
+    pub fn match_sepa_purpose(&self, purpose: &str) -> Option<ImporterConfigTarget> {
+        self.sepa_purpose
+            .get(purpose.trim())
+            .map(|account| ImporterConfigTarget {
+                account: account.clone(),
+                note: None,
             })
     }
 
+    pub fn match_loan_reference_opt(
+        &self,
+        loan_reference: &Option<String>,
+    ) -> Option<ImporterConfigTarget> {
+        match_table(&self.loan_mapping, loan_reference)
+    }
+
+    pub fn match_standing_order_opt(
+        &self,
+        instruction_name: &Option<String>,
+    ) -> Option<ImporterConfigTarget> {
+        match_table(&self.standing_order_mapping, instruction_name)
+    }
+
     pub fn match_mapping_opt(
         &self,
         field: &Option<String>,
+        payee: &str,
+        note: &str,
     ) -> Result<Option<ImporterConfigTarget>> {
         match field {
-            Some(field) => self.match_mapping(field),
+            Some(field) => self.match_mapping_fields(field, payee, note),
             None => Ok(None),
         }
     }
 
+    /// matches the mapping rules against a field that is the payee as well (e.g. a description without note)
     pub fn match_mapping(&self, field: &str) -> Result<Option<ImporterConfigTarget>> {
+        self.match_mapping_fields(field, field, "")
+    }
+
+    /// Matches the mapping rules against the given field (`search`), the payee (`search_payee`) and the note (`search_note`).
+    pub fn match_mapping_fields(
+        &self,
+        field: &str,
+        payee: &str,
+        note: &str,
+    ) -> Result<Option<ImporterConfigTarget>> {
         for rule in &self.mapping {
-            if rule.matches(field)? {
+            if rule.matches_fields(field, payee, note)? {
                 return Ok(Some(ImporterConfigTarget {
                     account: rule.account.clone(),
                     note: rule.note.clone(),
@@ -181,6 +485,63 @@ impl ImporterConfig {
         Ok(None)
     }
 
+    /// state configured for the given status of the input file (the status is compared case-insensitively)
+    pub fn state_rule(&self, status: &str) -> Option<TransactionState> {
+        let status = status.trim();
+        self.state_rules
+            .iter()
+            .find(|(rule, _)| rule.eq_ignore_ascii_case(status))
+            .map(|(_, state)| state.clone())
+    }
+
+    /// date basis of the importer with the given slug
+    pub fn date_basis(&self, importer: &str) -> DateBasis {
+        self.importer_date_basis
+            .get(importer)
+            .copied()
+            .unwrap_or(self.date_basis)
+    }
+
+    /// Adds the configured prefix and suffix of the importer to the note.
+    /// Transactions without note only get one (prefix and suffix), if `force_note` is set.
+    pub fn importer_note(&self, importer: &str, note: Option<String>) -> Option<String> {
+        let Some(affixes) = self.importer_notes.get(importer) else {
+            return note;
+        };
+        let prefix = affixes.note_prefix.as_deref().unwrap_or_default();
+        let suffix = affixes.note_suffix.as_deref().unwrap_or_default();
+        match note {
+            Some(note) => Some(format!("{}{}{}", prefix, note, suffix)),
+            None if affixes.force_note => Some(format!("{}{}", prefix, suffix).trim().to_owned())
+                .filter(|note| !note.is_empty()),
+            None => None,
+        }
+    }
+
+    /// the cash transfer account, if the type or category of a transaction marks it as cash withdrawal
+    pub fn match_cash_withdrawal(&self, field: &str) -> Result<Option<ImporterConfigTarget>> {
+        for pattern in &self.cash_withdrawals {
+            let regex = RegexBuilder::new(pattern).case_insensitive(true).build()?;
+            if !field.is_empty() && regex.is_match(field) {
+                return Ok(Some(ImporterConfigTarget {
+                    account: self.transfer_accounts.cash.clone(),
+                    note: None,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// asset account of transactions whose own account (IBAN or card) is not mapped
+    pub fn missing_asset(&self) -> Option<ImporterConfigTarget> {
+        self.missing_asset_account
+            .as_ref()
+            .map(|account| ImporterConfigTarget {
+                account: account.clone(),
+                note: None,
+            })
+    }
+
     pub fn fallback(&self) -> Option<ImporterConfigTarget> {
         self.fallback_account
             .as_ref()
@@ -198,14 +559,260 @@ pub struct ImporterConfigTarget {
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
 pub struct HledgerConfig {
     pub path: String,
+    /// journal read by hledger queries, `$LEDGER_FILE` (or hledger's default) if not set
+    pub journal_file: Option<std::path::PathBuf>,
+    /// seconds after which a hledger invocation is aborted, 30 by default
+    pub timeout: Option<u64>,
+    /// what happens if hledger can not be found or fails when the output is formatted
+    #[serde(default)]
+    pub format_fallback: FormatFallback,
 }
 
 impl Default for HledgerConfig {
     fn default() -> Self {
         Self {
             path: "hledger".to_owned(),
+            journal_file: None,
+            timeout: None,
+            format_fallback: FormatFallback::default(),
+        }
+    }
+}
+
+/// Handling of a missing or failing hledger executable when the output is formatted with `hledger print`
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatFallback {
+    /// emit the transactions as rendered by the importer with a warning
+    Raw,
+    /// abort with an error
+    #[default]
+    Error,
+}
+
+impl HledgerConfig {
+    /// hledger command reading the configured journal file
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        if let Some(journal_file) = &self.journal_file {
+            command.arg("-f").arg(journal_file);
+        }
+        command
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(30))
+    }
+}
+
+/// Date of the input file that becomes the transaction date, the other date is kept as tag
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DateBasis {
+    /// the booking (posting) date, the value date is kept as `valuation` tag
+    #[default]
+    Booking,
+    /// the value (purchase) date, the booking date is kept as `booking` tag
+    Value,
+}
+
+/// Text added before and after the notes of an importer
+#[derive(Debug, Deserialize, PartialEq, Eq, Default)]
+pub struct NoteAffixes {
+    pub note_prefix: Option<String>,
+    pub note_suffix: Option<String>,
+    /// transactions without note get a note consisting of prefix and suffix
+    #[serde(default)]
+    pub force_note: bool,
+}
+
+/// Handling of transactions that could not be assigned to any account and therefore have no postings
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+pub enum EmptyPostingsHandling {
+    /// print a warning and skip the transaction
+    #[default]
+    Warn,
+    /// abort the import with an error
+    Error,
+}
+
+/// Reads a decimal given as TOML number or string. Floats are converted via their shortest
+/// representation, so that `0.01` is exactly one cent and not the nearest binary fraction.
+fn decimal_option<'de, D>(deserializer: D) -> std::result::Result<Option<BigDecimal>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = match Option::<toml::Value>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(toml::Value::Integer(i)) => return Ok(Some(BigDecimal::from(i))),
+        Some(toml::Value::Float(f)) => f.to_string(),
+        Some(toml::Value::String(s)) => s,
+        Some(other) => {
+            return Err(serde::de::Error::custom(format!(
+                "expected a decimal number, found {}",
+                other.type_str()
+            )))
+        }
+    };
+    BigDecimal::from_str(value.trim())
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Timezone in which the dates of imported timestamps are interpreted.
+/// Configured as `"source"` (keep the offset of the timestamp), `"local"` (timezone of the system)
+/// or as fixed UTC offset (e.g. `"+01:00"`).
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+#[serde(try_from = "String")]
+pub enum TargetTimezone {
+    #[default]
+    Source,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl TryFrom<String> for TargetTimezone {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "source" => Ok(TargetTimezone::Source),
+            "local" => Ok(TargetTimezone::Local),
+            _ => match FixedOffset::from_str(&value) {
+                Ok(offset) => Ok(TargetTimezone::Fixed(offset)),
+                Err(_) => Err(format!("invalid timezone \"{}\"", value)),
+            },
+        }
+    }
+}
+
+/// Placement of the commodity of amounts
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+pub enum CommodityStyle {
+    /// `12.34 EUR`
+    #[default]
+    Suffix,
+    /// `EUR 12.34`
+    PrefixCode,
+    /// `€12.34` (with the symbol of `commodity_symbols`, or like `PrefixCode` without symbol)
+    PrefixSymbol,
+}
+
+/// Rounding modes for amounts, half-even (banker's rounding) by default
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+pub enum AmountRounding {
+    #[default]
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Up,
+    Down,
+}
+
+impl From<AmountRounding> for RoundingMode {
+    fn from(value: AmountRounding) -> Self {
+        match value {
+            AmountRounding::HalfEven => RoundingMode::HalfEven,
+            AmountRounding::HalfUp => RoundingMode::HalfUp,
+            AmountRounding::HalfDown => RoundingMode::HalfDown,
+            AmountRounding::Up => RoundingMode::Up,
+            AmountRounding::Down => RoundingMode::Down,
+        }
+    }
+}
+
+/// Normalization of the commodities given in the input files
+#[derive(Debug, Deserialize, PartialEq, Eq, Default)]
+pub struct CommodityConfig {
+    /// map currency symbols to ISO 4217 codes, uppercase codes and warn about unknown commodities
+    #[serde(default)]
+    pub normalize: bool,
+    /// maps commodities of the input files (compared case-insensitively) to the commodity used in the journal
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+}
+
+/// Options that control how the resulting hledger transactions are rendered
+#[derive(Debug, Deserialize, PartialEq, Eq, Default)]
+pub struct OutputConfig {
+    /// emit the note as a comment line instead of appending it to the payee (`payee | note`)
+    #[serde(default)]
+    pub note_as_comment: bool,
+    /// notes longer than this number of characters are emitted as comment lines wrapped at this width
+    pub note_max_width: Option<usize>,
+    /// omit the `(code)` in the first line of a transaction and keep it only as `code` tag
+    #[serde(default)]
+    pub suppress_code: bool,
+    /// command (program and arguments) the final journal text is piped through before printing
+    pub post_process_command: Option<Vec<String>>,
+    /// additionally attach the time of day (if available) as `time` tag to the asset posting
+    #[serde(default)]
+    pub posting_time_tag: bool,
+    /// tags (by name) that are removed from all transactions and postings, e.g. `["revolut_type"]`
+    #[serde(default)]
+    pub suppress_tags: Vec<String>,
+    /// use a content hash as code of transactions without code (bank reference), so that they can be deduplicated
+    #[serde(default)]
+    pub hash_codes: bool,
+    /// attach the name of the importer as `bank` tag to every transaction
+    #[serde(default)]
+    pub tag_importer: bool,
+    /// render the date of the `valuation` tag as secondary date (`2024-06-03=2024-06-01`) instead of the tag
+    #[serde(default)]
+    pub valuation_as_date2: bool,
+    /// emit a `commodity` directive for every commodity formatting rule before the transactions
+    #[serde(default)]
+    pub commodity_directives: bool,
+    /// attach the fields of the input row as `raw` tag to every transaction (CSV importers)
+    #[serde(default)]
+    pub tag_raw_record: bool,
+    /// deduct fees from the asset posting and keep them as its comment instead of separate fee postings (Revolut, PayPal)
+    #[serde(default)]
+    pub collapse_fees: bool,
+    /// keep the precision of amounts as given in the input file (e.g. `150.00`) instead of removing trailing zeros
+    #[serde(default)]
+    pub keep_amount_precision: bool,
+    /// round amounts to this number of decimal places (amounts are kept as given in the input file if not set)
+    pub amount_decimals: Option<i64>,
+    /// rounding mode used for `amount_decimals`
+    #[serde(default)]
+    pub rounding_mode: AmountRounding,
+    /// sort the postings of a transaction (postings with amount first, then by account, the plug posting last)
+    #[serde(default)]
+    pub sort_postings: bool,
+    /// placement of the commodity of amounts, after the number by default
+    #[serde(default)]
+    pub commodity_style: CommodityStyle,
+    /// symbols of commodities for the `PrefixSymbol` style, e.g. `EUR = "€"`
+    #[serde(default)]
+    pub commodity_symbols: HashMap<String, String>,
+    /// number of spaces postings and comments are indented with, 4 (like `hledger print`) by default
+    pub indent: Option<usize>,
+    /// separator used to join notes from several sources (e.g. account note and transaction note), `, ` by default
+    pub note_separator: Option<String>,
+}
+
+impl OutputConfig {
+    pub fn indentation(&self) -> String {
+        " ".repeat(self.indent.unwrap_or(4))
+    }
+
+    /// joins all non-empty notes (in the given order), duplicates are dropped
+    pub fn join_notes<I: IntoIterator<Item = Option<String>>>(&self, notes: I) -> Option<String> {
+        let mut result: Vec<String> = Vec::new();
+        for note in notes.into_iter().flatten() {
+            let note = note.trim();
+            if !note.is_empty() && !result.iter().any(|n| n == note) {
+                result.push(note.to_owned());
+            }
+        }
+        if result.is_empty() {
+            None
+        } else {
+            Some(result.join(self.note_separator.as_deref().unwrap_or(", ")))
         }
     }
 }
@@ -219,6 +826,38 @@ pub struct IbanMapping {
     pub note: Option<String>,
 }
 
+/// Splits the offset posting of transactions whose payee matches the regular expression
+/// across several accounts, e.g. a combined utility bill
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct SplitRule {
+    pub search: String,
+    pub accounts: Vec<SplitTarget>,
+}
+
+/// Account and its share (in percent) of a split
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct SplitTarget {
+    pub account: String,
+    pub percentage: BigDecimal,
+}
+
+impl SplitRule {
+    pub fn matches(&self, payee: &str) -> Result<bool> {
+        let regex = RegexBuilder::new(&self.search)
+            .case_insensitive(true)
+            .build()?;
+        Ok(!payee.is_empty() && regex.is_match(payee))
+    }
+}
+
+/// Maps the IBAN of a counterparty (e.g. landlord, employer) to a hledger account
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct PartnerIbanMapping {
+    pub iban: String,
+    pub account: String,
+    pub note: Option<String>,
+}
+
 /// Maps a credit card number (or identifier) to a hleger asset/liability account
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct CardMapping {
@@ -226,6 +865,8 @@ pub struct CardMapping {
     pub account: String,
     pub fees_account: Option<String>,
     pub note: Option<String>,
+    /// commodity of the card, used for transactions of the export without currency
+    pub commodity: Option<String>,
 }
 
 /// Encapsulates configuration of SEPA-payment identification
@@ -238,7 +879,10 @@ pub struct SepaConfig {
 /// Maps SEPA-Mandate ID to hledger account
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct SepaMandateMapping {
+    #[serde(default)]
     pub mandate_id: String,
+    /// regular expression that is tried, if no rule matches the mandate ID exactly
+    pub mandate_id_regex: Option<String>,
     pub account: String,
     pub note: Option<String>,
 }
@@ -246,11 +890,138 @@ pub struct SepaMandateMapping {
 /// Maps SEPA-Creditor ID to hledger account
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct SepaCreditorMapping {
+    #[serde(default)]
     pub creditor_id: String,
+    /// regular expression that is tried, if no rule matches the creditor ID exactly
+    pub creditor_id_regex: Option<String>,
     pub account: String,
     pub note: Option<String>,
 }
 
+/// keys (ignoring empty keys of regular expression rules) that are mapped to more than one account
+fn mapping_conflicts<'a, I: Iterator<Item = (&'a String, &'a String)>>(
+    section: &str,
+    mappings: I,
+) -> Vec<String> {
+    let mut accounts: Vec<(&String, Vec<&String>)> = Vec::new();
+    for (key, account) in mappings.filter(|(key, _)| !key.is_empty()) {
+        match accounts.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, list)) if !list.contains(&account) => list.push(account),
+            Some(_) => {}
+            None => accounts.push((key, vec![account])),
+        }
+    }
+    accounts
+        .into_iter()
+        .filter(|(_, list)| list.len() > 1)
+        .map(|(key, list)| {
+            let list: Vec<String> = list.iter().map(|a| format!("\"{}\"", a)).collect();
+            format!(
+                "{} \"{}\" is mapped to {}",
+                section,
+                key,
+                list.join(" and ")
+            )
+        })
+        .collect()
+}
+
+/// reads a TOML file and resolves its includes recursively (`visited` guards against include cycles)
+fn load_table(
+    path: &std::path::Path,
+    visited: &mut Vec<std::path::PathBuf>,
+) -> Result<toml::Table> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|_| ImportError::ConfigRead(path.to_owned()))?;
+    if visited.contains(&canonical) {
+        return Err(ImportError::ConfigInclude(format!(
+            "\"{}\" is included recursively",
+            path.display()
+        )));
+    }
+    visited.push(canonical);
+
+    let config_str =
+        std::fs::read_to_string(path).map_err(|_| ImportError::ConfigRead(path.to_owned()))?;
+    let mut table = toml::from_str::<toml::Table>(&config_str)?;
+
+    let includes = match table.remove("include") {
+        Some(toml::Value::Array(includes)) => includes,
+        Some(_) => {
+            return Err(ImportError::ConfigInclude(
+                "\"include\" must be an array of file names".to_owned(),
+            ))
+        }
+        None => Vec::new(),
+    };
+    let dir = path.parent().unwrap_or(std::path::Path::new("."));
+    for include in includes {
+        let include = match include {
+            toml::Value::String(include) => dir.join(include),
+            _ => {
+                return Err(ImportError::ConfigInclude(
+                    "\"include\" must be an array of file names".to_owned(),
+                ))
+            }
+        };
+        let included = load_table(&include, visited)?;
+        merge_tables(&mut table, included, "")?;
+    }
+
+    visited.pop();
+    Ok(table)
+}
+
+fn merge_tables(target: &mut toml::Table, source: toml::Table, prefix: &str) -> Result<()> {
+    for (key, value) in source {
+        let name = format!("{}{}", prefix, key);
+        match (target.get_mut(&key), value) {
+            (None, value) => {
+                target.insert(key, value);
+            }
+            (Some(toml::Value::Array(existing)), toml::Value::Array(additional)) => {
+                existing.extend(additional);
+            }
+            (Some(toml::Value::Table(existing)), toml::Value::Table(additional)) => {
+                merge_tables(existing, additional, &format!("{}.", name))?;
+            }
+            (Some(existing), value) => {
+                if *existing != value {
+                    return Err(ImportError::ConfigInclude(format!(
+                        "conflicting values for \"{}\"",
+                        name
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// exact match of the (trimmed) key in a table of accounts
+fn match_table(
+    table: &HashMap<String, String>,
+    key: &Option<String>,
+) -> Option<ImporterConfigTarget> {
+    key.as_ref()
+        .and_then(|key| table.get(key.trim()))
+        .map(|account| ImporterConfigTarget {
+            account: account.clone(),
+            note: None,
+        })
+}
+
+fn matches_regex_opt(regex: &Option<String>, field: &str) -> Result<bool> {
+    match regex {
+        Some(regex) => {
+            let regex = RegexBuilder::new(regex).case_insensitive(true).build()?;
+            Ok(!field.is_empty() && regex.is_match(field))
+        }
+        None => Ok(false),
+    }
+}
+
 /// Definition of the hledger accounts that should be used to post bank transfers and cash transfers
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct TransferAccounts {
@@ -261,15 +1032,48 @@ pub struct TransferAccounts {
 /// Search for given regular expression and post to account, if the search matches
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct SimpleMapping {
+    /// matched against the text fields of a transaction, may be omitted if `search_payee` or `search_note` is given
+    #[serde(default)]
     pub search: String,
+    /// additionally has to match the payee
+    pub search_payee: Option<String>,
+    /// additionally has to match the note (e.g. the reference or posting text)
+    pub search_note: Option<String>,
     pub account: String,
     pub note: Option<String>,
+    /// match `search` case-sensitively (e.g. to distinguish ticker symbols), case-insensitive by default
+    #[serde(default)]
+    pub case_sensitive: bool,
 }
 
 impl SimpleMapping {
     pub fn matches(&self, field: &str) -> Result<bool> {
-        let regex = RegexBuilder::new(&self.search)
-            .case_insensitive(true)
+        self.matches_regex(&self.search, field)
+    }
+
+    /// All given searches have to match: `search` the field, `search_payee` the payee and `search_note` the note.
+    pub fn matches_fields(&self, field: &str, payee: &str, note: &str) -> Result<bool> {
+        let field_search =
+            !self.search.is_empty() || (self.search_payee.is_none() && self.search_note.is_none());
+        if field_search && !self.matches(field)? {
+            return Ok(false);
+        }
+        if let Some(search_payee) = &self.search_payee {
+            if !self.matches_regex(search_payee, payee)? {
+                return Ok(false);
+            }
+        }
+        if let Some(search_note) = &self.search_note {
+            if !self.matches_regex(search_note, note)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn matches_regex(&self, search: &str, field: &str) -> Result<bool> {
+        let regex = RegexBuilder::new(search)
+            .case_insensitive(!self.case_sensitive)
             .build()?;
         Ok(!field.is_empty() && regex.is_match(field))
     }
@@ -289,6 +1093,47 @@ pub struct CreditorDebitorMapping {
 #[derive(Debug, Deserialize, PartialEq, Eq, Default)]
 pub struct WordFilter {
     pub payee: Vec<FilterEntry>,
+    /// regular expressions for trailing all-caps tokens of the payee (store numbers, dates, city codes)
+    /// that are stripped when payees are normalized
+    #[serde(default)]
+    pub payee_trailing: Vec<String>,
+}
+
+impl WordFilter {
+    /// Strips trailing all-caps tokens matching any of the `payee_trailing` patterns from the payee,
+    /// e.g. `SPAR 4711 WIEN 0503` becomes `SPAR`.
+    /// Returns the cleaned payee and the removed tail (if anything was removed).
+    pub fn strip_payee_trailing(&self, payee: &str) -> Result<(String, Option<String>)> {
+        if self.payee_trailing.is_empty() {
+            return Ok((payee.to_owned(), None));
+        }
+
+        let patterns = self
+            .payee_trailing
+            .iter()
+            .map(|p| Regex::new(p))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let tokens: Vec<_> = Regex::new(r"\S+")?.find_iter(payee).collect();
+        let mut keep = tokens.len();
+        while keep > 1 {
+            let token = tokens[keep - 1].as_str();
+            let all_caps = token == token.to_uppercase();
+            if !all_caps || !patterns.iter().any(|p| p.is_match(token)) {
+                break;
+            }
+            keep -= 1;
+        }
+
+        if keep == tokens.len() {
+            return Ok((payee.to_owned(), None));
+        }
+        let split = tokens[keep].start();
+        Ok((
+            payee[..split].trim_end().to_owned(),
+            Some(payee[split..].trim().to_owned()),
+        ))
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
@@ -331,7 +1176,11 @@ mod tests {
         let expected = ImporterConfig {
             hledger: HledgerConfig {
                 path: "/opt/homebrew/bin/hledger".to_owned(),
+                journal_file: None,
+                timeout: None,
+                format_fallback: FormatFallback::Error,
             },
+            output: OutputConfig::default(),
             commodity_formatting_rules: None,
             ibans: vec![],
             cards: vec![],
@@ -341,12 +1190,36 @@ mod tests {
                 creditors: vec![],
                 mandates: vec![],
             },
+            commodities: CommodityConfig::default(),
+            split: Vec::new(),
+            iban_mapping: Vec::new(),
+            state_rules: HashMap::new(),
+            date_basis: DateBasis::default(),
+            importer_date_basis: HashMap::new(),
+            importer_notes: HashMap::new(),
+            override_commodity: HashMap::new(),
+            sepa_purpose: HashMap::new(),
+            loan_mapping: HashMap::new(),
+            standing_order_mapping: HashMap::new(),
             transfer_accounts: TransferAccounts {
                 bank: "Assets:Bank".to_owned(),
                 cash: "Assets:Cash".to_owned(),
             },
+            cash_withdrawals: Vec::new(),
             filter: WordFilter::default(),
             fallback_account: Some("Equity:Unassigned".to_owned()),
+            missing_asset_account: None,
+            conversion_difference_account: None,
+            min_abs_amount: None,
+            dust_account: None,
+            strict_commodities: Vec::new(),
+            canonical_accounts: Vec::new(),
+            prefer_original_currency: false,
+            empty_postings: EmptyPostingsHandling::default(),
+            timezone: TargetTimezone::default(),
+            input_encoding: None,
+            strict: false,
+            state_file: None,
             #[cfg(feature = "revolut")]
             revolut: None,
             categories: vec![],
@@ -384,6 +1257,7 @@ mod tests {
         .to_owned();
         let expected = ImporterConfig {
             hledger: HledgerConfig::default(),
+            output: OutputConfig::default(),
             commodity_formatting_rules: None,
             ibans: vec![],
             cards: vec![],
@@ -393,17 +1267,42 @@ mod tests {
                 creditors: vec![],
                 mandates: vec![],
             },
+            commodities: CommodityConfig::default(),
+            split: Vec::new(),
+            iban_mapping: Vec::new(),
+            state_rules: HashMap::new(),
+            date_basis: DateBasis::default(),
+            importer_date_basis: HashMap::new(),
+            importer_notes: HashMap::new(),
+            override_commodity: HashMap::new(),
+            sepa_purpose: HashMap::new(),
+            loan_mapping: HashMap::new(),
+            standing_order_mapping: HashMap::new(),
             transfer_accounts: TransferAccounts {
                 bank: "Assets:Bank".to_owned(),
                 cash: "Assets:Cash".to_owned(),
             },
+            cash_withdrawals: Vec::new(),
             filter: WordFilter {
                 payee: vec![FilterEntry {
                     pattern: "foo".to_owned(),
                     replacement: "bar".to_owned(),
                 }],
+                payee_trailing: vec![],
             },
             fallback_account: None,
+            missing_asset_account: None,
+            conversion_difference_account: None,
+            min_abs_amount: None,
+            dust_account: None,
+            strict_commodities: Vec::new(),
+            canonical_accounts: Vec::new(),
+            prefer_original_currency: false,
+            empty_postings: EmptyPostingsHandling::default(),
+            timezone: TargetTimezone::default(),
+            input_encoding: None,
+            strict: false,
+            state_file: None,
             #[cfg(feature = "paypal")]
             paypal: None,
             #[cfg(feature = "revolut")]
@@ -454,27 +1353,43 @@ mod tests {
         .to_owned();
         let expected = ImporterConfig {
             hledger: HledgerConfig::default(),
+            output: OutputConfig::default(),
             commodity_formatting_rules: None,
             mapping: vec![],
             creditor_and_debitor_mapping: vec![],
+            commodities: CommodityConfig::default(),
+            split: Vec::new(),
+            iban_mapping: Vec::new(),
+            state_rules: HashMap::new(),
+            date_basis: DateBasis::default(),
+            importer_date_basis: HashMap::new(),
+            importer_notes: HashMap::new(),
+            override_commodity: HashMap::new(),
+            sepa_purpose: HashMap::new(),
+            loan_mapping: HashMap::new(),
+            standing_order_mapping: HashMap::new(),
             transfer_accounts: TransferAccounts {
                 bank: "Assets:Bank".to_owned(),
                 cash: "Assets:Cash".to_owned(),
             },
+            cash_withdrawals: Vec::new(),
             cards: vec![CardMapping {
                 card: "123XXX456".to_owned(),
                 account: "Liabilities:Test".to_owned(),
                 fees_account: None,
                 note: Some("Test".to_owned()),
+                commodity: None,
             }],
             sepa: SepaConfig {
                 creditors: vec![SepaCreditorMapping {
                     creditor_id: "AT12ZZ0000000".to_owned(),
+                    creditor_id_regex: None,
                     account: "Expenses:Test".to_owned(),
                     note: None,
                 }],
                 mandates: vec![SepaMandateMapping {
                     mandate_id: "1234567890".to_owned(),
+                    mandate_id_regex: None,
                     account: "Expenses:Test2".to_owned(),
                     note: None,
                 }],
@@ -495,6 +1410,18 @@ mod tests {
             ],
             filter: WordFilter::default(),
             fallback_account: None,
+            missing_asset_account: None,
+            conversion_difference_account: None,
+            min_abs_amount: None,
+            dust_account: None,
+            strict_commodities: Vec::new(),
+            canonical_accounts: Vec::new(),
+            prefer_original_currency: false,
+            empty_postings: EmptyPostingsHandling::default(),
+            timezone: TargetTimezone::default(),
+            input_encoding: None,
+            strict: false,
+            state_file: None,
             #[cfg(feature = "revolut")]
             revolut: None,
             #[cfg(feature = "flatex")]
@@ -540,17 +1467,24 @@ mod tests {
         .to_owned();
         let expected = ImporterConfig {
             hledger: HledgerConfig::default(),
+            output: OutputConfig::default(),
             commodity_formatting_rules: None,
             mapping: vec![
                 SimpleMapping {
                     search: "Store".to_owned(),
+                    search_payee: None,
+                    search_note: None,
                     account: "Expenses:Test".to_owned(),
                     note: None,
+                    case_sensitive: false,
                 },
                 SimpleMapping {
                     search: "Lab".to_owned(),
+                    search_payee: None,
+                    search_note: None,
                     account: "Expenses:Lab".to_owned(),
                     note: Some("Note Test".to_owned()),
+                    case_sensitive: false,
                 },
             ],
             creditor_and_debitor_mapping: vec![CreditorDebitorMapping {
@@ -559,10 +1493,22 @@ mod tests {
                 default_pl_account: Some("Expenses:Specials".to_owned()),
                 days_difference: Some(3),
             }],
+            commodities: CommodityConfig::default(),
+            split: Vec::new(),
+            iban_mapping: Vec::new(),
+            state_rules: HashMap::new(),
+            date_basis: DateBasis::default(),
+            importer_date_basis: HashMap::new(),
+            importer_notes: HashMap::new(),
+            override_commodity: HashMap::new(),
+            sepa_purpose: HashMap::new(),
+            loan_mapping: HashMap::new(),
+            standing_order_mapping: HashMap::new(),
             transfer_accounts: TransferAccounts {
                 bank: "Assets:Bank".to_owned(),
                 cash: "Assets:Cash".to_owned(),
             },
+            cash_withdrawals: Vec::new(),
             cards: vec![],
             sepa: SepaConfig {
                 creditors: vec![],
@@ -571,6 +1517,18 @@ mod tests {
             ibans: vec![],
             filter: WordFilter::default(),
             fallback_account: None,
+            missing_asset_account: None,
+            conversion_difference_account: None,
+            min_abs_amount: None,
+            dust_account: None,
+            strict_commodities: Vec::new(),
+            canonical_accounts: Vec::new(),
+            prefer_original_currency: false,
+            empty_postings: EmptyPostingsHandling::default(),
+            timezone: TargetTimezone::default(),
+            input_encoding: None,
+            strict: false,
+            state_file: None,
             #[cfg(feature = "revolut")]
             revolut: None,
             #[cfg(feature = "flatex")]
@@ -584,4 +1542,510 @@ mod tests {
         let result = toml::from_str::<ImporterConfig>(&config_str).expect("TOML parsing failed");
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn output_config_from_toml_str() {
+        let config_str = "note_as_comment = true";
+        let result = toml::from_str::<OutputConfig>(config_str).expect("TOML parsing failed");
+        assert_eq!(
+            result,
+            OutputConfig {
+                note_as_comment: true,
+                ..Default::default()
+            }
+        );
+
+        let config_str = "post_process_command = [\"sed\", \"s/foo/bar/\"]";
+        let result = toml::from_str::<OutputConfig>(config_str).expect("TOML parsing failed");
+        assert_eq!(
+            result.post_process_command,
+            Some(vec!["sed".to_owned(), "s/foo/bar/".to_owned()])
+        );
+
+        let result = toml::from_str::<OutputConfig>("").expect("TOML parsing failed");
+        assert_eq!(result, OutputConfig::default());
+    }
+
+    #[test]
+    fn timezone_from_toml_str() {
+        let parse = |s: &str| TargetTimezone::try_from(s.to_owned());
+        assert_eq!(parse("source"), Ok(TargetTimezone::Source));
+        assert_eq!(parse("Local"), Ok(TargetTimezone::Local));
+        assert_eq!(
+            parse("+02:00"),
+            Ok(TargetTimezone::Fixed(FixedOffset::east_opt(7200).unwrap()))
+        );
+        assert!(parse("Europe/Vienna").is_err());
+    }
+
+    #[test]
+    fn sepa_matching_by_regex() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = [
+            { creditor_id_regex = \"^AT98ZZZ0000\", account = \"Expenses:Utilities\" },
+            { creditor_id = \"AT98ZZZ00001234\", account = \"Expenses:Internet\", note = \"Exact\" },
+        ]
+        mandates = [
+            { mandate_id_regex = \"^INS-\", account = \"Expenses:Insurance\" },
+        ]
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let account = |target: Option<ImporterConfigTarget>| target.map(|t| t.account);
+
+        let result = config.match_sepa_creditor("AT98ZZZ00001234").unwrap();
+        assert_eq!(account(result), Some("Expenses:Internet".to_owned()));
+        let result = config.match_sepa_creditor("AT98ZZZ00005678").unwrap();
+        assert_eq!(account(result), Some("Expenses:Utilities".to_owned()));
+        let result = config.match_sepa_creditor("at98zzz00009999").unwrap();
+        assert_eq!(account(result), Some("Expenses:Utilities".to_owned()));
+        let result = config.match_sepa_creditor("DE98ZZZ00005678").unwrap();
+        assert_eq!(account(result), None);
+        let result = config.match_sepa_creditor("").unwrap();
+        assert_eq!(account(result), None);
+
+        let result = config.match_sepa_mandate("INS-2024-01").unwrap();
+        assert_eq!(account(result), Some("Expenses:Insurance".to_owned()));
+        let result = config.match_sepa_mandate("").unwrap();
+        assert_eq!(account(result), None);
+    }
+
+    #[test]
+    fn strip_payee_trailing() {
+        let filter = WordFilter {
+            payee: vec![],
+            payee_trailing: vec![
+                "^\\d+$".to_owned(),
+                "^(WIEN|GRAZ|LINZ)$".to_owned(),
+                "^\\d{2}\\.\\d{2}\\.?$".to_owned(),
+            ],
+        };
+
+        let strip = |payee: &str| filter.strip_payee_trailing(payee).unwrap();
+        assert_eq!(
+            strip("SPAR 4711 WIEN 0503"),
+            ("SPAR".to_owned(), Some("4711 WIEN 0503".to_owned()))
+        );
+        assert_eq!(
+            strip("BILLA DANKT  1234"),
+            ("BILLA DANKT".to_owned(), Some("1234".to_owned()))
+        );
+        assert_eq!(
+            strip("HOFER 12.05. GRAZ"),
+            ("HOFER".to_owned(), Some("12.05. GRAZ".to_owned()))
+        );
+        assert_eq!(
+            strip("McDonalds 123 Graz"),
+            ("McDonalds 123 Graz".to_owned(), None)
+        );
+        assert_eq!(strip("Amazon.de"), ("Amazon.de".to_owned(), None));
+        assert_eq!(strip("4711"), ("4711".to_owned(), None));
+
+        let filter = WordFilter::default();
+        assert_eq!(
+            filter.strip_payee_trailing("SPAR 4711").unwrap(),
+            ("SPAR 4711".to_owned(), None)
+        );
+    }
+
+    #[test]
+    fn hledger_command_with_journal_file() {
+        let config = HledgerConfig::default();
+        assert_eq!(config.command().get_args().count(), 0);
+
+        let config: HledgerConfig = toml::from_str(
+            "path = \"hledger\"\njournal_file = \"/home/user/finance/2024.journal\"",
+        )
+        .unwrap();
+        let command = config.command();
+        let args: Vec<&std::ffi::OsStr> = command.get_args().collect();
+        assert_eq!(args, vec!["-f", "/home/user/finance/2024.journal"]);
+        assert_eq!(command.get_program(), "hledger");
+    }
+
+    #[test]
+    fn hledger_section_with_journal_file_only() {
+        let config = toml::from_str::<ImporterConfig>(
+            "ibans = []
+            cards = []
+            mapping = []
+            creditor_and_debitor_mapping = []
+
+            [hledger]
+            journal_file = \"/home/user/finance/2024.journal\"
+
+            [sepa]
+            creditors = []
+            mandates = []
+
+            [transfer_accounts]
+            bank = \"Assets:Bank\"
+            cash = \"Assets:Cash\"
+            ",
+        )
+        .unwrap();
+        assert_eq!(&config.hledger.path, "hledger");
+        assert_eq!(
+            config.hledger.journal_file,
+            Some(std::path::PathBuf::from("/home/user/finance/2024.journal"))
+        );
+    }
+
+    #[test]
+    fn hledger_section_with_timeout_only() {
+        let config: HledgerConfig = toml::from_str("timeout = 60").unwrap();
+        assert_eq!(&config.path, "hledger");
+        assert_eq!(config.timeout(), std::time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn conflicting_mappings() {
+        let dir = std::env::temp_dir().join("hledger-import-conflicting-mappings");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let config = |ibans: &str| {
+            format!(
+                "ibans = [{}]
+                cards = [ {{ card = \"1234\", account = \"Liabilities:Card\" }} ]
+                mapping = []
+                creditor_and_debitor_mapping = []
+
+                [sepa]
+                creditors = [
+                  {{ creditor_id_regex = \"^AT\", account = \"Expenses:A\" }},
+                  {{ creditor_id_regex = \"^DE\", account = \"Expenses:B\" }},
+                ]
+                mandates = []
+
+                [transfer_accounts]
+                bank = \"Assets:Bank\"
+                cash = \"Assets:Cash\"
+                ",
+                ibans
+            )
+        };
+
+        // the same mapping twice (e.g. from an included file) is fine
+        std::fs::write(
+            &path,
+            config(
+                "{ iban = \"AT01\", account = \"Assets:Checking\" },
+                 { iban = \"AT01\", account = \"Assets:Checking\" },
+                 { iban = \"AT02\", account = \"Assets:Savings\" }",
+            ),
+        )
+        .unwrap();
+        assert!(ImporterConfig::load_from(&path).is_ok());
+
+        std::fs::write(
+            &path,
+            config(
+                "{ iban = \"AT01\", account = \"Assets:Checking\" },
+                 { iban = \"AT01\", account = \"Assets:Savings\" }",
+            ),
+        )
+        .unwrap();
+        match ImporterConfig::load_from(&path) {
+            Err(ImportError::InvalidConfig(msg)) => assert_eq!(
+                msg,
+                "conflicting mappings: ibans \"AT01\" is mapped to \"Assets:Checking\" and \"Assets:Savings\""
+            ),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn case_colliding_keys() {
+        let dir = std::env::temp_dir().join("hledger-import-case-colliding-keys");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let config = |state_rules: &str, aliases: &str| {
+            format!(
+                "ibans = []
+                cards = []
+                mapping = []
+                creditor_and_debitor_mapping = []
+
+                [sepa]
+                creditors = []
+                mandates = []
+
+                [transfer_accounts]
+                bank = \"Assets:Bank\"
+                cash = \"Assets:Cash\"
+
+                [state_rules]
+                {}
+
+                [commodities]
+                aliases = {{ {} }}
+                ",
+                state_rules, aliases
+            )
+        };
+
+        // keys that only differ in case are fine as long as they agree
+        std::fs::write(
+            &path,
+            config(
+                "authorized = \"pending\"\nAUTHORIZED = \"pending\"",
+                "Euro = \"EUR\", EURO = \"EUR\"",
+            ),
+        )
+        .unwrap();
+        assert!(ImporterConfig::load_from(&path).is_ok());
+
+        std::fs::write(
+            &path,
+            config(
+                "authorized = \"pending\"\nAUTHORIZED = \"cleared\"",
+                "Dollar = \"USD\", dollar = \"CAD\"",
+            ),
+        )
+        .unwrap();
+        match ImporterConfig::load_from(&path) {
+            Err(ImportError::InvalidConfig(msg)) => assert_eq!(
+                msg,
+                "conflicting mappings: state_rules \"authorized\" is mapped to \"Cleared\" and \"Pending\"; \
+                 commodities.aliases \"dollar\" is mapped to \"CAD\" and \"USD\""
+            ),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_with_includes() {
+        let dir = std::env::temp_dir().join("hledger-import-config-include-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("rules")).unwrap();
+
+        std::fs::write(
+            dir.join("config.toml"),
+            "include = [\"rules/mappings.toml\"]
+            ibans = []
+            cards = []
+            mapping = [{ search = \"Grocery Store\", account = \"Expenses:Groceries\" }]
+            creditor_and_debitor_mapping = []
+
+            [sepa]
+            creditors = []
+            mandates = []
+
+            [transfer_accounts]
+            bank = \"Assets:Bank\"
+            cash = \"Assets:Cash\"
+            ",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("rules/mappings.toml"),
+            "mapping = [{ search = \"Pharmacy\", account = \"Expenses:Health\" }]
+
+            [sepa]
+            creditors = [{ creditor_id = \"AT00ZZZ00000000000\", account = \"Expenses:Phone\" }]
+            ",
+        )
+        .unwrap();
+
+        let config = ImporterConfig::load_from(&dir.join("config.toml")).unwrap();
+        let searches: Vec<&str> = config.mapping.iter().map(|m| m.search.as_str()).collect();
+        assert_eq!(searches, vec!["Grocery Store", "Pharmacy"]);
+        assert_eq!(config.sepa.creditors.len(), 1);
+        assert_eq!(&config.transfer_accounts.bank, "Assets:Bank");
+
+        // conflicting scalar values
+        std::fs::write(
+            dir.join("rules/mappings.toml"),
+            "[transfer_accounts]
+            bank = \"Assets:Other\"
+            ",
+        )
+        .unwrap();
+        match ImporterConfig::load_from(&dir.join("config.toml")) {
+            Err(ImportError::ConfigInclude(msg)) => assert!(msg.contains("transfer_accounts.bank")),
+            _ => panic!("expected a conflict"),
+        }
+
+        // include cycle
+        std::fs::write(
+            dir.join("rules/mappings.toml"),
+            "include = [\"../config.toml\"]",
+        )
+        .unwrap();
+        assert!(matches!(
+            ImporterConfig::load_from(&dir.join("config.toml")),
+            Err(ImportError::ConfigInclude(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_commodity_rule() {
+        let dir = std::env::temp_dir().join("hledger-import-commodity-rules");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let config = |rule: &str| {
+            format!(
+                "commodity_formatting_rules = [\"{}\"]
+                ibans = []
+                cards = []
+                mapping = []
+                creditor_and_debitor_mapping = []
+
+                [sepa]
+                creditors = []
+                mandates = []
+
+                [transfer_accounts]
+                bank = \"Assets:Bank\"
+                cash = \"Assets:Cash\"
+                ",
+                rule
+            )
+        };
+
+        std::fs::write(&path, config("1.000,00 EUR")).unwrap();
+        assert!(ImporterConfig::load_from(&path).is_ok());
+
+        std::fs::write(&path, config("1.000,00")).unwrap();
+        match ImporterConfig::load_from(&path) {
+            Err(ImportError::InvalidConfig(msg)) => assert!(
+                msg.starts_with(
+                    "invalid commodity formatting rule \"1.000,00\" in commodity_formatting_rules"
+                ),
+                "{}",
+                msg
+            ),
+            _ => panic!("expected an invalid commodity formatting rule"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn case_sensitive_mapping() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = [
+          { search = \"^BAY$\", account = \"Assets:Stocks:BAY\", case_sensitive = true },
+          { search = \"bay\", account = \"Expenses:Travel\" },
+        ]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+        assert!(config.mapping[0].case_sensitive);
+        assert!(!config.mapping[1].case_sensitive);
+
+        assert!(config.mapping[0].matches("BAY").unwrap());
+        assert!(!config.mapping[0].matches("Bay").unwrap());
+        assert!(config.mapping[1].matches("BAY").unwrap());
+
+        let account = |field: &str| config.match_mapping(field).unwrap().unwrap().account;
+        assert_eq!(account("BAY"), "Assets:Stocks:BAY");
+        assert_eq!(account("Bay"), "Expenses:Travel");
+    }
+
+    #[test]
+    fn importer_note_affixes() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [importer_notes]
+        revolut = { note_prefix = \"[Revolut] \", force_note = true }
+        paypal = { note_suffix = \" (PayPal)\" }
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        assert_eq!(
+            config.importer_note("revolut", Some("Card payment".to_owned())),
+            Some("[Revolut] Card payment".to_owned())
+        );
+        assert_eq!(
+            config.importer_note("revolut", None),
+            Some("[Revolut]".to_owned())
+        );
+        assert_eq!(
+            config.importer_note("paypal", Some("Order".to_owned())),
+            Some("Order (PayPal)".to_owned())
+        );
+        assert_eq!(config.importer_note("paypal", None), None);
+        assert_eq!(
+            config.importer_note("erste", Some("Rent".to_owned())),
+            Some("Rent".to_owned())
+        );
+    }
+
+    #[test]
+    fn mapping_on_payee_and_note() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = [
+          { search_payee = \"Amazon\", search_note = \"Prime\", account = \"Expenses:Subscriptions\" },
+          { search = \"Amazon\", account = \"Expenses:Shopping\" },
+        ]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+        assert_eq!(&config.mapping[0].search, "");
+
+        let account = |payee: &str, note: &str| {
+            config
+                .match_mapping_fields(payee, payee, note)
+                .unwrap()
+                .map(|target| target.account)
+        };
+        assert_eq!(
+            account("Amazon EU", "Prime membership").as_deref(),
+            Some("Expenses:Subscriptions")
+        );
+        assert_eq!(
+            account("Amazon EU", "Order 123").as_deref(),
+            Some("Expenses:Shopping")
+        );
+        assert_eq!(account("Netflix", "Prime").as_deref(), None);
+        // without note only the generic search applies
+        assert_eq!(
+            config.match_mapping("Amazon EU").unwrap().unwrap().account,
+            "Expenses:Shopping"
+        );
+    }
 }
@@ -12,8 +12,25 @@ pub enum ImportError {
     ConfigRead(std::path::PathBuf),
     #[error("Failed to parse configuration file: {0}")]
     ConfigParse(#[from] toml::de::Error),
+    #[error("Failed to include configuration file: {0}")]
+    ConfigInclude(String),
     #[error("Failed to read input file \"{0}\"")]
     InputFileRead(std::path::PathBuf),
+    #[error("A glob pattern can only be used with a directory as input, \"{0}\" is a file")]
+    GlobWithFile(std::path::PathBuf),
+    #[cfg(feature = "remote")]
+    #[error("Failed to download input file \"{0}\": {1}")]
+    Download(String, String),
+    #[error("Failed to write output file \"{0}\"")]
+    OutputFileWrite(std::path::PathBuf),
+    #[error("Output file \"{0}\" already exists, use --overwrite to replace it")]
+    OutputFileExists(std::path::PathBuf),
+    #[error("Accounts \"{0}\" and \"{1}\" would both be written to \"{2}\"")]
+    OutputFileCollision(String, String, String),
+    #[error("Unknown input encoding \"{0}\"")]
+    UnknownEncoding(String),
+    #[error("Failed to read or write state file \"{0}\"")]
+    StateFile(std::path::PathBuf),
     #[error("Failed to parse input file: {0}")]
     InputParse(String),
     #[cfg(feature = "flatex")]
@@ -25,10 +42,24 @@ pub enum ImportError {
     Regex(#[from] regex::Error),
     #[error("Failed to extract transaction information from hledger: {0}")]
     Query(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
     #[error("Missing section \"{0}\" in configuration")]
     MissingConfig(String),
     #[error("Missing value \"{0}\" in document")]
     MissingValue(String),
+    #[error("Failed to read interactive input: {0}")]
+    Prompt(String),
+    #[error("Post-processing command failed: {0}")]
+    PostProcess(String),
+    #[error("Transaction \"{0}\" has no postings, please check your account mappings")]
+    EmptyPostings(String),
+    #[error(
+        "Transaction \"{0}\" uses commodity \"{1}\", which is not listed in strict_commodities"
+    )]
+    UnknownCommodity(String, String),
+    #[error("{} transaction(s) could not be classified:\n{}", .0.len(), .0.join("\n"))]
+    Unclassified(Vec<String>),
 }
 
 pub type Result<T> = std::result::Result<T, ImportError>;
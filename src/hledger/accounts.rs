@@ -0,0 +1,179 @@
+use std::collections::BTreeSet;
+
+use crate::hledger::output::Transaction;
+
+/// distinct accounts of all postings, sorted by name
+pub fn accounts(transactions: &[Transaction]) -> BTreeSet<String> {
+    transactions
+        .iter()
+        .flat_map(|t| t.postings.iter())
+        .map(|p| p.account.clone())
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// Rewrites the casing of posting accounts to the given canonical accounts, e.g. `expenses:food` to `Expenses:Food`.
+/// The canonical account with the most matching components (compared case-insensitively) wins,
+/// so `Expenses` also fixes the casing of the first component of `expenses:unknown`.
+pub fn canonicalize_accounts(transactions: &mut [Transaction], canonical: &[String]) {
+    if canonical.is_empty() {
+        return;
+    }
+    let mut reported = BTreeSet::new();
+    for posting in transactions.iter_mut().flat_map(|t| t.postings.iter_mut()) {
+        let components: Vec<&str> = posting.account.split(':').collect();
+        let best = canonical
+            .iter()
+            .map(|c| c.split(':').collect::<Vec<_>>())
+            .filter(|c| {
+                c.len() <= components.len()
+                    && c.iter()
+                        .zip(&components)
+                        .all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+            })
+            .max_by_key(|c| c.len());
+        let Some(best) = best else {
+            continue;
+        };
+        let account = best
+            .iter()
+            .chain(components.iter().skip(best.len()))
+            .copied()
+            .collect::<Vec<_>>()
+            .join(":");
+        if account != posting.account {
+            if reported.insert(posting.account.clone()) {
+                log::info!(
+                    "rewriting account \"{}\" to \"{}\"",
+                    posting.account,
+                    account
+                );
+            }
+            posting.account = account;
+        }
+    }
+}
+
+/// renders the accounts as tree, one account name component per line (indented by its depth)
+pub fn account_tree(accounts: &BTreeSet<String>) -> String {
+    let mut result = String::new();
+    let mut previous: Vec<&str> = Vec::new();
+    for account in accounts {
+        let components: Vec<&str> = account.split(':').collect();
+        let common = components
+            .iter()
+            .zip(previous.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        for (depth, component) in components.iter().enumerate().skip(common) {
+            result.push_str(&format!("{}{}\n", "  ".repeat(depth), component));
+        }
+        previous = components;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, Posting, TransactionState};
+
+    use super::*;
+
+    #[test]
+    fn accounts_as_tree() {
+        let posting = |account: &str| Posting {
+            account: account.to_owned(),
+            amount: Some(AmountAndCommodity::new(
+                BigDecimal::from(1),
+                "EUR".to_owned(),
+            )),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let transaction = |postings: Vec<Posting>| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings,
+        };
+        let transactions = vec![
+            transaction(vec![
+                posting("Assets:Bank:Checking"),
+                posting("Expenses:Groceries"),
+            ]),
+            transaction(vec![
+                posting("Assets:Bank:Checking"),
+                posting("Expenses:Groceris"),
+            ]),
+            transaction(vec![posting("Assets:Cash"), posting("")]),
+        ];
+
+        let accounts = accounts(&transactions);
+        assert_eq!(
+            accounts.iter().map(|a| a.as_str()).collect::<Vec<_>>(),
+            vec![
+                "Assets:Bank:Checking",
+                "Assets:Cash",
+                "Expenses:Groceries",
+                "Expenses:Groceris"
+            ]
+        );
+        assert_eq!(
+            account_tree(&accounts),
+            "Assets\n  Bank\n    Checking\n  Cash\nExpenses\n  Groceries\n  Groceris\n"
+        );
+    }
+
+    #[test]
+    fn canonical_account_casing() {
+        let posting = |account: &str| Posting {
+            account: account.to_owned(),
+            amount: None,
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let mut transactions = vec![Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                posting("expenses:food"),
+                posting("EXPENSES:unknown"),
+                posting("Assets:Bank"),
+                posting("Assets:Bank:Checking"),
+            ],
+        }];
+        let canonical = vec!["Expenses".to_owned(), "Expenses:Food".to_owned()];
+
+        canonicalize_accounts(&mut transactions, &canonical);
+        let accounts: Vec<&str> = transactions[0]
+            .postings
+            .iter()
+            .map(|p| p.account.as_str())
+            .collect();
+        assert_eq!(
+            accounts,
+            [
+                "Expenses:Food",
+                "Expenses:unknown",
+                "Assets:Bank",
+                "Assets:Bank:Checking"
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::CommodityConfig;
+use crate::hledger::output::Transaction;
+
+/// Active currency codes of ISO 4217
+const ISO_4217: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD",
+    "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ",
+    "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD",
+    "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK", "LBP", "LKR",
+    "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR", "MVR",
+    "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN",
+    "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR",
+    "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB",
+    "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX", "USD", "UYU", "UZS",
+    "VES", "VND", "VUV", "WST", "XAF", "XCD", "XCG", "XOF", "XPF", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+/// Common currency symbols and spellings and their ISO 4217 code
+const SYMBOLS: &[(&str, &str)] = &[
+    ("€", "EUR"),
+    ("EURO", "EUR"),
+    ("EUROS", "EUR"),
+    ("$", "USD"),
+    ("US$", "USD"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₣", "CHF"),
+];
+
+/// Normalizes a commodity of the input file: the configured aliases are applied first (ignoring
+/// letter case), then currency symbols are mapped to their ISO 4217 code and alphabetic codes are
+/// uppercased (`eur` becomes `EUR`). Other commodities (e.g. securities) are kept as they are.
+pub fn normalize_commodity(commodity: &str, aliases: &HashMap<String, String>) -> String {
+    let trimmed = commodity.trim();
+    if let Some((_, target)) = aliases
+        .iter()
+        .find(|(alias, _)| alias.trim().eq_ignore_ascii_case(trimmed))
+    {
+        return target.clone();
+    }
+
+    let upper = trimmed.to_uppercase();
+    if let Some((_, code)) = SYMBOLS.iter().find(|(symbol, _)| *symbol == upper) {
+        return code.to_string();
+    }
+    if upper.chars().all(|c| c.is_ascii_alphabetic()) {
+        upper
+    } else {
+        commodity.to_owned()
+    }
+}
+
+pub fn is_iso_currency(commodity: &str) -> bool {
+    ISO_4217.contains(&commodity)
+}
+
+/// Normalizes the commodities of all postings (if enabled) and warns once about every commodity
+/// that is neither an ISO 4217 currency nor the target of an alias.
+pub fn normalize_commodities(transactions: &mut [Transaction], config: &CommodityConfig) {
+    if !config.normalize {
+        return;
+    }
+
+    let mut unknown = BTreeSet::new();
+    for posting in transactions.iter_mut().flat_map(|t| t.postings.iter_mut()) {
+        if let Some(amount) = posting.amount.as_mut() {
+            amount.commodity = normalize_commodity(&amount.commodity, &config.aliases);
+            if !is_iso_currency(&amount.commodity)
+                && !config.aliases.values().any(|a| *a == amount.commodity)
+            {
+                unknown.insert(amount.commodity.clone());
+            }
+        }
+    }
+    for commodity in unknown {
+        log::warn!(
+            "unknown commodity \"{}\" (not an ISO 4217 currency), add it to commodities.aliases to silence this warning",
+            commodity
+        );
+    }
+}
+
+/// Replaces the commodity of all amounts and balance assertions (but not of prices) with the given
+/// commodity, e.g. for accounts that are always kept in one currency.
+pub fn override_commodity(transactions: &mut [Transaction], commodity: &str) {
+    for posting in transactions.iter_mut().flat_map(|t| t.postings.iter_mut()) {
+        for amount in posting
+            .amount
+            .iter_mut()
+            .chain(posting.balance_assertion.iter_mut())
+        {
+            amount.commodity = commodity.to_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::hledger::output::{AmountAndCommodity, Posting, TransactionState};
+
+    #[test]
+    fn normalize_currency_symbols_and_codes() {
+        let aliases = HashMap::from([("Dollar".to_owned(), "USD".to_owned())]);
+
+        assert_eq!(normalize_commodity("€", &aliases), "EUR");
+        assert_eq!(normalize_commodity("eur", &aliases), "EUR");
+        assert_eq!(normalize_commodity(" EURO ", &aliases), "EUR");
+        assert_eq!(normalize_commodity("dollar", &aliases), "USD");
+        assert_eq!(normalize_commodity("BTC", &aliases), "BTC");
+        assert_eq!(
+            normalize_commodity("AT0000A0E9W5", &aliases),
+            "AT0000A0E9W5"
+        );
+
+        assert!(is_iso_currency("EUR"));
+        assert!(!is_iso_currency("BTC"));
+    }
+
+    #[test]
+    fn override_source_commodity() {
+        let posting = |commodity: &str, price: Option<&str>| Posting {
+            account: "Assets:Revolut".to_owned(),
+            amount: Some(AmountAndCommodity::new(
+                BigDecimal::from(-12),
+                commodity.to_owned(),
+            )),
+            comment: None,
+            tags: Vec::new(),
+            price: price.map(|p| AmountAndCommodity::new(BigDecimal::from(13), p.to_owned())),
+            balance_assertion: Some(AmountAndCommodity::new(
+                BigDecimal::from(100),
+                commodity.to_owned(),
+            )),
+        };
+        let mut transactions = vec![Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            code: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            payee: "Coffee".to_owned(),
+            note: None,
+            tags: Vec::new(),
+            postings: vec![posting("€", None), posting("€", Some("USD"))],
+        }];
+
+        override_commodity(&mut transactions, "EUR");
+        assert_eq!(transactions[0].postings[0], posting("EUR", None));
+        // prices keep their commodity
+        assert_eq!(transactions[0].postings[1], posting("EUR", Some("USD")));
+    }
+}
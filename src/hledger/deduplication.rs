@@ -1,21 +1,317 @@
 use crate::config::HledgerConfig;
 use crate::error::ImportError;
 use crate::error::Result;
+use crate::hledger::output::{AmountAndCommodity, Transaction};
+use crate::hledger::process::run_with_timeout;
+use crate::hledger::query::{query_hledger_by_date, HledgerJsonTransaction};
+use chrono::Days;
 use std::collections::HashSet;
-use std::process::Command;
 
-pub fn get_hledger_codes(config: &HledgerConfig) -> Result<HashSet<String>> {
-    let output = Command::new(&config.path).arg("codes").output();
-    let output = match output {
-        Ok(o) => o,
-        Err(e) => return Err(ImportError::HledgerExecution(e)),
+/// The codes of the journal. With `code_tags` (see `output.suppress_code`), the values of the
+/// `code` tags are queried as well.
+pub fn get_hledger_codes(config: &HledgerConfig, code_tags: bool) -> Result<HashSet<String>> {
+    let mut codes = parse_codes(&hledger_output(config, &["codes"])?);
+    if code_tags {
+        codes.extend(parse_codes(&hledger_output(
+            config,
+            &["tags", "^code$", "--values"],
+        )?));
+    }
+    Ok(codes)
+}
+
+fn hledger_output(config: &HledgerConfig, args: &[&str]) -> Result<String> {
+    let output = run_with_timeout(config.command().args(args), None, config.timeout())?;
+    match std::str::from_utf8(&output) {
+        Ok(output) => Ok(output.to_owned()),
+        Err(e) => Err(ImportError::StringConversion(e)),
+    }
+}
+
+/// Reads the known codes from a file with one code per line (e.g. the output of `hledger codes`),
+/// for environments without hledger at import time.
+pub fn read_known_codes(path: &std::path::Path) -> Result<HashSet<String>> {
+    let codes =
+        std::fs::read_to_string(path).map_err(|_| ImportError::InputFileRead(path.to_owned()))?;
+    Ok(parse_codes(&codes))
+}
+
+fn parse_codes(codes: &str) -> HashSet<String> {
+    codes
+        .lines()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// Drops transactions that are likely already part of the journal under another code:
+/// a journal transaction with the same payee and amount whose date is at most `days` apart.
+pub fn drop_near_duplicates(
+    config: &HledgerConfig,
+    transactions: Vec<Transaction>,
+    days: u64,
+) -> Result<Vec<Transaction>> {
+    let (Some(first), Some(last)) = (
+        transactions.iter().map(|t| t.date).min(),
+        transactions.iter().map(|t| t.date).max(),
+    ) else {
+        return Ok(transactions);
     };
+    let begin = first.checked_sub_days(Days::new(days)).unwrap_or(first);
+    let end = last.checked_add_days(Days::new(days + 1)).unwrap_or(last);
+    let known = query_hledger_by_date(config, begin, end)?;
+    Ok(without_near_duplicates(transactions, &known, days))
+}
 
-    let codes = match std::str::from_utf8(&output.stdout) {
-        Ok(c) => c,
-        Err(e) => return Err(ImportError::StringConversion(e)),
+fn without_near_duplicates(
+    transactions: Vec<Transaction>,
+    known: &[HledgerJsonTransaction],
+    days: u64,
+) -> Vec<Transaction> {
+    transactions
+        .into_iter()
+        .filter(|transaction| {
+            let duplicate = known
+                .iter()
+                .any(|known| is_near_duplicate(transaction, known, days));
+            if duplicate {
+                log::info!(
+                    "skipping transaction \"{} {}\", a transaction with the same payee and amount is already in the journal",
+                    transaction.date.format("%Y-%m-%d"),
+                    transaction.payee
+                );
+            }
+            !duplicate
+        })
+        .collect()
+}
+
+/// same payee (case-insensitive, without note), the first amount of the transaction is posted in the known transaction
+/// and the dates are at most `days` apart
+fn is_near_duplicate(transaction: &Transaction, known: &HledgerJsonTransaction, days: u64) -> bool {
+    let Some(amount) = transaction.postings.iter().find_map(|p| p.amount.as_ref()) else {
+        return false;
     };
+    let known_payee = known
+        .tdescription
+        .as_deref()
+        .unwrap_or_default()
+        .split('|')
+        .next()
+        .unwrap_or_default()
+        .trim();
+    (transaction.date - known.tdate).num_days().unsigned_abs() <= days
+        && known_payee.to_lowercase() == transaction.payee.trim().to_lowercase()
+        && known
+            .tpostings
+            .iter()
+            .flat_map(|p| p.pamount.iter().cloned())
+            .filter_map(|a| AmountAndCommodity::try_from(a).ok())
+            .any(|a| a.commodity == amount.commodity && a.amount == amount.amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{Posting, TransactionState};
+    use crate::hledger::query::{HledgerJsonAmount, HledgerJsonPosting, HledgerJsonQuantity};
+
+    use super::*;
+
+    #[test]
+    fn near_duplicates_within_window() {
+        let transaction = |day: u32, payee: &str, cents: i64| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 6, day).unwrap(),
+            code: Some(format!("NEW{}", day)),
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                Posting {
+                    account: "Assets:Bank".to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::new(cents.into(), 2),
+                        "EUR".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+                Posting {
+                    account: "Expenses:Rent".to_owned(),
+                    amount: None,
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+            ],
+        };
+        let known = vec![HledgerJsonTransaction {
+            tcode: "OLD".to_owned(),
+            tdate: NaiveDate::from_ymd_opt(2024, 6, 10).unwrap(),
+            tdate2: None,
+            tcomment: None,
+            tdescription: Some("Landlord | June".to_owned()),
+            tpostings: vec![HledgerJsonPosting {
+                paccount: "Assets:Bank".to_owned(),
+                pcomment: None,
+                pamount: vec![HledgerJsonAmount {
+                    acommodity: "EUR".to_owned(),
+                    aquantity: HledgerJsonQuantity {
+                        decimal_mantissa: -85000,
+                        decimal_places: 2,
+                    },
+                }],
+            }],
+        }];
+
+        let transactions = vec![
+            transaction(12, "landlord", -85000),
+            transaction(14, "Landlord", -85000),
+            transaction(10, "Landlord", -84000),
+            transaction(10, "Store", -85000),
+        ];
+        let result = without_near_duplicates(transactions, &known, 3);
+        let codes: Vec<_> = result.iter().filter_map(|t| t.code.as_deref()).collect();
+        // within the window of 3 days only the first transaction is a duplicate
+        assert_eq!(codes, ["NEW14", "NEW10", "NEW10"]);
+        assert_eq!(&result[1].payee, "Landlord");
+
+        let result = without_near_duplicates(vec![transaction(14, "Landlord", -85000)], &known, 4);
+        assert!(result.is_empty());
+    }
+
+    #[cfg(feature = "flatex")]
+    #[test]
+    fn deduplicate_with_codes_file() {
+        let dir = std::env::temp_dir().join("hledger-import-known-codes");
+        std::fs::create_dir_all(&dir).unwrap();
+        let codes_file = dir.join("codes.txt");
+        std::fs::write(&codes_file, "TA1\n\n  TA3  \n").unwrap();
+        let input_file = dir.join("flatex.csv");
+        std::fs::write(
+            &input_file,
+            "Buchungstag;Valuta;Empfänger;Zahlungspfl.;TA.Nr.;Buchungsinformationen;Betrag;
+03.06.2024;04.06.2024;Someone;AT000000000000000000;TA1;Transfer;1.000,50;EUR
+05.06.2024;05.06.2024;Someone;AT000000000000000000;TA2;Transfer;-20,00;EUR
+",
+        )
+        .unwrap();
+        let config = toml::from_str::<crate::config::ImporterConfig>(
+            "ibans = []
+            cards = []
+            mapping = []
+            creditor_and_debitor_mapping = []
+
+            [sepa]
+            creditors = []
+            mandates = []
+
+            [transfer_accounts]
+            bank = \"Assets:Bank\"
+            cash = \"Assets:Cash\"
+
+            [flatex_csv]
+            account = \"Assets:Flatex\"
+            ",
+        )
+        .unwrap();
+
+        let codes = read_known_codes(&codes_file).unwrap();
+        assert_eq!(codes, HashSet::from(["TA1".to_owned(), "TA3".to_owned()]));
+
+        let transactions =
+            crate::import(crate::Importer::FlatexCSV, &input_file, &config, &codes).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].code, Some("TA2".to_owned()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(all(unix, feature = "flatex"))]
+    #[test]
+    fn deduplicate_suppressed_codes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::config::ImporterConfig;
+
+        let dir = std::env::temp_dir().join("hledger-import-suppressed-codes");
+        std::fs::create_dir_all(&dir).unwrap();
+        // stands in for hledger: `codes` lists the codes of the journal, `tags` the values of the `code` tags
+        let hledger = dir.join("hledger");
+        std::fs::write(
+            &hledger,
+            "#!/bin/sh
+journal=$2
+case \"$3\" in
+  codes) sed -n 's/^[0-9-]* [*!] *(\\([^)]*\\)).*/\\1/p' \"$journal\" ;;
+  tags) sed -n 's/^ *; code: *//p' \"$journal\" ;;
+esac
+",
+        )
+        .unwrap();
+        std::fs::set_permissions(&hledger, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let input_file = dir.join("flatex.csv");
+        std::fs::write(
+            &input_file,
+            "Buchungstag;Valuta;Empfänger;Zahlungspfl.;TA.Nr.;Buchungsinformationen;Betrag;
+03.06.2024;04.06.2024;Someone;AT000000000000000000;TA1;Transfer;1.000,50;EUR
+",
+        )
+        .unwrap();
+        let mut config = toml::from_str::<ImporterConfig>(
+            "ibans = []
+            cards = []
+            mapping = []
+            creditor_and_debitor_mapping = []
+
+            [sepa]
+            creditors = []
+            mandates = []
+
+            [transfer_accounts]
+            bank = \"Assets:Bank\"
+            cash = \"Assets:Cash\"
+
+            [flatex_csv]
+            account = \"Assets:Flatex\"
+
+            [output]
+            suppress_code = true
+            ",
+        )
+        .unwrap();
+
+        let transactions = crate::import(
+            crate::Importer::FlatexCSV,
+            &input_file,
+            &config,
+            &HashSet::new(),
+        )
+        .unwrap();
+        let journal = dir.join("journal.ledger");
+        let rendered = transactions[0].render(&config.output);
+        assert!(!rendered.contains("(TA1)"));
+        std::fs::write(&journal, rendered).unwrap();
+
+        config.hledger.path = hledger.to_string_lossy().into_owned();
+        config.hledger.journal_file = Some(journal);
+        let codes = get_hledger_codes(&config.hledger, true).unwrap();
+        assert_eq!(codes, HashSet::from(["TA1".to_owned()]));
+        // without suppressed codes, the tags are not queried
+        assert!(get_hledger_codes(&config.hledger, false)
+            .unwrap()
+            .is_empty());
 
-    let result = codes.lines().map(|c| c.to_string()).collect();
-    Ok(result)
+        let transactions =
+            crate::import(crate::Importer::FlatexCSV, &input_file, &config, &codes).unwrap();
+        assert!(transactions.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,14 +1,52 @@
-use std::io::{Read, Write};
+use std::io::Write;
 use std::process::{Command, Stdio};
 
-use crate::{config::HledgerConfig, error::*};
+use regex::Regex;
+
+use crate::{
+    config::{FormatFallback, HledgerConfig},
+    error::*,
+};
+
+use super::process::run_with_timeout;
 
 pub fn hledger_format(
     config: &HledgerConfig,
     transactions: &str,
     commodity_formatting_rules: &Option<Vec<String>>,
 ) -> Result<String> {
-    let args: Vec<&str> = if let Some(rules) = commodity_formatting_rules {
+    let args = print_args(commodity_formatting_rules);
+
+    let output = match run_with_timeout(
+        Command::new(&config.path).args(args),
+        Some(transactions),
+        config.timeout(),
+    ) {
+        Ok(output) => output,
+        Err(ImportError::HledgerExecution(e)) if config.format_fallback == FormatFallback::Raw => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                log::warn!(
+                    "hledger executable \"{}\" not found, the transactions are not formatted",
+                    config.path
+                );
+            } else {
+                log::warn!(
+                    "formatting with hledger failed ({}), the transactions are not formatted",
+                    e
+                );
+            }
+            return Ok(transactions.to_owned());
+        }
+        Err(e) => return Err(e),
+    };
+    let output = std::str::from_utf8(&output).map_err(ImportError::StringConversion)?;
+
+    Ok(output.to_owned())
+}
+
+/// arguments of `hledger print` including the given commodity formatting rules
+fn print_args(commodity_formatting_rules: &Option<Vec<String>>) -> Vec<&str> {
+    if let Some(rules) = commodity_formatting_rules {
         let mut args = vec!["print", "-x", "-f-", "--round=soft"];
         rules.iter().for_each(|r| {
             args.push("-c");
@@ -17,29 +55,221 @@ pub fn hledger_format(
         args
     } else {
         vec!["print", "-x", "-f-"]
-    };
+    }
+}
 
-    let mut process = Command::new(&config.path)
+/// merges the global commodity formatting rules with the rules of a specific importer
+pub fn merge_commodity_rules(
+    global_rules: &Option<Vec<String>>,
+    importer_rules: Vec<String>,
+) -> Option<Vec<String>> {
+    match global_rules {
+        Some(global_rules) => {
+            let mut rules = global_rules.clone();
+            rules.extend(importer_rules);
+            Some(rules)
+        }
+        None if importer_rules.is_empty() => None,
+        None => Some(importer_rules),
+    }
+}
+
+/// `commodity` directives (one per line) for the given commodity formatting rules, e.g. `commodity 1.000,00 EUR`,
+/// so that the journal keeps its format independent of the inference of hledger
+pub fn commodity_directives(commodity_formatting_rules: &Option<Vec<String>>) -> String {
+    let mut directives = String::new();
+    let mut seen = Vec::new();
+    for rule in commodity_formatting_rules.iter().flatten() {
+        let rule = rule.trim();
+        if !seen.contains(&rule) {
+            seen.push(rule);
+            directives.push_str(&format!("commodity {}\n", rule));
+        }
+    }
+    directives
+}
+
+/// Checks the shape of a commodity formatting rule (`-c` of `hledger print`): an amount with a commodity symbol
+/// before or after it, e.g. `1.000,00 EUR`, `EUR 1,000.00`, `$1,000.00` or `1.000,0000 "AAPL 2"`.
+pub fn is_valid_commodity_rule(rule: &str) -> bool {
+    let shape = Regex::new(
+        r#"^(?:(?P<pre>"[^"]+"|[^\d\s"+\-.,]+)\s*)?-?\d(?:[\d.,' ]*\d)?(?:\s*(?P<suf>"[^"]+"|[^\d\s"+\-.,]+))?$"#,
+    )
+    .expect("valid regular expression");
+    match shape.captures(rule.trim()) {
+        Some(captures) => captures.name("pre").is_some() != captures.name("suf").is_some(),
+        None => false,
+    }
+}
+
+/// pipes the given journal text through an arbitrary command (e.g. a custom formatter script)
+/// and returns whatever the command writes to its standard output
+pub fn post_process(command: &[String], journal: &str) -> Result<String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or(ImportError::PostProcess("no command given".to_owned()))?;
+
+    let mut process = Command::new(program)
         .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()
         .map_err(ImportError::HledgerExecution)?;
 
-    if let Some(mut stdin) = process.stdin.take() {
-        stdin
-            .write_all(transactions.as_bytes())
-            .map_err(ImportError::HledgerExecution)?;
+    // feed stdin from a separate thread, so that a command producing a lot of output can not block us
+    let writer = process.stdin.take().map(|mut stdin| {
+        let journal = journal.to_owned();
+        std::thread::spawn(move || stdin.write_all(journal.as_bytes()))
+    });
+
+    let output = process
+        .wait_with_output()
+        .map_err(ImportError::HledgerExecution)?;
+
+    let write_result = writer.map(|writer| writer.join());
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ImportError::PostProcess(format!(
+            "\"{}\" exited with {}: {}",
+            program,
+            output.status,
+            stderr.trim()
+        )));
+    }
+
+    match write_result {
+        // commands are free to stop reading their input early
+        Some(Ok(Err(e))) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+            return Err(ImportError::HledgerExecution(e))
+        }
+        Some(Err(_)) => {
+            return Err(ImportError::PostProcess(
+                "failed to write to standard input".to_owned(),
+            ))
+        }
+        _ => {}
+    }
+
+    let result = std::str::from_utf8(&output.stdout).map_err(ImportError::StringConversion)?;
+    Ok(result.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_commodity_rules_in_args() {
+        let global = Some(vec!["1.000,00 EUR".to_owned()]);
+        let importer = vec!["1.000,0000 USD".to_owned()];
+
+        let rules = merge_commodity_rules(&global, importer.clone());
+        assert_eq!(
+            print_args(&rules),
+            vec![
+                "print",
+                "-x",
+                "-f-",
+                "--round=soft",
+                "-c",
+                "1.000,00 EUR",
+                "-c",
+                "1.000,0000 USD"
+            ]
+        );
+
+        let rules = merge_commodity_rules(&None, importer);
+        assert_eq!(
+            print_args(&rules),
+            vec!["print", "-x", "-f-", "--round=soft", "-c", "1.000,0000 USD"]
+        );
+
+        let rules = merge_commodity_rules(&None, vec![]);
+        assert_eq!(rules, None);
+        assert_eq!(print_args(&rules), vec!["print", "-x", "-f-"]);
     }
 
-    let mut output = String::new();
-    if let Some(mut stdout) = process.stdout.take() {
-        stdout
-            .read_to_string(&mut output)
-            .map_err(ImportError::HledgerExecution)?;
+    #[test]
+    fn post_process_noop() {
+        let journal = "2024-11-22 * Test\n    Assets:Cash     -10 EUR\n    Expenses:Test\n";
+        let command = vec!["cat".to_owned()];
+        let result = post_process(&command, journal).expect("piping through cat failed");
+        assert_eq!(result, journal);
     }
 
-    process.wait().map_err(ImportError::HledgerExecution)?;
+    #[test]
+    fn post_process_failing_command() {
+        let command = vec![
+            "sh".to_owned(),
+            "-c".to_owned(),
+            "echo broken >&2; exit 3".to_owned(),
+        ];
+        let result = post_process(&command, "2024-11-22 * Test");
+        match result {
+            Err(ImportError::PostProcess(msg)) => assert!(msg.contains("broken")),
+            _ => panic!("expected a post-processing error"),
+        }
 
-    Ok(output)
+        let result = post_process(&[], "2024-11-22 * Test");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn commodity_rule_shape() {
+        for rule in [
+            "1.000,00 EUR",
+            "EUR 1,000.00",
+            "$1,000.00",
+            "1 000,00 €",
+            "1.000,0000 \"AAPL 2\"",
+            "-1.000,00 EUR",
+            "1 EUR",
+        ] {
+            assert!(is_valid_commodity_rule(rule), "{}", rule);
+        }
+        for rule in [
+            "",
+            "EUR",
+            "1.000,00",
+            "EUR 1.000,00 EUR",
+            "1.000,00 EUR extra",
+            "1.000;00 EUR",
+        ] {
+            assert!(!is_valid_commodity_rule(rule), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn raw_output_without_hledger() {
+        let transactions = "2024-05-10 * Test\n    Assets:Cash  -10 EUR\n    Expenses:Test\n";
+        let mut config = HledgerConfig {
+            path: "/nonexistent/hledger".to_owned(),
+            ..Default::default()
+        };
+
+        let result = hledger_format(&config, transactions, &None);
+        assert!(matches!(result, Err(ImportError::HledgerExecution(_))));
+
+        config.format_fallback = FormatFallback::Raw;
+        let result = hledger_format(&config, transactions, &None).unwrap();
+        assert_eq!(result, transactions);
+    }
+
+    #[test]
+    fn raw_output_with_failing_hledger() {
+        let transactions = "2024-05-10 * Test\n    Assets:Cash  -10 EUR\n    Expenses:Test\n";
+        let mut config: HledgerConfig = toml::from_str("path = \"false\"").unwrap();
+
+        let result = hledger_format(&config, transactions, &None);
+        assert!(matches!(result, Err(ImportError::HledgerExecution(_))));
+
+        // the fallback can be configured on its own
+        config = toml::from_str("format_fallback = \"raw\"").unwrap();
+        assert_eq!(&config.path, "hledger");
+        config.path = "false".to_owned();
+        let result = hledger_format(&config, transactions, &None).unwrap();
+        assert_eq!(result, transactions);
+    }
 }
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use crate::hledger::output::Transaction;
+
+/// Content-addressed hash of a transaction, formatted as `<prefix>_<hash>`.
+///
+/// The hash is computed over a canonical text of the date, payee and posting amounts (sorted),
+/// so it does not depend on the field order of structures or on the Rust version.
+/// Accounts are left out on purpose: changed mapping rules must not change the hash of a transaction.
+pub fn transaction_hash(prefix: &str, transaction: &Transaction) -> String {
+    let mut amounts: Vec<String> = transaction
+        .postings
+        .iter()
+        .filter_map(|p| p.amount.as_ref())
+        .map(|a| format!("amount:{} {}", a.commodity, a.normalized().amount))
+        .collect();
+    amounts.sort();
+
+    let canonical = format!(
+        "date:{}\npayee:{}\n{}\n",
+        transaction.date.format("%Y-%m-%d"),
+        transaction.payee.trim(),
+        amounts.join("\n")
+    );
+    format!("{}_{:x}", prefix, md5::compute(canonical.as_bytes()))
+}
+
+/// Uses a content hash as code of transactions without code (e.g. for deduplication).
+/// Identical transactions within one import (e.g. two coffees of the same price on the same day) are told apart
+/// by their occurrence: the first one keeps the plain hash, the following ones get `_2`, `_3`, ... appended.
+pub fn assign_hash_codes(transactions: &mut [Transaction], prefix: &str) {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for transaction in transactions.iter_mut().filter(|t| t.code.is_none()) {
+        let hash = transaction_hash(prefix, transaction);
+        let occurrence = occurrences.entry(hash.clone()).or_default();
+        *occurrence += 1;
+        transaction.code = Some(match *occurrence {
+            1 => hash,
+            n => format!("{}_{}", hash, n),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, Posting, Tag, TransactionState};
+
+    use super::*;
+
+    #[test]
+    fn stable_hash() {
+        let posting = |account: &str, amount: Option<&str>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| {
+                AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), "EUR".to_owned())
+            }),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let mut transaction = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            code: None,
+            payee: "Patreon".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                posting("Assets:Revolut", Some("-24.40")),
+                posting("Expenses:Donation", None),
+            ],
+        };
+
+        // md5 of "date:2024-05-01\npayee:Patreon\namount:EUR -24.4\n"
+        let expected = "revolut_03ad26f004fdb506224ac99b53c8d118";
+        assert_eq!(transaction_hash("revolut", &transaction), expected);
+
+        // independent of accounts, tags and the precision of amounts
+        transaction.postings = vec![
+            posting("Expenses:Other", None),
+            posting("Assets:Revolut", Some("-24.4")),
+        ];
+        transaction.tags = vec![Tag::new("tag".to_owned())];
+        assert_eq!(transaction_hash("revolut", &transaction), expected);
+
+        transaction.payee = "Apple".to_owned();
+        assert_ne!(transaction_hash("revolut", &transaction), expected);
+    }
+
+    #[test]
+    fn identical_transactions() {
+        let transaction = |payee: &str| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![Posting {
+                account: "Assets:Revolut".to_owned(),
+                amount: Some(AmountAndCommodity::new(
+                    BigDecimal::from_str("-3.50").unwrap(),
+                    "EUR".to_owned(),
+                )),
+                comment: None,
+                tags: vec![],
+                price: None,
+                balance_assertion: None,
+            }],
+        };
+        let mut transactions = vec![
+            transaction("Coffee"),
+            transaction("Cake"),
+            transaction("Coffee"),
+            Transaction {
+                code: Some("bank-reference".to_owned()),
+                ..transaction("Coffee")
+            },
+            transaction("Coffee"),
+        ];
+        assign_hash_codes(&mut transactions, "revolut");
+
+        let coffee = transaction_hash("revolut", &transaction("Coffee"));
+        let codes: Vec<String> = transactions
+            .iter()
+            .map(|t| t.code.clone().unwrap())
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                coffee.clone(),
+                transaction_hash("revolut", &transaction("Cake")),
+                format!("{}_2", coffee),
+                "bank-reference".to_owned(),
+                format!("{}_3", coffee),
+            ]
+        );
+    }
+}
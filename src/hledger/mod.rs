@@ -1,4 +1,9 @@
+pub mod accounts;
+pub mod currency;
 pub mod deduplication;
 pub mod format;
+pub mod hasher;
 pub mod output;
+pub mod process;
 pub mod query;
+pub mod rules;
@@ -1,10 +1,14 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, RoundingMode, Signed, Zero};
 use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::config::{CommodityStyle, DateBasis, OutputConfig};
 
 /// helper structure that binds the currency/commodity to a given amount (e.g. 25.39 USD or 0.1 BTC)
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct AmountAndCommodity {
     pub amount: BigDecimal,
     pub commodity: String,
@@ -20,6 +24,60 @@ impl AmountAndCommodity {
     pub fn new(amount: BigDecimal, commodity: String) -> Self {
         Self { amount, commodity }
     }
+
+    /// renders the amount with the commodity placed as configured
+    pub fn render(&self, options: &OutputConfig) -> String {
+        let symbol = options.commodity_symbols.get(&self.commodity);
+        match (options.commodity_style, symbol) {
+            (CommodityStyle::Suffix, _) => self.to_string(),
+            (CommodityStyle::PrefixSymbol, Some(symbol)) if self.amount.is_negative() => {
+                format!("-{}{}", symbol, self.amount.abs())
+            }
+            (CommodityStyle::PrefixSymbol, Some(symbol)) => format!("{}{}", symbol, self.amount),
+            (CommodityStyle::PrefixCode | CommodityStyle::PrefixSymbol, _) => {
+                format!("{} {}", &self.commodity, self.amount)
+            }
+        }
+    }
+
+    /// rounds the amount to the given number of decimal places
+    pub fn rounded(&self, decimals: i64, mode: RoundingMode) -> Self {
+        Self {
+            amount: self.amount.with_scale_round(decimals, mode),
+            commodity: self.commodity.clone(),
+        }
+    }
+
+    /// removes trailing zeros of the fractional part (e.g. `150.00` becomes `150`)
+    pub fn normalized(&self) -> Self {
+        let amount = self.amount.normalized();
+        let amount = if amount.fractional_digit_count() < 0 {
+            amount.with_scale(0)
+        } else {
+            amount
+        };
+        Self {
+            amount,
+            commodity: self.commodity.clone(),
+        }
+    }
+}
+
+/// Moves the decimal point of the value to the left (e.g. `12345` with 2 decimals becomes `123.45`).
+/// Unlike a division by `10^decimals`, the result is exact and keeps the given number of decimal places.
+pub fn shift_decimal_point(value: BigDecimal, decimals: i64) -> BigDecimal {
+    let (digits, scale) = value.into_bigint_and_exponent();
+    BigDecimal::new(digits, scale + decimals)
+}
+
+/// The first ten characters of a timestamp (the ISO date, e.g. `2024-06-03` of `2024-06-03 13:05:33`),
+/// if the value is long enough. Unlike slicing bytes, multibyte characters do not cause a panic.
+pub fn safe_date_prefix(value: &str) -> Option<&str> {
+    match value.char_indices().nth(10) {
+        Some((end, _)) => Some(&value[..end]),
+        None if value.chars().count() == 10 => Some(value),
+        None => None,
+    }
 }
 
 /// hledger uses tags to identify transactions or postings.
@@ -36,10 +94,24 @@ impl PartialEq for Tag {
     }
 }
 
+/// consistent with the equality of tags
+impl Ord for Tag {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl PartialOrd for Tag {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Display for Tag {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(value) = &self.value {
-            write!(f, "{}: {}", &self.name, value)
+            // `|` is kept, it separates the fields of the `raw` tag
+            write!(f, "{}: {}", &self.name, single_line(value))
         } else {
             write!(f, "{}:", &self.name)
         }
@@ -70,7 +142,8 @@ impl Tag {
 /// Cleared transactions are posted and confirmed by the bank (e.g. the transcation appears on the account statement).
 /// Pending transactions are in an unclear state and might need further checking. Pending transactions are not verified.
 /// Transactions in default state are registered in the accounting system and usually do not need any further verification.
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TransactionState {
     #[default]
     Default,
@@ -102,54 +175,324 @@ pub struct Transaction {
     pub postings: Vec<Posting>,
 }
 
+/// Transactions are ordered by date, payee and the first posting amount (transactions without amount first,
+/// amounts compared numerically, then by commodity). Remaining ties are ordered by code and the other fields,
+/// so that only equal transactions compare as equal (e.g. `-3.5 EUR` and `-3.50 EUR`).
+impl Ord for Transaction {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        fn amount(t: &Transaction) -> Option<&AmountAndCommodity> {
+            t.postings.iter().find_map(|p| p.amount.as_ref())
+        }
+        self.date
+            .cmp(&other.date)
+            .then_with(|| self.payee.cmp(&other.payee))
+            .then_with(|| amount(self).cmp(&amount(other)))
+            .then_with(|| self.code.cmp(&other.code))
+            .then_with(|| {
+                (
+                    &self.state,
+                    &self.note,
+                    &self.comment,
+                    &self.tags,
+                    &self.postings,
+                )
+                    .cmp(&(
+                        &other.state,
+                        &other.note,
+                        &other.comment,
+                        &other.tags,
+                        &other.postings,
+                    ))
+            })
+    }
+}
+
+impl PartialOrd for Transaction {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Display for Transaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let date = self.date.format("%Y-%m-%d").to_string();
+        write!(f, "{}", self.render(&OutputConfig::default()))
+    }
+}
+
+impl Transaction {
+    /// Sum of all explicit posting amounts per commodity, commodities that sum up to zero are omitted.
+    /// The residual must be balanced by a posting without amount (plug posting).
+    pub fn residual(&self) -> HashMap<String, BigDecimal> {
+        let mut residual: HashMap<String, BigDecimal> = HashMap::new();
+        self.postings
+            .iter()
+            .filter_map(|p| p.amount.as_ref().map(|a| (a, p.price.as_ref())))
+            .for_each(|(a, price)| match price {
+                // a priced amount is balanced by its cost
+                Some(price) => {
+                    *residual.entry(price.commodity.clone()).or_default() +=
+                        price.amount.abs() * a.amount.signum();
+                }
+                None => *residual.entry(a.commodity.clone()).or_default() += &a.amount,
+            });
+        residual.retain(|_, amount| !amount.is_zero());
+        residual
+    }
+
+    /// Posts the residual of a conversion with priced postings (e.g. `108.12 USD @@ 100.01 EUR`) and without
+    /// plug posting to the given account, e.g. a rounding difference of the exchange rate.
+    /// Conversions without price are balanced by hledger, which infers the exchange rate.
+    pub fn book_conversion_difference(&mut self, account: &str) {
+        if self.postings.iter().all(|p| p.price.is_none())
+            || self.postings.iter().any(|p| p.amount.is_none())
+        {
+            return;
+        }
+
+        let mut residual: Vec<(String, BigDecimal)> = self.residual().into_iter().collect();
+        residual.sort_by(|a, b| a.0.cmp(&b.0));
+        for (commodity, amount) in residual {
+            self.postings.push(Posting {
+                account: account.to_owned(),
+                amount: Some(AmountAndCommodity::new(-amount, commodity)),
+                comment: Some("conversion difference".to_owned()),
+                tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
+            });
+        }
+    }
+
+    /// A transaction balances if the amounts of each commodity sum up to zero,
+    /// or if there is exactly one posting without amount that takes up the residual.
+    pub fn balanced(&self) -> bool {
+        match self.postings.iter().filter(|p| p.amount.is_none()).count() {
+            0 => self.residual().is_empty(),
+            1 => true,
+            _ => false,
+        }
+    }
+
+    /// Uses the value date (kept in the `valuation` tag by the importers) as transaction date and keeps
+    /// the booking date as `booking` tag instead. Transactions without value date are left unchanged.
+    pub fn apply_date_basis(&mut self, basis: DateBasis) {
+        if basis == DateBasis::Booking {
+            return;
+        }
+        let Some(tag) = self.tags.iter_mut().find(|t| t.name == "valuation") else {
+            return;
+        };
+        let valuation = tag
+            .value
+            .as_deref()
+            .and_then(safe_date_prefix)
+            .and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok());
+        if let Some(valuation) = valuation {
+            *tag = Tag::new_val(
+                "booking".to_owned(),
+                self.date.format("%Y-%m-%d").to_string(),
+            );
+            self.date = valuation;
+        }
+    }
+
+    /// removes the tags with the given names from the transaction and its postings
+    pub fn remove_tags(&mut self, names: &[String]) {
+        self.tags.retain(|tag| !names.contains(&tag.name));
+        for posting in self.postings.iter_mut() {
+            posting.tags.retain(|tag| !names.contains(&tag.name));
+        }
+    }
+
+    /// sorts the postings deterministically: postings with amount first, then by account name,
+    /// the plug posting (without amount) last. Postings of the same account keep their order.
+    pub fn sort_postings(&mut self) {
+        self.postings.sort_by(|a, b| {
+            (a.amount.is_none(), &a.account).cmp(&(b.amount.is_none(), &b.account))
+        });
+    }
+
+    /// rounds all posting amounts to the given number of decimal places
+    pub fn round_amounts(&mut self, decimals: i64, mode: RoundingMode) {
+        for posting in self.postings.iter_mut() {
+            if let Some(amount) = &posting.amount {
+                posting.amount = Some(amount.rounded(decimals, mode));
+            }
+            if let Some(price) = &posting.price {
+                posting.price = Some(price.rounded(decimals, mode));
+            }
+            if let Some(balance) = &posting.balance_assertion {
+                posting.balance_assertion = Some(balance.rounded(decimals, mode));
+            }
+        }
+    }
+
+    /// removes trailing zeros of all posting amounts
+    pub fn normalize_amounts(&mut self) {
+        for posting in self.postings.iter_mut() {
+            if let Some(amount) = &posting.amount {
+                posting.amount = Some(amount.normalized());
+            }
+            if let Some(price) = &posting.price {
+                posting.price = Some(price.normalized());
+            }
+            if let Some(balance) = &posting.balance_assertion {
+                posting.balance_assertion = Some(balance.normalized());
+            }
+        }
+    }
+
+    /// the date of the `valuation` tag (if it starts with an ISO date)
+    fn valuation_date(&self) -> Option<NaiveDate> {
+        self.tags
+            .iter()
+            .find(|tag| tag.name == "valuation")
+            .and_then(|tag| tag.value.as_deref())
+            .and_then(safe_date_prefix)
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    }
+
+    /// renders the transaction in hledger journal format, respecting the given output options
+    pub fn render(&self, options: &OutputConfig) -> String {
+        let indent = options.indentation();
+        let mut date = self.date.format("%Y-%m-%d").to_string();
+        let date2 = options
+            .valuation_as_date2
+            .then(|| self.valuation_date())
+            .flatten();
+        if let Some(date2) = date2 {
+            date = format!("{}={}", date, date2.format("%Y-%m-%d"));
+        }
         let mut result = format!("{} {}", &date, &self.state);
         if let Some(code) = &self.code {
-            result = format!("{} ({})", &result, code);
+            if !options.suppress_code {
+                result = format!("{} ({})", &result, code);
+            }
         }
-        result = format!("{} {}", &result, &self.payee);
+        result = format!("{} {}", &result, escape_description(&self.payee));
         if let Some(note) = &self.note {
-            result = format!("{} | {}", &result, note);
+            let note = single_line(note);
+            let max_width = options
+                .note_max_width
+                .filter(|width| note.chars().count() > *width);
+            if let Some(width) = max_width {
+                for line in wrap_words(&note, width) {
+                    result = format!("{}\n{}; {}", &result, indent, line);
+                }
+            } else if options.note_as_comment {
+                result = format!("{}\n{}; {}", &result, indent, note);
+            } else {
+                result = format!("{} | {}", &result, escape_description(&note));
+            }
         }
         if let Some(comment) = &self.comment {
-            result = format!("{}\n    ; {}", &result, comment);
+            result = format!("{}\n{}; {}", &result, indent, sanitize(comment));
+        }
+        // the valuation date rendered as secondary date replaces the tag
+        self.tags
+            .iter()
+            .filter(|tag| date2.is_none() || tag.name != "valuation")
+            .for_each(|tag| {
+                result = format!("{}\n{}; {}", &result, indent, tag);
+            });
+        if let Some(code) = &self.code {
+            if options.suppress_code {
+                let tag = Tag::new_val("code".to_owned(), code.clone());
+                result = format!("{}\n{}; {}", &result, indent, tag);
+            }
         }
-        self.tags.iter().for_each(|tag| {
-            result = format!("{}\n    ; {}", &result, tag);
-        });
         self.postings.iter().for_each(|p| {
-            result = format!("{}\n{}", &result, p);
+            result = format!("{}\n{}", &result, p.render(options));
         });
-        write!(f, "{}", &result)
+        result
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// replaces line breaks, which would otherwise be read as the start of a new directive
+fn single_line(text: &str) -> String {
+    text.split(['\n', '\r'])
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// splits the text at whitespace into lines of at most `width` characters (longer words get a line of their own)
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// `|` separates payee and note in hledger, it is replaced in payees and comments along with line breaks
+fn sanitize(text: &str) -> String {
+    single_line(text).replace('|', "/")
+}
+
+/// hledger ends the description at the first `;` (start of a comment), so it is replaced as well
+fn escape_description(text: &str) -> String {
+    sanitize(text)
+        .replace(';', ",")
+        .trim_start_matches(',')
+        .trim()
+        .to_owned()
+}
+
+/// A posting books an amount of a single commodity on an account.
+/// Like in the hledger journal format, amounts in several commodities (e.g. a currency exchange)
+/// are represented by separate postings, one per commodity.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Posting {
     pub account: String,
     pub amount: Option<AmountAndCommodity>,
     pub comment: Option<String>,
     pub tags: Vec<Tag>,
+    /// total price of the amount in another commodity (`@@ 18.50 EUR`), e.g. of a foreign card purchase
+    pub price: Option<AmountAndCommodity>,
+    /// balance of the account after this posting (`= 100 EUR`), checked by hledger
+    pub balance_assertion: Option<AmountAndCommodity>,
 }
 
 impl Display for Posting {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(&OutputConfig::default()))
+    }
+}
+
+impl Posting {
+    /// renders the posting in hledger journal format, respecting the given output options
+    pub fn render(&self, options: &OutputConfig) -> String {
+        let indent = options.indentation();
         let mut render = match &self.amount {
-            Some(amount) => {
-                let amount = amount.to_string();
-                format!("    {}     {}", &self.account, &amount)
-            }
-            None => format!("    {}", &self.account),
+            Some(amount) => format!("{}{}     {}", indent, &self.account, amount.render(options)),
+            None if self.balance_assertion.is_some() => format!("{}{}    ", indent, &self.account),
+            None => format!("{}{}", indent, &self.account),
         };
+        if let (Some(_), Some(price)) = (&self.amount, &self.price) {
+            render = format!("{} @@ {}", &render, price.render(options));
+        }
+        if let Some(balance) = &self.balance_assertion {
+            render = format!("{} = {}", &render, balance.render(options));
+        }
         if let Some(comment) = &self.comment {
-            render = format!("{}\n    ; {}", &render, comment);
+            render = format!("{}\n{}; {}", &render, indent, sanitize(comment));
         }
         self.tags.iter().for_each(|tag| {
-            render = format!("{}\n    ; {}", &render, tag);
+            render = format!("{}\n{}; {}", &render, indent, tag);
         });
-        write!(f, "{}", &render)
+        render
     }
 }
 
@@ -262,6 +605,45 @@ mod tests {
         assert_eq!(a.to_string(), "12.1 USD");
     }
 
+    #[test]
+    fn normalized_amount() {
+        let normalized = |a: &str| {
+            AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), "EUR".to_owned())
+                .normalized()
+                .to_string()
+        };
+        assert_eq!(normalized("150.00"), "150 EUR");
+        assert_eq!(normalized("-12.50"), "-12.5 EUR");
+        assert_eq!(normalized("1500"), "1500 EUR");
+        assert_eq!(normalized("0.00"), "0 EUR");
+        assert_eq!(normalized("0.12345678"), "0.12345678 EUR");
+    }
+
+    #[test]
+    fn rounded_amount() {
+        let rounded = |a: &str, mode: RoundingMode| {
+            AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), "EUR".to_owned())
+                .rounded(2, mode)
+                .to_string()
+        };
+        assert_eq!(rounded("0.125", RoundingMode::HalfEven), "0.12 EUR");
+        assert_eq!(rounded("0.125", RoundingMode::HalfUp), "0.13 EUR");
+        assert_eq!(rounded("0.135", RoundingMode::HalfEven), "0.14 EUR");
+        assert_eq!(rounded("-0.125", RoundingMode::HalfUp), "-0.13 EUR");
+        assert_eq!(rounded("1.5", RoundingMode::HalfEven), "1.50 EUR");
+    }
+
+    #[test]
+    fn shift_decimal_point_exactly() {
+        let shifted = shift_decimal_point(BigDecimal::from(-1), 2);
+        assert_eq!(shifted.to_string(), "-0.01");
+        let shifted = shift_decimal_point(BigDecimal::from(15000), 2);
+        assert_eq!(shifted.to_string(), "150.00");
+        // more decimal places than fit into a u32 power of ten
+        let shifted = shift_decimal_point(BigDecimal::from_str("123456789012").unwrap(), 12);
+        assert_eq!(shifted.to_string(), "0.123456789012");
+    }
+
     #[test]
     fn posting_to_str() {
         let posting = Posting {
@@ -275,6 +657,8 @@ mod tests {
                 Tag::new("lunch".to_owned()),
                 Tag::new_val("valuation".to_owned(), "2024-05-02".to_owned()),
             ],
+            price: None,
+            balance_assertion: None,
         };
         let result = posting.to_string();
         assert_eq!(
@@ -287,6 +671,8 @@ mod tests {
             amount: None,
             comment: None,
             tags: vec![],
+            price: None,
+            balance_assertion: None,
         };
         let result = posting.to_string();
         assert_eq!(result, "    Expenses:Groceries");
@@ -296,11 +682,44 @@ mod tests {
             amount: None,
             comment: Some("test comment".to_owned()),
             tags: vec![],
+            price: None,
+            balance_assertion: None,
         };
         let result = posting.to_string();
         assert_eq!(result, "    Expenses:Groceries\n    ; test comment");
     }
 
+    #[test]
+    fn posting_indentation() {
+        let posting = Posting {
+            account: String::from("Assets:Cash"),
+            amount: Some(AmountAndCommodity::new(
+                BigDecimal::from_str("-11.44").unwrap(),
+                "EUR".to_owned(),
+            )),
+            comment: Some("lunch".to_owned()),
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+
+        let mut options = OutputConfig {
+            indent: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            posting.render(&options),
+            "  Assets:Cash     -11.44 EUR\n  ; lunch"
+        );
+
+        options.indent = Some(4);
+        assert_eq!(
+            posting.render(&options),
+            "    Assets:Cash     -11.44 EUR\n    ; lunch"
+        );
+        assert_eq!(posting.render(&options), posting.to_string());
+    }
+
     #[test]
     fn transaction_to_str() {
         let t = Transaction {
@@ -349,6 +768,137 @@ mod tests {
         assert_eq!(result, "2024-11-22 ! Payer");
     }
 
+    #[test]
+    fn transaction_note_as_comment() {
+        let t = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 11, 22).unwrap(),
+            code: Some("ABC123".to_owned()),
+            payee: "Test".to_owned(),
+            note: Some("Note".to_owned()),
+            state: TransactionState::Cleared,
+            comment: Some("comment".to_owned()),
+            tags: vec![Tag::new("lunch".to_owned())],
+            postings: vec![],
+        };
+        let options = OutputConfig {
+            note_as_comment: true,
+            ..Default::default()
+        };
+        let result = t.render(&options);
+        assert_eq!(
+            result,
+            "2024-11-22 * (ABC123) Test\n    ; Note\n    ; comment\n    ; lunch:"
+        );
+    }
+
+    #[test]
+    fn transaction_long_note_wrapped() {
+        let words = ["Invoice", "2024-0815", "monthly", "subscription", "renewal"];
+        let note = (0..25)
+            .map(|i| words[i % words.len()])
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(200)
+            .collect::<String>();
+        assert_eq!(note.chars().count(), 200);
+        let t = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 11, 22).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: Some(note.clone()),
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![],
+        };
+        let options = OutputConfig {
+            note_max_width: Some(60),
+            ..Default::default()
+        };
+        let result = t.render(&options);
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[0], "2024-11-22 * Test");
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1..]
+            .iter()
+            .all(|l| l.starts_with("    ; ") && l.len() <= 66));
+        let rejoined = lines[1..]
+            .iter()
+            .map(|l| l.trim_start_matches("    ; "))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(rejoined, note);
+
+        // short notes stay in the description
+        let options = OutputConfig {
+            note_max_width: Some(250),
+            ..Default::default()
+        };
+        assert_eq!(t.render(&options), format!("2024-11-22 * Test | {}", note));
+    }
+
+    #[test]
+    fn transaction_suppress_code() {
+        let t = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 11, 22).unwrap(),
+            code: Some("ABC123".to_owned()),
+            payee: "Test".to_owned(),
+            note: Some("Note".to_owned()),
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![Tag::new("lunch".to_owned())],
+            postings: vec![],
+        };
+        let options = OutputConfig {
+            suppress_code: true,
+            ..Default::default()
+        };
+        let result = t.render(&options);
+        assert_eq!(
+            result,
+            "2024-11-22 * Test | Note\n    ; lunch:\n    ; code: ABC123"
+        );
+
+        let t = Transaction { code: None, ..t };
+        let result = t.render(&options);
+        assert_eq!(result, "2024-11-22 * Test | Note\n    ; lunch:");
+    }
+
+    #[test]
+    fn transaction_valuation_as_date2() {
+        let t = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 6, 3).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![
+                Tag::new_val("valuation".to_owned(), "2024-06-01 13:05:33".to_owned()),
+                Tag::new("lunch".to_owned()),
+            ],
+            postings: vec![],
+        };
+        let mut options = OutputConfig::default();
+        assert_eq!(
+            t.render(&options),
+            "2024-06-03 * Test\n    ; valuation: 2024-06-01 13:05:33\n    ; lunch:"
+        );
+
+        options.valuation_as_date2 = true;
+        assert_eq!(
+            t.render(&options),
+            "2024-06-03=2024-06-01 * Test\n    ; lunch:"
+        );
+
+        let t = Transaction {
+            tags: vec![Tag::new("lunch".to_owned())],
+            ..t
+        };
+        assert_eq!(t.render(&options), "2024-06-03 * Test\n    ; lunch:");
+    }
+
     #[test]
     fn full_transaction_to_str() {
         let t = Transaction {
@@ -368,12 +918,16 @@ mod tests {
                     )),
                     comment: None,
                     tags: vec![],
+                    price: None,
+                    balance_assertion: None,
                 },
                 Posting {
                     account: "Expenses:Test".to_owned(),
                     amount: None,
                     comment: Some("Some test".to_owned()),
                     tags: vec![],
+                    price: None,
+                    balance_assertion: None,
                 },
             ],
         };
@@ -397,12 +951,16 @@ mod tests {
                     )),
                     comment: None,
                     tags: vec![],
+                    price: None,
+                    balance_assertion: None,
                 },
                 Posting {
                     account: "Expenses:Test".to_owned(),
                     amount: None,
                     comment: Some("Some test".to_owned()),
                     tags: vec![],
+                    price: None,
+                    balance_assertion: None,
                 },
             ],
         };
@@ -410,6 +968,262 @@ mod tests {
         assert_eq!(result, "2020-06-18 * Store | Bought something\n    ; this is a test\n    Assets:Cash     -2799.97 EUR\n    Expenses:Test\n    ; Some test");
     }
 
+    #[test]
+    fn multi_commodity_transaction_to_str() {
+        let t = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Exchanged to USD".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                Posting {
+                    account: "Assets:Revolut:EUR".to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::from_str("-100.00").unwrap(),
+                        "EUR".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+                Posting {
+                    account: "Assets:Revolut:USD".to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::from_str("108.12").unwrap(),
+                        "USD".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+            ],
+        };
+        let result = t.to_string();
+        assert_eq!(result, "2024-05-10 * Exchanged to USD\n    Assets:Revolut:EUR     -100.00 EUR\n    Assets:Revolut:USD     108.12 USD");
+    }
+
+    #[test]
+    fn balanced_transactions() {
+        let posting = |account: &str, amount: Option<(&str, &str)>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|(a, c)| {
+                AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), c.to_owned())
+            }),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let transaction = |postings: Vec<Posting>| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings,
+        };
+
+        // plug posting
+        let t = transaction(vec![
+            posting("Assets:Cash", Some(("-10.50", "EUR"))),
+            posting("Expenses:Test", None),
+        ]);
+        assert!(t.balanced());
+        assert_eq!(
+            t.residual(),
+            HashMap::from([("EUR".to_owned(), BigDecimal::from_str("-10.50").unwrap())])
+        );
+
+        // multi-commodity
+        let t = transaction(vec![
+            posting("Assets:Revolut:EUR", Some(("-100", "EUR"))),
+            posting("Equity:Conversion", Some(("100.00", "EUR"))),
+            posting("Assets:Revolut:USD", Some(("108.12", "USD"))),
+            posting("Equity:Conversion", Some(("-108.12", "USD"))),
+        ]);
+        assert!(t.balanced());
+        assert!(t.residual().is_empty());
+
+        // unbalanced
+        let t = transaction(vec![
+            posting("Assets:Cash", Some(("-10.50", "EUR"))),
+            posting("Expenses:Test", Some(("10.49", "EUR"))),
+            posting("Assets:Revolut:USD", Some(("1", "USD"))),
+        ]);
+        assert!(!t.balanced());
+        assert_eq!(
+            t.residual(),
+            HashMap::from([
+                ("EUR".to_owned(), BigDecimal::from_str("-0.01").unwrap()),
+                ("USD".to_owned(), BigDecimal::from_str("1").unwrap()),
+            ])
+        );
+
+        // two plug postings are ambiguous
+        let t = transaction(vec![
+            posting("Assets:Cash", Some(("-10.50", "EUR"))),
+            posting("Expenses:Test", None),
+            posting("Expenses:Other", None),
+        ]);
+        assert!(!t.balanced());
+    }
+
+    #[test]
+    fn sorted_postings() {
+        let posting = |account: &str, amount: Option<&str>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| {
+                AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), "EUR".to_owned())
+            }),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let mut transaction = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                posting("Expenses:Groceries", None),
+                posting("Expenses:Fees", Some("1")),
+                posting("Assets:Revolut", Some("-11")),
+                posting("Equity:Conversion", Some("5")),
+                posting("Equity:Conversion", Some("-5")),
+            ],
+        };
+
+        transaction.sort_postings();
+
+        let postings: Vec<(&str, Option<String>)> = transaction
+            .postings
+            .iter()
+            .map(|p| (p.account.as_str(), p.amount.as_ref().map(|a| a.to_string())))
+            .collect();
+        assert_eq!(
+            postings,
+            vec![
+                ("Assets:Revolut", Some("-11 EUR".to_owned())),
+                ("Equity:Conversion", Some("5 EUR".to_owned())),
+                ("Equity:Conversion", Some("-5 EUR".to_owned())),
+                ("Expenses:Fees", Some("1 EUR".to_owned())),
+                ("Expenses:Groceries", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn transaction_escape_payee_and_note() {
+        let mut transaction = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Store\nVienna".to_owned(),
+            note: Some(";not a comment".to_owned()),
+            state: TransactionState::Cleared,
+            comment: Some("first line\r\nsecond line".to_owned()),
+            tags: vec![],
+            postings: vec![],
+        };
+        assert_eq!(
+            transaction.to_string(),
+            "2024-05-10 * Store Vienna | not a comment\n    ; first line second line"
+        );
+
+        transaction.payee = "; 2024-05-10 * Injected".to_owned();
+        transaction.note = Some("a; b".to_owned());
+        transaction.comment = None;
+        assert_eq!(
+            transaction.to_string(),
+            "2024-05-10 * 2024-05-10 * Injected | a, b"
+        );
+
+        let options = OutputConfig {
+            note_as_comment: true,
+            ..Default::default()
+        };
+        transaction.note = Some("line 1\nline 2".to_owned());
+        assert_eq!(
+            transaction.render(&options),
+            "2024-05-10 * 2024-05-10 * Injected\n    ; line 1 line 2"
+        );
+    }
+
+    #[test]
+    fn transaction_sanitize_pipes_and_line_breaks() {
+        let transaction = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Store | Vienna\r".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: Some("a|b\nc".to_owned()),
+            tags: vec![Tag::new_val(
+                "purpose".to_owned(),
+                "rent|june\r\n2024-06-01 * Injected".to_owned(),
+            )],
+            postings: vec![Posting {
+                account: "Assets:Cash".to_owned(),
+                amount: None,
+                comment: Some("x|y\r\nz".to_owned()),
+                tags: vec![Tag::new_val("ref".to_owned(), "1|2\n3".to_owned())],
+                price: None,
+                balance_assertion: None,
+            }],
+        };
+        assert_eq!(
+            transaction.to_string(),
+            "2024-05-10 * Store / Vienna\n    ; a/b c\n    ; purpose: rent|june 2024-06-01 * Injected\n    Assets:Cash\n    ; x/y z\n    ; ref: 1|2 3"
+        );
+    }
+
+    #[test]
+    fn commodity_styles() {
+        let amount = |a: &str, c: &str| {
+            AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), c.to_owned())
+        };
+        let mut options = OutputConfig {
+            commodity_symbols: HashMap::from([("EUR".to_owned(), "€".to_owned())]),
+            ..Default::default()
+        };
+
+        assert_eq!(amount("12.34", "EUR").render(&options), "12.34 EUR");
+        assert_eq!(amount("-12.34", "USD").render(&options), "-12.34 USD");
+
+        options.commodity_style = CommodityStyle::PrefixCode;
+        assert_eq!(amount("12.34", "EUR").render(&options), "EUR 12.34");
+        assert_eq!(amount("-12.34", "USD").render(&options), "USD -12.34");
+
+        options.commodity_style = CommodityStyle::PrefixSymbol;
+        assert_eq!(amount("12.34", "EUR").render(&options), "€12.34");
+        assert_eq!(amount("-12.34", "EUR").render(&options), "-€12.34");
+        // no symbol configured
+        assert_eq!(amount("-12.34", "USD").render(&options), "USD -12.34");
+
+        let posting = Posting {
+            account: "Assets:Cash".to_owned(),
+            amount: Some(amount("-12.34", "EUR")),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: Some(amount("100", "EUR")),
+        };
+        assert_eq!(
+            posting.render(&options),
+            "    Assets:Cash     -€12.34 = €100"
+        );
+    }
+
     #[test]
     fn display_minus_one_cent() {
         let amount = AmountAndCommodity {
@@ -419,4 +1233,163 @@ mod tests {
         let result = amount.to_string();
         assert_eq!(result, "-0.01 EUR");
     }
+
+    #[test]
+    fn transaction_ordering() {
+        let transaction = |day: u32, payee: &str, amount: Option<&str>| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, day).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![Posting {
+                account: "Assets:Cash".to_owned(),
+                amount: amount.map(|a| {
+                    AmountAndCommodity::new(BigDecimal::from_str(a).unwrap(), "EUR".to_owned())
+                }),
+                comment: None,
+                tags: vec![],
+                price: None,
+                balance_assertion: None,
+            }],
+        };
+
+        let mut transactions = [
+            transaction(2, "Bakery", Some("-3.50")),
+            transaction(1, "Grocery", Some("-20")),
+            transaction(2, "Bakery", Some("-10.00")),
+            transaction(1, "Bakery", Some("-5")),
+            transaction(2, "Bakery", None),
+            transaction(2, "Bakery", Some("-3.5")),
+        ];
+        transactions.sort();
+
+        let order: Vec<(u32, &str, Option<String>)> = transactions
+            .iter()
+            .map(|t| {
+                (
+                    chrono::Datelike::day(&t.date),
+                    t.payee.as_str(),
+                    t.postings[0].amount.as_ref().map(|a| a.amount.to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (1, "Bakery", Some("-5".to_owned())),
+                (1, "Grocery", Some("-20".to_owned())),
+                (2, "Bakery", None),
+                (2, "Bakery", Some("-10.00".to_owned())),
+                (2, "Bakery", Some("-3.50".to_owned())),
+                (2, "Bakery", Some("-3.5".to_owned())),
+            ]
+        );
+
+        // consistent with equality
+        let a = transaction(2, "Bakery", Some("-3.5"));
+        let b = transaction(2, "Bakery", Some("-3.50"));
+        assert_eq!(a, b);
+        assert_eq!(a.cmp(&b), std::cmp::Ordering::Equal);
+        let c = Transaction {
+            state: TransactionState::Pending,
+            ..b.clone()
+        };
+        assert_ne!(b, c);
+        assert_ne!(b.cmp(&c), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn date_prefix_of_short_and_multibyte_values() {
+        assert_eq!(safe_date_prefix("2024-06-03 13:05:33"), Some("2024-06-03"));
+        assert_eq!(safe_date_prefix("2024-06-03"), Some("2024-06-03"));
+        assert_eq!(safe_date_prefix("202"), None);
+        assert_eq!(safe_date_prefix("ä2024-06-03"), Some("ä2024-06-0"));
+        assert_eq!(safe_date_prefix("€€€€€€€€€€€"), Some("€€€€€€€€€€"));
+
+        let mut transaction = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 6, 3).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![Tag::new_val(
+                "valuation".to_owned(),
+                "ä2024-06-01".to_owned(),
+            )],
+            postings: vec![],
+        };
+        transaction.apply_date_basis(DateBasis::Value);
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
+        );
+        transaction.tags[0].value = Some("202".to_owned());
+        transaction.apply_date_basis(DateBasis::Value);
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
+        );
+    }
+
+    #[test]
+    fn conversion_difference() {
+        let posting = |account: &str, amount: &str, commodity: &str| Posting {
+            account: account.to_owned(),
+            amount: Some(AmountAndCommodity::new(
+                BigDecimal::from_str(amount).unwrap(),
+                commodity.to_owned(),
+            )),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let mut transaction = Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Exchanged to USD".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                posting("Assets:Revolut:EUR", "-100.00", "EUR"),
+                Posting {
+                    price: Some(AmountAndCommodity::new(
+                        BigDecimal::from_str("100.01").unwrap(),
+                        "EUR".to_owned(),
+                    )),
+                    ..posting("Assets:Revolut:USD", "108.12", "USD")
+                },
+            ],
+        };
+        let balanced = transaction.clone();
+
+        transaction.book_conversion_difference("Expenses:Conversion Differences");
+        assert_eq!(transaction.postings.len(), 3);
+        assert_eq!(
+            transaction.postings[2].to_string(),
+            "    Expenses:Conversion Differences     -0.01 EUR\n    ; conversion difference"
+        );
+        assert!(transaction.residual().is_empty());
+
+        // hledger infers the exchange rate of conversions without price
+        let mut transaction = balanced.clone();
+        transaction.postings[1].price = None;
+        transaction.book_conversion_difference("Expenses:Conversion Differences");
+        assert_eq!(transaction.postings.len(), 2);
+
+        // transactions with plug posting are balanced by hledger
+        let mut transaction = balanced;
+        transaction
+            .postings
+            .push(posting("Equity:Conversion", "0", "EUR"));
+        transaction.postings[2].amount = None;
+        transaction.book_conversion_difference("Expenses:Conversion Differences");
+        assert_eq!(transaction.postings.len(), 3);
+    }
 }
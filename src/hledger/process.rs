@@ -0,0 +1,136 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::error::{ImportError, Result};
+
+/// interval in which a running process is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Runs the command (feeding `input` to its standard input) and returns its standard output.
+/// If the command does not finish within the timeout, it is killed and an error is returned.
+/// A command exiting with a non-zero status fails as well.
+pub fn run_with_timeout(
+    command: &mut Command,
+    input: Option<&str>,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    let stdin = if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    };
+    let mut process = command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(ImportError::HledgerExecution)?;
+
+    // feed stdin and read stdout in separate threads, so that full pipes can not block us
+    let writer = match (process.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => {
+            let input = input.to_owned();
+            Some(std::thread::spawn(move || {
+                stdin.write_all(input.as_bytes())
+            }))
+        }
+        _ => None,
+    };
+    let reader = process.stdout.take().map(|mut stdout| {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        })
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = process.try_wait().map_err(ImportError::HledgerExecution)? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            process.kill().map_err(ImportError::HledgerExecution)?;
+            process.wait().map_err(ImportError::HledgerExecution)?;
+            return Err(ImportError::HledgerExecution(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!(
+                    "\"{}\" did not finish within {} seconds",
+                    command.get_program().to_string_lossy(),
+                    timeout.as_secs_f64()
+                ),
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    if let Some(Ok(Err(e))) = writer.map(|writer| writer.join()) {
+        // hledger is free to stop reading its input early
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            return Err(ImportError::HledgerExecution(e));
+        }
+    }
+    if !status.success() {
+        return Err(ImportError::HledgerExecution(std::io::Error::other(
+            format!(
+                "\"{}\" failed ({})",
+                command.get_program().to_string_lossy(),
+                status
+            ),
+        )));
+    }
+    match reader.map(|reader| reader.join()) {
+        Some(Ok(output)) => output.map_err(ImportError::HledgerExecution),
+        Some(Err(_)) => Err(ImportError::HledgerExecution(std::io::Error::other(
+            "failed to read standard output",
+        ))),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_of_command() {
+        let output = run_with_timeout(
+            Command::new("cat").arg("-"),
+            Some("2024-11-22 * Test\n"),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_eq!(output, b"2024-11-22 * Test\n");
+    }
+
+    #[test]
+    fn failing_command() {
+        let result = run_with_timeout(
+            Command::new("sh").args(["-c", "echo partial; exit 1"]),
+            None,
+            Duration::from_secs(10),
+        );
+        match result {
+            Err(ImportError::HledgerExecution(e)) => {
+                assert!(e.to_string().contains("exit status: 1"), "{}", e)
+            }
+            _ => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn kill_slow_command() {
+        let start = Instant::now();
+        let result = run_with_timeout(
+            Command::new("sleep").arg("10"),
+            None,
+            Duration::from_millis(100),
+        );
+        match result {
+            Err(ImportError::HledgerExecution(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::TimedOut)
+            }
+            _ => panic!("expected a timeout"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
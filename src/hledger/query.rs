@@ -1,12 +1,11 @@
-use std::process::Command;
-
 use bigdecimal::{BigDecimal, FromPrimitive};
 use chrono::NaiveDate;
 use serde::Deserialize;
 
 use crate::{config::HledgerConfig, error::*};
 
-use super::output::AmountAndCommodity;
+use super::output::{shift_decimal_point, AmountAndCommodity};
+use super::process::run_with_timeout;
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct HledgerJsonTransaction {
@@ -43,7 +42,7 @@ impl TryFrom<HledgerJsonQuantity> for BigDecimal {
 
     fn try_from(value: HledgerJsonQuantity) -> std::result::Result<Self, Self::Error> {
         match BigDecimal::from_i64(value.decimal_mantissa) {
-            Some(d) => Ok(d / (10_i64).pow(value.decimal_places)),
+            Some(d) => Ok(shift_decimal_point(d, value.decimal_places as i64)),
             None => Err(ImportError::NumerConversion(format!(
                 "{}",
                 value.decimal_mantissa
@@ -71,54 +70,50 @@ pub fn query_hledger_by_payee_and_account(
     begin: Option<NaiveDate>,
     end: Option<NaiveDate>,
 ) -> Result<Vec<HledgerJsonTransaction>> {
-    let output = if begin.is_some() && end.is_some() {
-        Command::new(&config.path)
-            .arg("print")
-            .arg("-O")
-            .arg("json")
-            .arg(format!("payee:{}", payee))
-            .arg("-b")
-            .arg(begin.unwrap().format("%Y-%m-%d").to_string())
-            .arg("-e")
-            .arg(end.unwrap().format("%Y-%m-%d").to_string())
-            .arg(account)
-            .output()
-    } else if let Some(begin) = begin {
-        Command::new(&config.path)
-            .arg("print")
-            .arg("-O")
-            .arg("json")
-            .arg(format!("payee:{}", payee))
-            .arg("-b")
-            .arg(begin.format("%Y-%m-%d").to_string())
-            .arg(account)
-            .output()
-    } else if let Some(end) = end {
-        Command::new(&config.path)
-            .arg("print")
-            .arg("-O")
-            .arg("json")
-            .arg(format!("payee:{}", payee))
-            .arg("-e")
-            .arg(end.format("%Y-%m-%d").to_string())
-            .arg(account)
-            .output()
-    } else {
-        Command::new(&config.path)
-            .arg("print")
-            .arg("-O")
-            .arg("json")
-            .arg(format!("payee:{}", payee))
-            .arg(account)
-            .output()
-    };
+    let mut command = config.command();
+    command
+        .arg("print")
+        .arg("-O")
+        .arg("json")
+        .arg(format!("payee:{}", payee));
+    if let Some(begin) = begin {
+        command.arg("-b").arg(begin.format("%Y-%m-%d").to_string());
+    }
+    if let Some(end) = end {
+        command.arg("-e").arg(end.format("%Y-%m-%d").to_string());
+    }
+    command.arg(account);
 
-    let output = match output {
-        Ok(o) => o,
-        Err(e) => return Err(ImportError::HledgerExecution(e)),
-    };
+    query_transactions(config, &mut command)
+}
+
+/// all transactions of the journal between `begin` (inclusive) and `end` (exclusive)
+pub fn query_hledger_by_date(
+    config: &HledgerConfig,
+    begin: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<HledgerJsonTransaction>> {
+    let mut command = config.command();
+    command
+        .arg("print")
+        .arg("-O")
+        .arg("json")
+        .arg("-b")
+        .arg(begin.format("%Y-%m-%d").to_string())
+        .arg("-e")
+        .arg(end.format("%Y-%m-%d").to_string());
+
+    query_transactions(config, &mut command)
+}
+
+/// runs a `hledger print -O json` command and parses its transactions
+fn query_transactions(
+    config: &HledgerConfig,
+    command: &mut std::process::Command,
+) -> Result<Vec<HledgerJsonTransaction>> {
+    let output = run_with_timeout(command, None, config.timeout())?;
 
-    let json_str = match std::str::from_utf8(&output.stdout) {
+    let json_str = match std::str::from_utf8(&output) {
         Ok(c) => c,
         Err(e) => return Err(ImportError::StringConversion(e)),
     };
@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{ImportError, Result};
+use crate::hledger::output::{Transaction, TransactionState};
+
+/// Transactions as CSV file together with a matching hledger CSV rules file,
+/// so that the transactions can be imported with `hledger import`.
+#[derive(Debug)]
+pub struct RulesExport {
+    pub csv: String,
+    pub rules: String,
+}
+
+impl RulesExport {
+    pub fn new(transactions: &[Transaction]) -> Self {
+        let posting_count = transactions
+            .iter()
+            .map(|t| t.postings.len())
+            .max()
+            .unwrap_or_default();
+        let fields = Self::fields(posting_count);
+
+        let mut csv = csv_line(&fields);
+        for transaction in transactions {
+            csv.push_str(&csv_line(&Self::record(transaction, posting_count)));
+        }
+
+        let mut rules = String::from("# generated by hledger-import\n");
+        rules.push_str("skip 1\n");
+        rules.push_str(&format!("fields {}\n", fields.join(", ")));
+        rules.push_str("date-format %Y-%m-%d\n");
+        rules.push_str("description %payee\n");
+        rules.push_str("if %note .\n  description %payee | %note\n");
+
+        Self { csv, rules }
+    }
+
+    /// writes `<file>` and the rules file `<file>.rules` next to it (where `hledger import` expects it)
+    pub fn write(&self, file: &Path) -> Result<()> {
+        let rules_file = rules_path(file);
+        std::fs::write(file, &self.csv).map_err(|_| ImportError::OutputFileWrite(file.into()))?;
+        std::fs::write(&rules_file, &self.rules)
+            .map_err(|_| ImportError::OutputFileWrite(rules_file))
+    }
+
+    /// CSV columns, the names are hledger field names (or referenced in the rules)
+    fn fields(posting_count: usize) -> Vec<String> {
+        let mut fields: Vec<String> = ["date", "code", "status", "payee", "note", "comment"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        for i in 1..=posting_count {
+            fields.push(format!("account{}", i));
+            fields.push(format!("amount{}", i));
+            fields.push(format!("currency{}", i));
+        }
+        fields
+    }
+
+    fn record(transaction: &Transaction, posting_count: usize) -> Vec<String> {
+        let status = match transaction.state {
+            TransactionState::Default => "",
+            TransactionState::Cleared => "*",
+            TransactionState::Pending => "!",
+        };
+        let comment = transaction
+            .comment
+            .iter()
+            .cloned()
+            .chain(transaction.tags.iter().map(|t| t.to_string()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut record = vec![
+            transaction.date.format("%Y-%m-%d").to_string(),
+            transaction.code.clone().unwrap_or_default(),
+            status.to_owned(),
+            transaction.payee.clone(),
+            transaction.note.clone().unwrap_or_default(),
+            comment,
+        ];
+        for i in 0..posting_count {
+            match transaction.postings.get(i) {
+                Some(posting) => {
+                    record.push(posting.account.clone());
+                    match &posting.amount {
+                        Some(amount) => {
+                            record.push(amount.amount.to_string());
+                            record.push(amount.commodity.clone());
+                        }
+                        None => record.extend([String::new(), String::new()]),
+                    }
+                }
+                None => record.extend([String::new(), String::new(), String::new()]),
+            }
+        }
+        record
+    }
+}
+
+/// path of the rules file belonging to the given CSV file
+pub fn rules_path(file: &Path) -> PathBuf {
+    let mut rules_file = file.as_os_str().to_owned();
+    rules_file.push(".rules");
+    PathBuf::from(rules_file)
+}
+
+fn csv_line(values: &[String]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|v| {
+            if v.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", v.replace('"', "\"\""))
+            } else {
+                v.clone()
+            }
+        })
+        .collect();
+    format!("{}\n", values.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, Posting, Tag};
+
+    use super::*;
+
+    #[test]
+    fn rules_reference_csv_columns() {
+        let posting = |account: &str, amount: Option<i32>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| AmountAndCommodity::new(BigDecimal::from(a), "EUR".to_owned())),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let transactions = vec![
+            Transaction {
+                date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+                code: Some("ABC123".to_owned()),
+                payee: "Store, Inc".to_owned(),
+                note: Some("Groceries".to_owned()),
+                state: TransactionState::Cleared,
+                comment: None,
+                tags: vec![Tag::new_val(
+                    "valuation".to_owned(),
+                    "2024-03-02".to_owned(),
+                )],
+                postings: vec![
+                    posting("Assets:Bank", Some(-20)),
+                    posting("Expenses:Groceries", None),
+                ],
+            },
+            Transaction {
+                date: NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(),
+                code: None,
+                payee: "Split".to_owned(),
+                note: None,
+                state: TransactionState::Pending,
+                comment: None,
+                tags: vec![],
+                postings: vec![
+                    posting("Assets:Bank", Some(-30)),
+                    posting("Expenses:Food", Some(10)),
+                    posting("Expenses:Household", None),
+                ],
+            },
+        ];
+
+        let export = RulesExport::new(&transactions);
+
+        let mut lines = export.csv.lines();
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header.len(), 6 + 3 * 3);
+        assert_eq!(
+            lines.next().unwrap(),
+            "2024-03-01,ABC123,*,\"Store, Inc\",Groceries,valuation: 2024-03-02,Assets:Bank,-20,EUR,Expenses:Groceries,,,,,"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "2024-03-05,,!,Split,,,Assets:Bank,-30,EUR,Expenses:Food,10,EUR,Expenses:Household,,"
+        );
+        assert!(lines.next().is_none());
+
+        let fields_line = export
+            .rules
+            .lines()
+            .find(|l| l.starts_with("fields "))
+            .unwrap();
+        assert_eq!(fields_line, format!("fields {}", header.join(", ")));
+        // all columns referenced by the rules exist in the CSV file
+        for reference in export
+            .rules
+            .split(|c: char| c.is_whitespace())
+            .filter_map(|w| w.strip_prefix('%'))
+            .filter(|w| !w.starts_with(['Y', 'm', 'd']))
+        {
+            assert!(header.contains(&reference), "{}", reference);
+        }
+
+        assert_eq!(
+            rules_path(Path::new("/tmp/export.csv")),
+            PathBuf::from("/tmp/export.csv.rules")
+        );
+    }
+}
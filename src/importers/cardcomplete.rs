@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use bigdecimal::BigDecimal;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveTime};
 use fast_xml::de::from_reader;
 use fast_xml::DeError;
 use serde::Deserialize;
@@ -9,6 +9,7 @@ use serde::Deserialize;
 use crate::config::ImporterConfig;
 use crate::error::*;
 use crate::hledger::output::{AmountAndCommodity, Posting, Tag, Transaction, TransactionState};
+use crate::input::is_empty_input;
 use crate::HledgerImporter;
 
 pub struct CardcompleteXmlImporter {}
@@ -32,21 +33,23 @@ impl HledgerImporter for CardcompleteXmlImporter {
         config: &crate::config::ImporterConfig,
         _known_codes: &std::collections::HashSet<String>,
     ) -> Result<Vec<Transaction>> {
-        let file = match std::fs::File::open(input_file) {
-            Ok(file) => file,
+        let content = match std::fs::read(input_file) {
+            Ok(content) => content,
             Err(_) => return Err(ImportError::InputFileRead(input_file.to_owned())),
         };
+        if is_empty_input(input_file, &content) {
+            return Ok(Vec::new());
+        }
 
-        let reader = std::io::BufReader::new(file);
-        let read_result: std::result::Result<CCDocument, DeError> = from_reader(reader);
+        let read_result: std::result::Result<CCDocument, DeError> = from_reader(content.as_slice());
         match read_result {
             Ok(doc) => {
                 let mut result = doc
-                    .transactions
+                    .into_transactions()
                     .into_iter()
                     .map(|t| t.into_hledger(config))
                     .collect::<Result<Vec<_>>>()?;
-                result.sort_by(|a, b| a.date.partial_cmp(&b.date).unwrap());
+                sort_transactions(&mut result);
                 Ok(result)
             }
             Err(e) => Err(ImportError::InputParse(e.to_string())),
@@ -56,15 +59,59 @@ impl HledgerImporter for CardcompleteXmlImporter {
     fn output_title(&self) -> &'static str {
         "cardcomplete import"
     }
+
+    fn slug(&self) -> &'static str {
+        "cardcomplete"
+    }
+}
+
+/// Sorts the transactions by date, then by date and time of the card payment (`valuation` and `time` tags),
+/// transactions without (valid) time come first and ties keep the order of the export.
+fn sort_transactions(transactions: &mut [Transaction]) {
+    let tag = |t: &Transaction, name: &str| {
+        t.tags
+            .iter()
+            .find(|tag| tag.name == name)
+            .and_then(|tag| tag.value.clone())
+    };
+    transactions.sort_by_key(|t| {
+        let time = tag(t, "time").and_then(|value| {
+            NaiveTime::parse_from_str(&value, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(&value, "%H:%M"))
+                .ok()
+        });
+        (t.date, tag(t, "valuation"), time)
+    });
 }
 
-/// XML root node in Cardcomplete XML export
+/// XML root node in Cardcomplete XML export.
+/// The transactions are either direct children of the root node or wrapped in `TRANSACTIONS`
+/// elements, depending on the export. Namespace prefixes of elements are ignored by the parser.
 #[derive(Debug, Deserialize)]
 struct CCDocument {
-    #[serde(rename = "TRANSACTION")]
+    #[serde(rename = "TRANSACTION", default)]
+    pub transactions: Vec<CCTransaction>,
+    #[serde(rename = "TRANSACTIONS", default)]
+    pub containers: Vec<CCTransactions>,
+}
+
+/// `TRANSACTIONS` container element of some Cardcomplete exports
+#[derive(Debug, Deserialize)]
+struct CCTransactions {
+    #[serde(rename = "TRANSACTION", default)]
     pub transactions: Vec<CCTransaction>,
 }
 
+impl CCDocument {
+    fn into_transactions(self) -> Vec<CCTransaction> {
+        let mut transactions = self.transactions;
+        for container in self.containers {
+            transactions.extend(container.transactions);
+        }
+        transactions
+    }
+}
+
 /// XML representation of Cardcomplete transaction export
 #[derive(Debug, Deserialize, Default)]
 struct CCTransaction {
@@ -106,30 +153,45 @@ impl CCTransaction {
 
         let posting_date = self.posting_date()?;
         let tags = self.tags()?;
-        let state = self.state();
+        let state = config.state_rule(&self.state).unwrap_or(self.state());
 
-        let own_target = config.identify_card_opt(&self.card_number);
+        let own_target = config
+            .identify_card_opt(&self.card_number)
+            .or(config.missing_asset());
+        let has_asset_posting = own_target.is_some();
         if let Some(own_target) = own_target {
             note.clone_from(&own_target.note);
+            let mut amount = self.amount()?;
+            if amount.commodity.trim().is_empty() {
+                if let Some(commodity) = config.card_commodity_opt(&self.card_number) {
+                    amount.commodity = commodity;
+                }
+            }
             postings.push(Posting {
                 account: own_target.account,
-                amount: Some(self.amount()?),
+                amount: Some(amount),
                 comment: None,
-                tags: Vec::new(),
+                tags: self.posting_tags(config),
+                price: None,
+                balance_assertion: None,
             });
         }
 
         let other_target = config
-            .match_mapping(&self.merchant_name)?
+            .match_cash_withdrawal(&self.category)?
+            .or(config.match_mapping(&self.merchant_name)?)
             .or(config.match_category(&self.category))
             .or(config.fallback());
-        if let Some(other_target) = other_target {
+        // the offset posting alone would not balance, such transactions are reported as empty transactions
+        if let (true, Some(other_target)) = (has_asset_posting, other_target) {
             note.clone_from(&other_target.note);
             postings.push(Posting {
                 account: other_target.account,
                 amount: None,
                 comment: None,
                 tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
             });
         }
 
@@ -180,19 +242,32 @@ impl CCTransaction {
         Ok(tags)
     }
 
-    pub fn amount(&self) -> Result<AmountAndCommodity> {
-        let parts = self.amount.split(',');
-        let parts_lengths: Vec<usize> = parts.into_iter().map(|p| p.len()).collect();
-        let decimal_len = if parts_lengths.len() > 1 {
-            parts_lengths[1]
+    pub fn posting_tags(&self, config: &ImporterConfig) -> Vec<Tag> {
+        if config.output.posting_time_tag && !self.time.is_empty() {
+            vec![Tag::new_val("time".to_owned(), self.time.clone())]
         } else {
-            0_usize
+            Vec::new()
+        }
+    }
+
+    pub fn amount(&self) -> Result<AmountAndCommodity> {
+        let amount = self.amount.trim();
+
+        // the decimal separator is usually a comma (German localization), but some exports use a period.
+        // If both occur, the last one is the decimal separator and the other one separates thousands.
+        let decimal_separator = match (amount.rfind(','), amount.rfind('.')) {
+            (Some(comma), Some(period)) if period > comma => '.',
+            (None, Some(_)) if amount.matches('.').count() == 1 => '.',
+            _ => ',',
         };
+        let thousands_separator = if decimal_separator == ',' { '.' } else { ',' };
 
-        let amount_filtered = self.amount.replace(',', "");
+        let amount_filtered = amount
+            .replace(thousands_separator, "")
+            .replace(decimal_separator, ".");
 
         let big_dec = match BigDecimal::from_str(&amount_filtered) {
-            Ok(b) => b / ((10_u32).pow(decimal_len as u32)),
+            Ok(b) => b,
             Err(e) => return Err(ImportError::InputParse(e.to_string())),
         };
 
@@ -232,10 +307,52 @@ mod tests {
 
     use super::*;
 
+    /// transaction of the export, wrapped in the given elements
+    fn cc_document(prefix: &str, suffix: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+{}<TRANSACTION>
+  <HAENLDERNAME-MERCHANT_NAME>Store</HAENLDERNAME-MERCHANT_NAME>
+  <BETRAG-AMOUNT>-3,70</BETRAG-AMOUNT>
+  <WAEHRUNG-CURRENCY>EUR</WAEHRUNG-CURRENCY>
+  <DATUM-DATE>24.12.2023</DATUM-DATE>
+  <ZEIT-TIME>18:31</ZEIT-TIME>
+  <BRANCHE-CATEGORY>Groceries</BRANCHE-CATEGORY>
+  <STATUS-STATUS>Verbucht</STATUS-STATUS>
+  <BUCHUNGSDATUM-POSTING_DATE>27.12.2023</BUCHUNGSDATUM-POSTING_DATE>
+</TRANSACTION>{}",
+            prefix, suffix
+        )
+    }
+
+    #[test]
+    fn document_structure_variants() {
+        for (prefix, suffix) in [
+            ("<TRANSACTIONS_EXPORT>", "</TRANSACTIONS_EXPORT>"),
+            (
+                "<EXPORT><HEADER>card complete</HEADER><TRANSACTIONS>",
+                "</TRANSACTIONS></EXPORT>",
+            ),
+            (
+                "<cc:EXPORT xmlns:cc=\"urn:cardcomplete\"><cc:TRANSACTIONS>",
+                "</cc:TRANSACTIONS></cc:EXPORT>",
+            ),
+        ] {
+            let xml = cc_document(prefix, suffix);
+            let doc: CCDocument = fast_xml::de::from_str(&xml).expect("XML parsing failed");
+            let transactions = doc.into_transactions();
+            assert_eq!(transactions.len(), 1, "{}", xml);
+            assert_eq!(transactions[0].merchant_name, "Store");
+            assert_eq!(transactions[0].posting_date, "27.12.2023");
+        }
+    }
+
     #[test]
     fn convert_date() {
-        let mut t = CCTransaction::default();
-        t.date = "25.12.2023".to_owned();
+        let t = CCTransaction {
+            date: "25.12.2023".to_owned(),
+            ..Default::default()
+        };
 
         let expected = NaiveDate::from_ymd_opt(2023, 12, 25).unwrap();
         let result = t.date().expect("Date parsing failed");
@@ -245,8 +362,10 @@ mod tests {
 
     #[test]
     fn convert_posting_date() {
-        let mut t = CCTransaction::default();
-        t.posting_date = "01.02.2020".to_owned();
+        let t = CCTransaction {
+            posting_date: "01.02.2020".to_owned(),
+            ..Default::default()
+        };
 
         let expected = NaiveDate::from_ymd_opt(2020, 2, 1).unwrap();
         let result = t.posting_date().expect("Date parsing failed");
@@ -256,22 +375,28 @@ mod tests {
 
     #[test]
     fn transaction_state() {
-        let mut t = CCTransaction::default();
-        t.state = "Verbucht".to_owned();
+        let t = CCTransaction {
+            state: "Verbucht".to_owned(),
+            ..Default::default()
+        };
 
         assert_eq!(TransactionState::Cleared, t.state());
 
-        t = CCTransaction::default();
-        t.state = "".to_owned();
+        let t = CCTransaction {
+            state: "".to_owned(),
+            ..Default::default()
+        };
 
         assert_eq!(TransactionState::Pending, t.state());
     }
 
     #[test]
     fn amount_and_commodity() {
-        let mut t = CCTransaction::default();
-        t.amount = "-3,70".to_owned();
-        t.currency = "EUR".to_owned();
+        let t = CCTransaction {
+            amount: "-3,70".to_owned(),
+            currency: "EUR".to_owned(),
+            ..Default::default()
+        };
 
         let expected = AmountAndCommodity {
             amount: BigDecimal::from_i32(-370).unwrap() / 100,
@@ -280,9 +405,11 @@ mod tests {
 
         assert_eq!(t.amount().unwrap(), expected);
 
-        t = CCTransaction::default();
-        t.amount = "350".to_owned();
-        t.currency = "USD".to_owned();
+        let t = CCTransaction {
+            amount: "350".to_owned(),
+            currency: "USD".to_owned(),
+            ..Default::default()
+        };
 
         let expected = AmountAndCommodity {
             amount: BigDecimal::from_i32(350).unwrap(),
@@ -291,9 +418,316 @@ mod tests {
 
         assert_eq!(t.amount().unwrap(), expected);
 
-        t = CCTransaction::default();
-        t.amount = "fail".to_owned();
+        let t = CCTransaction {
+            amount: "fail".to_owned(),
+            ..Default::default()
+        };
 
         assert!(t.amount().is_err());
     }
+
+    #[test]
+    fn amount_decimal_separators() {
+        let amount = |value: &str| {
+            CCTransaction {
+                amount: value.to_owned(),
+                currency: "EUR".to_owned(),
+                ..Default::default()
+            }
+            .amount()
+            .unwrap()
+            .amount
+        };
+
+        let expected = BigDecimal::from_i32(-370).unwrap() / 100;
+        assert_eq!(amount("-3,70"), expected);
+        assert_eq!(amount("-3.70"), expected);
+
+        let expected = BigDecimal::from_i32(123456).unwrap() / 100;
+        assert_eq!(amount("1.234,56"), expected);
+        assert_eq!(amount("1,234.56"), expected);
+        assert_eq!(amount("1234,56"), expected);
+
+        let expected = BigDecimal::from_i32(1234567).unwrap();
+        assert_eq!(amount("1.234.567"), expected);
+    }
+
+    #[test]
+    fn time_as_posting_tag() {
+        let config_str = "ibans = []
+        cards = [ { card = \"1234XXXX5678\", account = \"Liabilities:Card\" } ]
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unassigned\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [output]
+        posting_time_tag = true
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let t = CCTransaction {
+            merchant_name: "Store".to_owned(),
+            amount: "-3,70".to_owned(),
+            currency: "EUR".to_owned(),
+            date: "24.12.2023".to_owned(),
+            time: "18:31".to_owned(),
+            posting_date: "27.12.2023".to_owned(),
+            card_number: Some("1234XXXX5678".to_owned()),
+            ..Default::default()
+        };
+
+        let result = t.into_hledger(&config).unwrap();
+        assert_eq!(result.postings[0].account, "Liabilities:Card");
+        assert_eq!(
+            result.postings[0].tags,
+            vec![Tag::new_val("time".to_owned(), "18:31".to_owned())]
+        );
+        assert!(result.postings[1].tags.is_empty());
+        assert!(result.tags.iter().any(|t| t.name == "time"));
+
+        config.output.posting_time_tag = false;
+        let t = CCTransaction {
+            amount: "-3,70".to_owned(),
+            date: "24.12.2023".to_owned(),
+            time: "18:31".to_owned(),
+            posting_date: "27.12.2023".to_owned(),
+            card_number: Some("1234XXXX5678".to_owned()),
+            ..Default::default()
+        };
+        let result = t.into_hledger(&config).unwrap();
+        assert!(result.postings[0].tags.is_empty());
+    }
+
+    #[test]
+    fn configured_state_rules() {
+        let config_str = "ibans = []
+        cards = [ { card = \"1234XXXX5678\", account = \"Liabilities:Card\" } ]
+        mapping = []
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [state_rules]
+        verbucht = \"pending\"
+        Storniert = \"default\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let t = |state: &str| CCTransaction {
+            merchant_name: "Store".to_owned(),
+            amount: "-3,70".to_owned(),
+            currency: "EUR".to_owned(),
+            date: "24.12.2023".to_owned(),
+            posting_date: "27.12.2023".to_owned(),
+            state: state.to_owned(),
+            card_number: Some("1234XXXX5678".to_owned()),
+            ..Default::default()
+        };
+
+        let result = t("Verbucht").into_hledger(&config).unwrap();
+        assert_eq!(result.state, TransactionState::Pending);
+        let result = t("Storniert").into_hledger(&config).unwrap();
+        assert_eq!(result.state, TransactionState::Default);
+        let result = t("Offen").into_hledger(&config).unwrap();
+        assert_eq!(result.state, TransactionState::Pending);
+    }
+
+    #[test]
+    fn toggle_date_basis() {
+        let config_str = "ibans = []
+        cards = [ { card = \"1234XXXX5678\", account = \"Liabilities:Card\" } ]
+        mapping = []
+        creditor_and_debitor_mapping = []
+        date_basis = \"booking\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [importer_date_basis]
+        cardcomplete = \"value\"
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+        let t = || CCTransaction {
+            merchant_name: "Store".to_owned(),
+            amount: "-3,70".to_owned(),
+            currency: "EUR".to_owned(),
+            date: "24.12.2023".to_owned(),
+            posting_date: "27.12.2023".to_owned(),
+            card_number: Some("1234XXXX5678".to_owned()),
+            ..Default::default()
+        };
+
+        let mut result = t().into_hledger(&config).unwrap();
+        result.apply_date_basis(config.date_basis("cardcomplete"));
+        assert_eq!(result.date, NaiveDate::from_ymd_opt(2023, 12, 24).unwrap());
+        assert!(result
+            .tags
+            .iter()
+            .any(|t| t.to_string() == "booking: 2023-12-27"));
+        assert!(!result.tags.iter().any(|t| t.name == "valuation"));
+
+        config.importer_date_basis.clear();
+        let mut result = t().into_hledger(&config).unwrap();
+        result.apply_date_basis(config.date_basis("cardcomplete"));
+        assert_eq!(result.date, NaiveDate::from_ymd_opt(2023, 12, 27).unwrap());
+        assert!(result
+            .tags
+            .iter()
+            .any(|t| t.to_string() == "valuation: 2023-12-24"));
+    }
+
+    #[test]
+    fn card_commodity_for_rows_without_currency() {
+        let config_str = "ibans = []
+        cards = [ { card = \"1234XXXX5678\", account = \"Liabilities:Card\", commodity = \"EUR\" } ]
+        mapping = []
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let t = |currency: &str| CCTransaction {
+            merchant_name: "Store".to_owned(),
+            amount: "-3,70".to_owned(),
+            currency: currency.to_owned(),
+            date: "24.12.2023".to_owned(),
+            posting_date: "27.12.2023".to_owned(),
+            card_number: Some("1234XXXX5678".to_owned()),
+            ..Default::default()
+        };
+
+        let result = t("").into_hledger(&config).unwrap();
+        assert_eq!(
+            result.postings[0].amount,
+            Some(AmountAndCommodity::new(
+                BigDecimal::from_str("-3.70").unwrap(),
+                "EUR".to_owned()
+            ))
+        );
+
+        let result = t("USD").into_hledger(&config).unwrap();
+        assert_eq!(result.postings[0].amount.as_ref().unwrap().commodity, "USD");
+    }
+
+    #[test]
+    fn same_day_ordered_by_time() {
+        let config_str = "ibans = []
+        cards = [ { card = \"1234XXXX5678\", account = \"Liabilities:Card\" } ]
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unassigned\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let t = |merchant: &str, date: &str, time: &str| {
+            CCTransaction {
+                merchant_name: merchant.to_owned(),
+                amount: "-3,70".to_owned(),
+                currency: "EUR".to_owned(),
+                date: date.to_owned(),
+                time: time.to_owned(),
+                posting_date: "27.12.2023".to_owned(),
+                card_number: Some("1234XXXX5678".to_owned()),
+                ..Default::default()
+            }
+            .into_hledger(&config)
+            .unwrap()
+        };
+
+        let mut transactions = vec![
+            t("Evening", "24.12.2023", "18:31"),
+            t("Next day", "25.12.2023", "07:00"),
+            t("Day before", "23.12.2023", "20:00"),
+            t("Morning", "24.12.2023", "9:05"),
+            t("Unknown time", "24.12.2023", ""),
+        ];
+        sort_transactions(&mut transactions);
+        let payees: Vec<_> = transactions.iter().map(|t| t.payee.as_str()).collect();
+        assert_eq!(
+            payees,
+            [
+                "Day before",
+                "Unknown time",
+                "Morning",
+                "Evening",
+                "Next day"
+            ]
+        );
+    }
+
+    #[test]
+    fn cash_advance_to_cash_account() {
+        let config_str = "ibans = []
+        cards = [ { card = \"1234XXXX5678\", account = \"Liabilities:Card\" } ]
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unassigned\"
+        cash_withdrawals = [\"bargeld|cash advance\"]
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let t = |category: &str| {
+            CCTransaction {
+                merchant_name: "Bank Austria".to_owned(),
+                amount: "-100,00".to_owned(),
+                currency: "EUR".to_owned(),
+                date: "24.12.2023".to_owned(),
+                posting_date: "27.12.2023".to_owned(),
+                category: category.to_owned(),
+                card_number: Some("1234XXXX5678".to_owned()),
+                ..Default::default()
+            }
+            .into_hledger(&config)
+            .unwrap()
+        };
+
+        let result = t("Cash Advance");
+        assert_eq!(&result.postings[0].account, "Liabilities:Card");
+        assert_eq!(&result.postings[1].account, "Assets:Cash");
+
+        let result = t("Groceries");
+        assert_eq!(&result.postings[1].account, "Expenses:Unassigned");
+    }
 }
@@ -0,0 +1,287 @@
+use std::io::Read;
+
+use encoding_rs::Encoding;
+use serde::de::DeserializeOwned;
+
+use crate::error::{ImportError, Result};
+use crate::hledger::output::Tag;
+use crate::warnings::UnparsedRows;
+
+/// Reads the input file.
+/// If an encoding (e.g. `windows-1252`) is given, the content is transcoded to UTF-8.
+pub fn read_input(input_file: &std::path::Path, encoding: &Option<String>) -> Result<Vec<u8>> {
+    let content = std::fs::read(input_file)
+        .map_err(|_| ImportError::InputFileRead(input_file.to_path_buf()))?;
+
+    match encoding {
+        Some(label) => {
+            let encoding = Encoding::for_label(label.trim().as_bytes())
+                .ok_or(ImportError::UnknownEncoding(label.clone()))?;
+            let (decoded, _, _) = encoding.decode(&content);
+            Ok(decoded.into_owned().into_bytes())
+        }
+        None => Ok(content),
+    }
+}
+
+/// Skips the given number of lines at the beginning of the input (e.g. account information before the CSV header).
+pub fn skip_lines(content: &[u8], lines: usize) -> &[u8] {
+    let mut rest = content;
+    for _ in 0..lines {
+        match rest.iter().position(|b| *b == b'\n') {
+            Some(end) => rest = &rest[end + 1..],
+            None => return &[],
+        }
+    }
+    rest
+}
+
+/// Deserializes all records of a CSV file.
+/// Every record is returned together with its row (the line number within the file),
+/// so that errors can point the user to the malformed line.
+pub fn deserialize_records<R: Read, T: DeserializeOwned>(
+    reader: &mut csv::Reader<R>,
+) -> Result<Vec<(u64, T)>> {
+    deserialize_records_with_trailer(reader, |_| false)
+}
+
+/// Deserializes all records of a CSV file like [`deserialize_records`],
+/// but skips the last record if it is a trailer (e.g. a summary line with totals).
+pub fn deserialize_records_with_trailer<R: Read, T: DeserializeOwned>(
+    reader: &mut csv::Reader<R>,
+    is_trailer: impl Fn(&csv::StringRecord) -> bool,
+) -> Result<Vec<(u64, T)>> {
+    Ok(
+        deserialize_raw_records(reader, is_trailer, &mut UnparsedRows::default())?
+            .into_iter()
+            .map(|(row, value, _)| (row, value))
+            .collect(),
+    )
+}
+
+/// Deserializes all records of a CSV file like [`deserialize_records_with_trailer`],
+/// every record is returned together with its row and its `raw` tag (see [`raw_tag`]).
+/// Malformed records are skipped if `unparsed` collects them.
+pub fn deserialize_raw_records<R: Read, T: DeserializeOwned>(
+    reader: &mut csv::Reader<R>,
+    is_trailer: impl Fn(&csv::StringRecord) -> bool,
+    unparsed: &mut UnparsedRows,
+) -> Result<Vec<(u64, T, Tag)>> {
+    let headers = reader
+        .headers()
+        .map_err(|e| ImportError::InputParse(e.to_string()))?
+        .clone();
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let row = match &record {
+            Ok(record) => record.position(),
+            Err(e) => e.position(),
+        }
+        .map(|p| p.line())
+        .unwrap_or_default();
+        if let Some(record) = unparsed.check(row, None, record.map_err(csv_error))? {
+            records.push(record);
+        }
+    }
+    if records.last().is_some_and(is_trailer) {
+        if let Some(trailer) = records.pop() {
+            log::info!(
+                "skipping trailing line {}: {}",
+                trailer.position().map(|p| p.line()).unwrap_or_default(),
+                trailer.iter().collect::<Vec<_>>().join(";")
+            );
+        }
+    }
+
+    if records.is_empty() {
+        log::warn!("input file contains no records (only the header), no transactions imported");
+    }
+
+    let mut result = Vec::new();
+    for record in records {
+        let row = record.position().map(|p| p.line()).unwrap_or_default();
+        let raw = raw_tag(&record);
+        let value = record
+            .deserialize::<T>(Some(&headers))
+            .map_err(|e| at_row(row, csv_error(e)));
+        if let Some(value) = unparsed.check(row, Some(&raw), value)? {
+            result.push((row, value, raw));
+        }
+    }
+    Ok(result)
+}
+
+/// Compact representation of a CSV record as `raw` tag: the trimmed fields separated by `|`.
+/// hledger ends tag values at a comma or line break, so these are replaced by spaces.
+pub fn raw_tag(record: &csv::StringRecord) -> Tag {
+    let value = record
+        .iter()
+        .map(|field| {
+            field
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("|");
+    Tag::new_val("raw".to_owned(), value)
+}
+
+/// Compares the columns of the CSV file with the expected columns.
+/// Unless `allow_additional` is set, columns that are not expected are rejected as well.
+pub fn check_columns<R: Read>(
+    reader: &mut csv::Reader<R>,
+    expected: &[&str],
+    allow_additional: bool,
+) -> Result<()> {
+    let headers = reader.headers().map_err(csv_error)?;
+    let headers: Vec<&str> = headers.iter().map(str::trim).collect();
+
+    let missing: Vec<&str> = expected
+        .iter()
+        .filter(|c| !headers.contains(c))
+        .copied()
+        .collect();
+    let unexpected: Vec<&str> = if allow_additional {
+        Vec::new()
+    } else {
+        headers
+            .iter()
+            .filter(|c| !expected.contains(c))
+            .copied()
+            .collect()
+    };
+
+    if missing.is_empty() && unexpected.is_empty() {
+        Ok(())
+    } else {
+        Err(ImportError::InputParse(format!(
+            "unexpected CSV columns (missing: [{}], unexpected: [{}])",
+            missing.join(", "),
+            unexpected.join(", ")
+        )))
+    }
+}
+
+/// adds the row of the input file to parse errors
+pub fn at_row(row: u64, error: ImportError) -> ImportError {
+    match error {
+        ImportError::InputParse(msg) => ImportError::InputParse(format!("row {}: {}", row, msg)),
+        e => e,
+    }
+}
+
+fn csv_error(error: csv::Error) -> ImportError {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => ImportError::InputParse(err.to_string()),
+        _ => match error.position() {
+            Some(pos) => at_row(pos.line(), ImportError::InputParse(error.to_string())),
+            None => ImportError::InputParse(error.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Row {
+        #[serde(rename = "Name")]
+        pub name: String,
+        #[serde(rename = "Amount")]
+        pub amount: i32,
+    }
+
+    #[test]
+    fn report_malformed_row() {
+        let csv = "Name,Amount\nfirst,1\nsecond,2\nthird,three\n";
+        let mut reader = csv::ReaderBuilder::new().from_reader(csv.as_bytes());
+        let result = deserialize_records::<_, Row>(&mut reader);
+        match result {
+            Err(ImportError::InputParse(msg)) => assert!(msg.starts_with("row 4: "), "{}", msg),
+            _ => panic!("expected a parse error"),
+        }
+
+        let csv = "Name,Amount\nfirst,1\nsecond,2\n";
+        let mut reader = csv::ReaderBuilder::new().from_reader(csv.as_bytes());
+        let result = deserialize_records::<_, Row>(&mut reader).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].0, 3);
+        assert_eq!(&result[1].1.name, "second");
+        assert_eq!(result[1].1.amount, 2);
+    }
+
+    #[test]
+    fn header_only() {
+        let mut reader = csv::ReaderBuilder::new().from_reader("Name,Amount\n".as_bytes());
+        let result = deserialize_records::<_, Row>(&mut reader).unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn collect_malformed_rows() {
+        let csv = "Name,Amount\nfirst,1\nsecond,two\nthird,3\n";
+        let mut reader = csv::ReaderBuilder::new().from_reader(csv.as_bytes());
+        let mut unparsed = UnparsedRows::collecting();
+        let result =
+            deserialize_raw_records::<_, Row>(&mut reader, |_| false, &mut unparsed).unwrap();
+        let names: Vec<&str> = result.iter().map(|(_, r, _)| r.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "third"]);
+
+        assert_eq!(unparsed.warnings.len(), 1);
+        let warning = &unparsed.warnings[0];
+        assert_eq!(warning.index, 3);
+        assert_eq!(warning.fields["raw"], "second|two");
+        assert!(
+            warning.fields["error"].contains("row 3: "),
+            "{}",
+            warning.fields["error"]
+        );
+    }
+
+    #[test]
+    fn decode_windows_1252() {
+        let path = std::env::temp_dir().join("hledger-import-decode-windows-1252.csv");
+        // "é" and an em-dash encoded in windows-1252
+        let content = b"Name;Text\nCaf\xe9;a \x97 b\n";
+        std::fs::write(&path, content).unwrap();
+
+        let result = read_input(&path, &Some("windows-1252".to_owned())).unwrap();
+        assert_eq!(
+            String::from_utf8(result).unwrap(),
+            "Name;Text\nCaf\u{e9};a \u{2014} b\n"
+        );
+
+        let result = read_input(&path, &Some("latin1".to_owned())).unwrap();
+        assert!(String::from_utf8(result).unwrap().contains('\u{2014}'));
+
+        let result = read_input(&path, &None).unwrap();
+        assert_eq!(result, content);
+
+        let result = read_input(&path, &Some("no-such-charset".to_owned()));
+        assert!(result.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn raw_record_as_tag() {
+        let csv = "Name,Text\n\"Coffee, Tea\",\"first line\nsecond  line \"\n";
+        let mut reader = csv::ReaderBuilder::new().from_reader(csv.as_bytes());
+        let result = deserialize_raw_records::<_, (String, String)>(
+            &mut reader,
+            |_| false,
+            &mut UnparsedRows::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            result[0].2.to_string(),
+            "raw: Coffee Tea|first line second line"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime};
+
+use crate::config::TargetTimezone;
+use crate::error::{ImportError, Result};
+use crate::hledger::output::safe_date_prefix;
+
+/// timestamp formats (with UTC offset) used by the supported bank exports
+const OFFSET_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"];
+
+/// timestamp formats without UTC offset used by the supported bank exports
+const NAIVE_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+/// Parses an ISO 8601 timestamp (e.g. `2024-06-03T23:30:00.000+0200`) or a plain date (`2024-06-03`).
+/// If the timestamp carries a UTC offset, the date is derived in the given target timezone.
+/// Timestamps without offset and plain dates are taken as they are.
+pub fn parse_iso_date(value: &str, timezone: &TargetTimezone) -> Result<NaiveDate> {
+    let value = value.trim();
+
+    for format in OFFSET_FORMATS {
+        if let Ok(date_time) = DateTime::parse_from_str(value, format) {
+            return Ok(local_date(&date_time, timezone));
+        }
+    }
+
+    for format in NAIVE_FORMATS {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(date_time.date());
+        }
+    }
+
+    match safe_date_prefix(value) {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| ImportError::InputParse(format!("invalid date \"{}\": {}", value, e))),
+        None => Err(ImportError::InputParse(format!(
+            "invalid date \"{}\"",
+            value
+        ))),
+    }
+}
+
+fn local_date(date_time: &DateTime<FixedOffset>, timezone: &TargetTimezone) -> NaiveDate {
+    local_date_time(date_time, timezone).date()
+}
+
+/// The date and time of a timestamp in the given target timezone.
+pub fn local_date_time(
+    date_time: &DateTime<FixedOffset>,
+    timezone: &TargetTimezone,
+) -> NaiveDateTime {
+    match timezone {
+        TargetTimezone::Source => date_time.naive_local(),
+        TargetTimezone::Local => date_time.with_timezone(&Local).naive_local(),
+        TargetTimezone::Fixed(offset) => date_time.with_timezone(offset).naive_local(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn late_evening_timestamp() {
+        let value = "2024-06-03T23:30:00.000+0200";
+
+        let result = parse_iso_date(value, &TargetTimezone::Source).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+
+        let utc = TargetTimezone::Fixed(FixedOffset::from_str("+00:00").unwrap());
+        let result = parse_iso_date(value, &utc).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+
+        let vienna = TargetTimezone::Fixed(FixedOffset::from_str("+02:00").unwrap());
+        let result = parse_iso_date("2024-06-03T22:30:00.000+0000", &vienna).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 6, 4).unwrap());
+
+        let new_york = TargetTimezone::Fixed(FixedOffset::from_str("-04:00").unwrap());
+        let result = parse_iso_date(value, &new_york).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+
+        let result = parse_iso_date("2024-06-04T01:30:00+02:00", &new_york).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 6, 3).unwrap());
+    }
+
+    #[test]
+    fn timestamps_without_offset() {
+        let utc = TargetTimezone::Fixed(FixedOffset::from_str("+00:00").unwrap());
+
+        let result = parse_iso_date("2024-05-01 23:46:56", &utc).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 5, 1).unwrap());
+
+        let result = parse_iso_date("2024-05-01", &utc).unwrap();
+        assert_eq!(result, NaiveDate::from_ymd_opt(2024, 5, 1).unwrap());
+
+        assert!(parse_iso_date("2024-05", &utc).is_err());
+        assert!(parse_iso_date("202", &utc).is_err());
+        assert!(parse_iso_date("ä2024-05-01", &utc).is_err());
+        assert!(parse_iso_date("€€€€€€€€€€€€", &utc).is_err());
+        assert!(parse_iso_date("01.05.2024", &utc).is_err());
+    }
+}
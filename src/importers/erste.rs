@@ -1,17 +1,20 @@
 use std::collections::HashSet;
 
 use bigdecimal::BigDecimal;
-use bigdecimal::FromPrimitive;
+use bigdecimal::{FromPrimitive, Signed, Zero};
 use chrono::Days;
 use chrono::NaiveDate;
 use serde::Deserialize;
 
 use crate::config::ImporterConfig;
 use crate::config::ImporterConfigTarget;
+use crate::config::TargetTimezone;
 use crate::error::ImportError;
 use crate::error::Result;
 use crate::hledger::output::*;
 use crate::hledger::query::query_hledger_by_payee_and_account;
+use crate::importers::dates::parse_iso_date;
+use crate::input::is_empty_input;
 use crate::HledgerImporter;
 
 pub struct HledgerErsteJsonImporter {}
@@ -36,9 +39,11 @@ impl HledgerImporter for HledgerErsteJsonImporter {
         known_codes: &HashSet<String>,
     ) -> Result<Vec<Transaction>> {
         match std::fs::read_to_string(input_file) {
-            Ok(content) => match serde_json::from_str::<Vec<ErsteTransaction>>(&content) {
-                Ok(transactions) => {
-                    let result = transactions
+            Ok(content) if is_empty_input(input_file, content.as_bytes()) => Ok(Vec::new()),
+            Ok(content) => match serde_json::from_str::<ErsteDocument>(&content) {
+                Ok(document) => {
+                    let result = document
+                        .transactions()
                         .into_iter()
                         .filter(|t| !known_codes.contains(&t.reference_number))
                         .map(|t| t.into_hledger(config))
@@ -54,6 +59,31 @@ impl HledgerImporter for HledgerErsteJsonImporter {
     fn output_title(&self) -> &'static str {
         "Erste import"
     }
+
+    fn slug(&self) -> &'static str {
+        "erste"
+    }
+}
+
+/// Erste exports either contain a bare array of transactions or wrap the array in an object
+/// (e.g. together with pagination metadata)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErsteDocument {
+    Transactions(Vec<ErsteTransaction>),
+    Envelope {
+        #[serde(alias = "transactions")]
+        collection: Vec<ErsteTransaction>,
+    },
+}
+
+impl ErsteDocument {
+    fn transactions(self) -> Vec<ErsteTransaction> {
+        match self {
+            ErsteDocument::Transactions(transactions) => transactions,
+            ErsteDocument::Envelope { collection } => collection,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -68,12 +98,22 @@ struct ErsteTransaction {
     pub partner_account: Option<ErstePartnerAccount>,
     // pub partner_reference: Option<String>,
     pub amount: ErsteAmount,
+    /// original amount of foreign currency transactions (e.g. card purchases abroad)
+    pub amount_sender: Option<ErsteAmount>,
     pub note: Option<String>,
-    // pub card_number: Option<String>,
+    pub card_number: Option<String>,
+    /// e.g. a card payment or a transfer
+    pub payment_method: Option<String>,
+    pub card_type: Option<String>,
     // pub virtual_card_number: Option<String>,
     // pub virtual_card_device_name: Option<String>,
     pub sepa_mandate_id: Option<String>,
     pub sepa_creditor_id: Option<String>,
+    pub sepa_purpose_type: Option<String>,
+    /// reference of the loan repaid by the transaction
+    pub loan_reference: Option<String>,
+    /// name of the standing order that created the transaction
+    pub instruction_name: Option<String>,
     pub owner_account_number: Option<String>,
     // pub owner_account_title: Option<String>,
 }
@@ -81,21 +121,44 @@ struct ErsteTransaction {
 impl ErsteTransaction {
     fn into_hledger(self, config: &ImporterConfig) -> Result<Transaction> {
         let mut postings = Vec::new();
-        let mut note = None;
-        let date = self.booking_date()?;
-        let tags = self.tags();
+        let mut notes = Vec::new();
+        let date = self.booking_date(&config.timezone)?;
+        let mut tags = self.tags(&config.timezone);
+        // the original amount is kept as tag, unless it is booked as priced conversion
+        if !config.prefer_original_currency {
+            if let Some(original) = self.original_amount()? {
+                tags.push(Tag::new_val(
+                    "amount_sender".to_owned(),
+                    original.to_string(),
+                ));
+            }
+        }
 
-        let own_target = config
-            .identify_iban_opt(&self.owner_account_number)
-            .or(config.identify_card("Erste"));
+        // credit card statements carry the (masked) card number instead of the owner account,
+        // card payments are booked on the card before the account the card belongs to
+        let own_target = if self.is_card_payment() {
+            config
+                .identify_card_opt(&self.card_number)
+                .or(config.identify_iban_opt(&self.owner_account_number))
+        } else {
+            config
+                .identify_iban_opt(&self.owner_account_number)
+                .or(config.identify_card_opt(&self.card_number))
+        }
+        .or(config.identify_card("Erste"))
+        .or(config.missing_asset());
+        let has_asset_posting = own_target.is_some();
 
         if let Some(own_target) = own_target {
-            note = own_target.note;
+            notes.push(own_target.note);
+            let (amount, price) = self.own_amount(config)?;
             postings.push(Posting {
                 account: own_target.account,
-                amount: Some(self.amount.clone().try_into()?),
+                amount: Some(amount),
                 comment: None,
                 tags: Vec::new(),
+                price,
+                balance_assertion: None,
             });
         }
 
@@ -110,23 +173,41 @@ impl ErsteTransaction {
                 amount: None,
                 comment: None,
                 tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
             });
         } else {
+            let mapping_payee = self
+                .partner_name
+                .as_deref()
+                .or(self.reference.as_deref())
+                .unwrap_or_default();
+            let mapping_note = self.note.as_deref().unwrap_or_default();
             let other_target = config
-                .match_sepa_mandate_opt(&self.sepa_mandate_id)
-                .or(config.match_sepa_creditor_opt(&self.sepa_creditor_id))
+                .match_loan_reference_opt(&self.loan_reference)
+                .or(config.match_standing_order_opt(&self.instruction_name))
+                .or(config.match_sepa_mandate_opt(&self.sepa_mandate_id)?)
+                .or(config.match_sepa_creditor_opt(&self.sepa_creditor_id)?)
+                .or(self
+                    .partner_account
+                    .as_ref()
+                    .and_then(|p| config.match_partner_iban_opt(&p.iban)))
                 .or(self.match_creditor_debitor_mapping(config)?)
-                .or(config.match_mapping_opt(&self.partner_name)?)
-                .or(config.match_mapping_opt(&self.reference)?)
+                .or(config.match_sepa_purpose_opt(&self.sepa_purpose_type))
+                .or(config.match_mapping_opt(&self.partner_name, mapping_payee, mapping_note)?)
+                .or(config.match_mapping_opt(&self.reference, mapping_payee, mapping_note)?)
+                .or(config.match_mapping_opt(&self.note, mapping_payee, mapping_note)?)
                 .or(config.fallback());
 
             if let Some(other_target) = other_target {
-                note.clone_from(&other_target.note);
+                notes.push(other_target.note.clone());
                 postings.push(Posting {
                     account: other_target.account.clone(),
                     amount: None,
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 });
             }
         }
@@ -142,8 +223,12 @@ impl ErsteTransaction {
             }
         });
 
-        if let Some(trx_note) = &self.note {
-            note = Some(trx_note.clone());
+        notes.push(self.note);
+        let note = config.output.join_notes(notes);
+
+        // the offset posting alone would not balance, such transactions are reported as empty transactions
+        if !has_asset_posting {
+            postings.clear();
         }
 
         Ok(Transaction {
@@ -158,13 +243,61 @@ impl ErsteTransaction {
         })
     }
 
-    fn tags(&self) -> Vec<Tag> {
+    /// `paymentMethod` or `cardType` mark the transaction as card payment
+    fn is_card_payment(&self) -> bool {
+        let is_card_method = self
+            .payment_method
+            .as_deref()
+            .is_some_and(|method| method.to_lowercase().contains("card"));
+        let has_card_type = self
+            .card_type
+            .as_deref()
+            .is_some_and(|card_type| !card_type.is_empty());
+        is_card_method || has_card_type
+    }
+
+    /// The booked amount, or the original amount priced with the booked amount
+    /// if `prefer_original_currency` is set and the transaction was made in another currency.
+    fn own_amount(
+        &self,
+        config: &ImporterConfig,
+    ) -> Result<(AmountAndCommodity, Option<AmountAndCommodity>)> {
+        let booked: AmountAndCommodity = self.amount.clone().try_into()?;
+        let original = match self.original_amount()? {
+            Some(original) if config.prefer_original_currency => original,
+            _ => return Ok((booked, None)),
+        };
+        let price = AmountAndCommodity::new(booked.amount.abs(), booked.commodity);
+        Ok((original, Some(price)))
+    }
+
+    /// The original amount (`amountSender`) of transactions made in another currency than the booked amount.
+    fn original_amount(&self) -> Result<Option<AmountAndCommodity>> {
+        let booked: AmountAndCommodity = self.amount.clone().try_into()?;
+        let original: AmountAndCommodity = match &self.amount_sender {
+            Some(original) => original.clone().try_into()?,
+            None => return Ok(None),
+        };
+        if original.commodity.is_empty()
+            || original.commodity == booked.commodity
+            || original.amount.is_zero()
+        {
+            return Ok(None);
+        }
+
+        // the sign of the original amount is not reliable, the booked amount tells the direction
+        Ok(Some(AmountAndCommodity::new(
+            original.amount.abs() * booked.amount.signum(),
+            original.commodity,
+        )))
+    }
+
+    fn tags(&self, timezone: &TargetTimezone) -> Vec<Tag> {
         let mut tags = Vec::new();
-        let valuation = &self.valuation;
-        if valuation.len() >= 10 {
+        if let Ok(valuation) = parse_iso_date(&self.valuation, timezone) {
             tags.push(Tag {
                 name: "valuation".to_owned(),
-                value: Some(valuation[..10].to_owned()),
+                value: Some(valuation.format("%Y-%m-%d").to_string()),
             });
         }
         if let Some(reference) = &self.reference {
@@ -209,21 +342,45 @@ impl ErsteTransaction {
                 })
             }
         }
+        if let Some(loan_reference) = &self.loan_reference {
+            if !loan_reference.is_empty() {
+                tags.push(Tag {
+                    name: "loan_reference".to_owned(),
+                    value: Some(loan_reference.clone()),
+                })
+            }
+        }
+        if let Some(instruction_name) = &self.instruction_name {
+            if !instruction_name.is_empty() {
+                tags.push(Tag {
+                    name: "standing_order".to_owned(),
+                    value: Some(instruction_name.clone()),
+                })
+            }
+        }
+        if let Some(payment_method) = &self.payment_method {
+            if !payment_method.is_empty() {
+                tags.push(Tag {
+                    name: "payment_method".to_owned(),
+                    value: Some(payment_method.clone()),
+                })
+            }
+        }
+        if let Some(sepa_purpose) = &self.sepa_purpose_type {
+            if !sepa_purpose.is_empty() {
+                tags.push(Tag {
+                    name: "sepa_purpose".to_owned(),
+                    value: Some(sepa_purpose.clone()),
+                })
+            }
+        }
         tags
     }
 
-    fn booking_date(&self) -> Result<NaiveDate> {
-        if self.booking.len() >= 10 {
-            match NaiveDate::parse_from_str(&self.booking[..10], "%Y-%m-%d") {
-                Ok(date) => Ok(date),
-                Err(e) => Err(ImportError::InputParse(e.to_string())),
-            }
-        } else {
-            Err(ImportError::InputParse(format!(
-                "invalid booking date \"{}\"",
-                &self.booking
-            )))
-        }
+    fn booking_date(&self, timezone: &TargetTimezone) -> Result<NaiveDate> {
+        parse_iso_date(&self.booking, timezone).map_err(|_| {
+            ImportError::InputParse(format!("invalid booking date \"{}\"", &self.booking))
+        })
     }
 
     fn match_creditor_debitor_mapping(
@@ -241,13 +398,13 @@ impl ErsteTransaction {
 
                     let begin = match rule.days_difference {
                         Some(delta) => self
-                            .booking_date()?
+                            .booking_date(&config.timezone)?
                             .checked_sub_days(Days::new(delta as u64)),
                         None => None,
                     };
                     let end = match rule.days_difference {
                         Some(delta) => self
-                            .booking_date()?
+                            .booking_date(&config.timezone)?
                             .checked_add_days(Days::new(delta as u64 + 1)),
                         None => None,
                     };
@@ -315,7 +472,7 @@ impl TryFrom<ErsteAmount> for AmountAndCommodity {
         let amount = BigDecimal::from_i64(value.value);
         match amount {
             Some(amount) => Ok(Self {
-                amount: amount / ((10_i64).pow(value.precision)),
+                amount: shift_decimal_point(amount, value.precision as i64),
                 commodity: value.currency,
             }),
             None => Err(ImportError::NumerConversion(value.value.to_string())),
@@ -432,7 +589,7 @@ mod tests {
 
         assert_eq!(
             transaction
-                .booking_date()
+                .booking_date(&TargetTimezone::default())
                 .expect("Booking date should be valid but was not parsed correctly"),
             NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
         );
@@ -545,7 +702,9 @@ mod tests {
         // assert_eq!(&transaction.partner_reference, &None);
 
         assert_eq!(
-            transaction.booking_date().unwrap(),
+            transaction
+                .booking_date(&TargetTimezone::default())
+                .unwrap(),
             NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
         );
         assert_eq!(&transaction.valuation[..10], "2024-06-01");
@@ -682,4 +841,530 @@ mod tests {
 
         assert_eq!(expected, transaction.amount.try_into().unwrap());
     }
+
+    #[test]
+    fn deserialize_document_shapes() {
+        let transaction = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Test Partner\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": -1500,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  }
+}";
+
+        let json_str = format!("[{}]", transaction);
+        let document =
+            serde_json::from_str::<ErsteDocument>(&json_str).expect("JSON parsing failed");
+        let transactions = document.transactions();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            &transactions[0].reference_number,
+            "123456789000XXX-00XXXXXXXXXX"
+        );
+
+        let json_str = format!(
+            "{{ \"pageNumber\": 0, \"pageCount\": 1, \"collection\": [{}, {}] }}",
+            transaction, transaction
+        );
+        let document =
+            serde_json::from_str::<ErsteDocument>(&json_str).expect("JSON parsing failed");
+        assert_eq!(document.transactions().len(), 2);
+
+        let json_str = format!("{{ \"transactions\": [{}] }}", transaction);
+        let document =
+            serde_json::from_str::<ErsteDocument>(&json_str).expect("JSON parsing failed");
+        assert_eq!(document.transactions().len(), 1);
+
+        let json_str = "{ \"something\": [] }";
+        assert!(serde_json::from_str::<ErsteDocument>(json_str).is_err());
+    }
+
+    #[test]
+    fn sepa_purpose_mapping() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = []
+        mapping = [{ search = \"Employer\", account = \"Income:Other\" }]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [sepa_purpose]
+        SALA = \"Income:Salary\"
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Employer Inc\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": 250000,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"sepaPurposeType\": \"SALA\",
+  \"ownerAccountNumber\": \"AT483200000012345864\"
+}";
+        let transaction = serde_json::from_str::<ErsteTransaction>(json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+
+        assert_eq!(transaction.postings.len(), 2);
+        assert_eq!(&transaction.postings[0].account, "Assets:Erste");
+        assert_eq!(&transaction.postings[1].account, "Income:Salary");
+        assert!(transaction
+            .tags
+            .iter()
+            .any(|t| t.to_string() == "sepa_purpose: SALA"));
+
+        // other purpose codes fall through to the generic mapping
+        let json_str = json_str.replace("SALA", "RENT");
+        let transaction = serde_json::from_str::<ErsteTransaction>(&json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+        assert_eq!(&transaction.postings[1].account, "Income:Other");
+    }
+
+    #[test]
+    fn accumulate_notes() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\", note = \"Checking\" }]
+        cards = []
+        mapping = [{ search = \"Grocery\", account = \"Expenses:Groceries\" }]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Grocery Store\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": -1500,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"note\": \"birthday cake\",
+  \"ownerAccountNumber\": \"AT483200000012345864\"
+}";
+        let parse = |config: &ImporterConfig| {
+            serde_json::from_str::<ErsteTransaction>(json_str)
+                .expect("JSON parsing failed")
+                .into_hledger(config)
+                .unwrap()
+        };
+
+        assert_eq!(
+            parse(&config).note,
+            Some("Checking, birthday cake".to_owned())
+        );
+
+        config.output.note_separator = Some(" / ".to_owned());
+        assert_eq!(
+            parse(&config).note,
+            Some("Checking / birthday cake".to_owned())
+        );
+    }
+
+    #[test]
+    fn partner_iban_mapping() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = []
+        mapping = [{ search = \"Rent\", account = \"Expenses:Other\" }]
+        iban_mapping = [{ iban = \"AT61 1904 3002 3457 3201\", account = \"Expenses:Rent\" }]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Landlord\",
+  \"partnerAccount\": { \"iban\": \"AT611904300234573201\" },
+  \"reference\": \"Rent June\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": -90000,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"ownerAccountNumber\": \"AT483200000012345864\"
+}";
+        let transaction = serde_json::from_str::<ErsteTransaction>(json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+        assert_eq!(&transaction.postings[1].account, "Expenses:Rent");
+
+        // without a matching partner IBAN the text mapping applies
+        let json_str = json_str.replace("AT611904300234573201", "AT022050302101023600");
+        let transaction = serde_json::from_str::<ErsteTransaction>(&json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+        assert_eq!(&transaction.postings[1].account, "Expenses:Other");
+    }
+
+    #[test]
+    fn note_mapping() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = []
+        mapping = [{ search = \"Gym membership\", account = \"Expenses:Sports\" }]
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unknown\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"FIT GmbH\",
+  \"reference\": \"000123\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"note\": \"Gym membership June\",
+  \"amount\": {
+    \"value\": -3990,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"ownerAccountNumber\": \"AT483200000012345864\"
+}";
+        let transaction = serde_json::from_str::<ErsteTransaction>(json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+        assert_eq!(&transaction.postings[1].account, "Expenses:Sports");
+        assert_eq!(transaction.note, Some("Gym membership June".to_owned()));
+    }
+
+    #[test]
+    fn credit_card_statement() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = [
+          { card = \"Erste\", account = \"Liabilities:Erste Card\" },
+          { card = \"4000 XXXX XXXX 1234\", account = \"Liabilities:VISA\" },
+        ]
+        mapping = [{ search = \"Grocery\", account = \"Expenses:Groceries\" }]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Grocery Store\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": -1500,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"cardNumber\": \"4000 XXXX XXXX 1234\",
+  \"ownerAccountNumber\": null
+}";
+        let transaction = serde_json::from_str::<ErsteTransaction>(json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+        assert_eq!(&transaction.postings[0].account, "Liabilities:VISA");
+        assert_eq!(&transaction.postings[1].account, "Expenses:Groceries");
+
+        // unknown cards fall back to the generic Erste card
+        let json_str = json_str.replace("4000 XXXX XXXX 1234", "5000 XXXX XXXX 9999");
+        let transaction = serde_json::from_str::<ErsteTransaction>(&json_str)
+            .expect("JSON parsing failed")
+            .into_hledger(&config)
+            .unwrap();
+        assert_eq!(&transaction.postings[0].account, "Liabilities:Erste Card");
+    }
+
+    #[test]
+    fn prefer_original_currency() {
+        let config_str = "ibans = []
+        cards = [{ card = \"Erste\", account = \"Liabilities:Erste Card\" }]
+        mapping = [{ search = \"Diner\", account = \"Expenses:Restaurants\" }]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Diner New York\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": -1850,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"amountSender\": {
+    \"value\": 2000,
+    \"precision\": 2,
+    \"currency\": \"USD\"
+  },
+  \"cardNumber\": \"4000 XXXX XXXX 1234\"
+}";
+        let import = |config: &ImporterConfig| {
+            serde_json::from_str::<ErsteTransaction>(json_str)
+                .expect("JSON parsing failed")
+                .into_hledger(config)
+                .unwrap()
+        };
+
+        let transaction = import(&config);
+        assert_eq!(
+            transaction.postings[0].to_string(),
+            "    Liabilities:Erste Card     -18.50 EUR"
+        );
+        let amount_sender = transaction
+            .tags
+            .iter()
+            .find(|t| t.name == "amount_sender")
+            .unwrap();
+        assert_eq!(amount_sender.value.as_deref(), Some("-20.00 USD"));
+
+        config.prefer_original_currency = true;
+        let transaction = import(&config);
+        assert_eq!(
+            transaction.postings[0].to_string(),
+            "    Liabilities:Erste Card     -20.00 USD @@ 18.50 EUR"
+        );
+        assert_eq!(&transaction.postings[1].account, "Expenses:Restaurants");
+        assert!(!transaction.tags.iter().any(|t| t.name == "amount_sender"));
+        assert_eq!(
+            transaction.residual().get("EUR"),
+            Some(&"-18.50".parse::<BigDecimal>().unwrap())
+        );
+        assert!(!transaction.residual().contains_key("USD"));
+    }
+
+    #[test]
+    fn unmapped_owner_account() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = []
+        mapping = [{ search = \"Grocery\", account = \"Expenses:Groceries\" }]
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unknown\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = "{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Grocery Store\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {
+    \"value\": -1500,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  },
+  \"ownerAccountNumber\": \"AT000000000000000000\"
+}";
+        let import = |config: &ImporterConfig| {
+            serde_json::from_str::<ErsteTransaction>(json_str)
+                .expect("JSON parsing failed")
+                .into_hledger(config)
+                .unwrap()
+        };
+
+        let transaction = import(&config);
+        assert!(transaction.postings.is_empty());
+        let result = crate::validation::check_empty_postings(
+            vec![transaction],
+            crate::config::EmptyPostingsHandling::Error,
+        );
+        assert!(matches!(result, Err(ImportError::EmptyPostings(_))));
+
+        config.missing_asset_account = Some("Assets:Unmapped".to_owned());
+        let transaction = import(&config);
+        assert_eq!(&transaction.postings[0].account, "Assets:Unmapped");
+        assert_eq!(&transaction.postings[1].account, "Expenses:Groceries");
+    }
+
+    #[test]
+    fn loan_and_standing_order_mapping() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = []
+        mapping = [{ search = \"Erste Bank\", account = \"Expenses:Bank\" }]
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unknown\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [loan_mapping]
+        \"LN-4711\" = \"Liabilities:Mortgage\"
+
+        [standing_order_mapping]
+        \"Rent\" = \"Expenses:Rent\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = |extra: &str| {
+            format!(
+                "{{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-03T00:00:00.000+0200\",
+  \"partnerName\": \"Erste Bank\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {{
+    \"value\": -85000,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  }},
+  {}
+  \"ownerAccountNumber\": \"AT483200000012345864\"
+}}",
+                extra
+            )
+        };
+        let import = |json: &str| {
+            serde_json::from_str::<ErsteTransaction>(json)
+                .expect("JSON parsing failed")
+                .into_hledger(&config)
+                .unwrap()
+        };
+
+        let transaction = import(&json_str("\"loanReference\": \"LN-4711\","));
+        assert_eq!(&transaction.postings[0].account, "Assets:Erste");
+        assert_eq!(&transaction.postings[1].account, "Liabilities:Mortgage");
+        assert!(transaction
+            .tags
+            .iter()
+            .any(|t| t.to_string() == "loan_reference: LN-4711"));
+
+        let transaction = import(&json_str("\"instructionName\": \"Rent\","));
+        assert_eq!(&transaction.postings[1].account, "Expenses:Rent");
+        assert!(transaction
+            .tags
+            .iter()
+            .any(|t| t.to_string() == "standing_order: Rent"));
+
+        // unknown references fall back to the generic mapping
+        let transaction = import(&json_str("\"loanReference\": \"LN-0815\","));
+        assert_eq!(&transaction.postings[1].account, "Expenses:Bank");
+    }
+
+    #[test]
+    fn payment_method_card_routing() {
+        let config_str = "ibans = [{ iban = \"AT483200000012345864\", account = \"Assets:Erste\" }]
+        cards = [{ card = \"4000 XXXX XXXX 1234\", account = \"Liabilities:Debit Card\" }]
+        mapping = [{ search = \"Grocery\", account = \"Expenses:Groceries\" }]
+        creditor_and_debitor_mapping = []
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let json_str = |payment_method: &str| {
+            format!(
+                "{{
+  \"booking\": \"2024-06-03T00:00:00.000+0200\",
+  \"valuation\": \"2024-06-01T00:00:00.000+0200\",
+  \"partnerName\": \"Grocery Store\",
+  \"referenceNumber\": \"123456789000XXX-00XXXXXXXXXX\",
+  \"amount\": {{
+    \"value\": -1500,
+    \"precision\": 2,
+    \"currency\": \"EUR\"
+  }},
+  \"cardNumber\": \"4000 XXXX XXXX 1234\",
+  \"paymentMethod\": \"{}\",
+  \"cardType\": null,
+  \"ownerAccountNumber\": \"AT483200000012345864\"
+}}",
+                payment_method
+            )
+        };
+        let import = |json: &str| {
+            serde_json::from_str::<ErsteTransaction>(json)
+                .expect("JSON parsing failed")
+                .into_hledger(&config)
+                .unwrap()
+        };
+
+        let transaction = import(&json_str("CARD"));
+        assert_eq!(&transaction.postings[0].account, "Liabilities:Debit Card");
+        assert_eq!(&transaction.postings[1].account, "Expenses:Groceries");
+        assert!(transaction
+            .tags
+            .iter()
+            .any(|t| t.to_string() == "payment_method: CARD"));
+
+        // transfers are booked on the owner account
+        let transaction = import(&json_str("TRANSFER"));
+        assert_eq!(&transaction.postings[0].account, "Assets:Erste");
+    }
 }
@@ -6,44 +6,95 @@ use serde::Deserialize;
 
 use crate::config::ImporterConfig;
 use crate::error::*;
+use crate::hledger::output::shift_decimal_point;
 use crate::hledger::output::AmountAndCommodity;
 use crate::hledger::output::Posting;
 use crate::hledger::output::Tag;
 use crate::hledger::output::Transaction;
 use crate::hledger::output::TransactionState;
+use crate::importers::csv_reader::{
+    at_row, check_columns, deserialize_raw_records, read_input, skip_lines,
+};
+use crate::input::is_empty_input;
+use crate::warnings::UnparsedRows;
 use crate::HledgerImporter;
 
+/// columns of the Flatex CSV export (the currency column has no name)
+const COLUMNS: &[&str] = &[
+    "Buchungstag",
+    "Valuta",
+    "Empfänger",
+    "Zahlungspfl.",
+    "TA.Nr.",
+    "Buchungsinformationen",
+    "Betrag",
+    "",
+];
+
 pub struct FlatexCsvImport {}
 
+/// a summary line (e.g. totals) has no valid booking date in the first column
+fn is_summary(record: &csv::StringRecord) -> bool {
+    record
+        .get(0)
+        .is_none_or(|date| FlatexTransaction::parse_date(date.trim()).is_err())
+}
+
 impl HledgerImporter for FlatexCsvImport {
     fn parse(
         &self,
         input_file: &std::path::Path,
         config: &crate::config::ImporterConfig,
         known_codes: &std::collections::HashSet<String>,
+    ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
+        self.parse_rows(
+            input_file,
+            config,
+            known_codes,
+            &mut UnparsedRows::default(),
+        )
+    }
+
+    fn parse_rows(
+        &self,
+        input_file: &std::path::Path,
+        config: &crate::config::ImporterConfig,
+        known_codes: &std::collections::HashSet<String>,
+        unparsed: &mut UnparsedRows,
     ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
         let mut transactions = Vec::new();
+        let content = read_input(input_file, &config.input_encoding)?;
+        if is_empty_input(input_file, &content) {
+            return Ok(transactions);
+        }
+        let skip = config.flatex_csv.as_ref().map_or(0, |c| c.skip_lines);
         let mut reader = csv::ReaderBuilder::new()
             .delimiter(b';')
             .has_headers(true)
             .double_quote(false)
             .flexible(true)
-            .from_path(input_file);
-        match &mut reader {
-            Ok(reader) => {
-                for record in reader.deserialize::<FlatexTransaction>() {
-                    match record {
-                        Ok(record) => {
-                            let hledger_rec = record.into_hledger(config)?;
-                            if !known_codes.contains(&hledger_rec.code.clone().unwrap()) {
-                                transactions.push(hledger_rec);
-                            }
-                        }
-                        Err(e) => return Err(ImportError::InputParse(e.to_string())),
-                    }
-                }
+            .comment(Some(b'#'))
+            .from_reader(skip_lines(&content, skip));
+        if config.strict {
+            check_columns(&mut reader, COLUMNS, false)?;
+        }
+        let skip_summary = config.flatex_csv.as_ref().is_some_and(|c| c.skip_summary);
+        let records = deserialize_raw_records::<_, FlatexTransaction>(
+            &mut reader,
+            |r| skip_summary && is_summary(r),
+            unparsed,
+        )?;
+        for (row, record, raw) in records {
+            let hledger_rec = record.into_hledger(config).map_err(|e| at_row(row, e));
+            let Some(mut hledger_rec) = unparsed.check(row, Some(&raw), hledger_rec)? else {
+                continue;
+            };
+            if config.output.tag_raw_record {
+                hledger_rec.tags.push(raw);
+            }
+            if !known_codes.contains(&hledger_rec.code.clone().unwrap()) {
+                transactions.push(hledger_rec);
             }
-            Err(e) => return Err(ImportError::InputParse(e.to_string())),
         }
         Ok(transactions)
     }
@@ -51,6 +102,18 @@ impl HledgerImporter for FlatexCsvImport {
     fn output_title(&self) -> &'static str {
         "flatex import"
     }
+
+    fn slug(&self) -> &'static str {
+        "flatex"
+    }
+
+    fn commodity_rules(&self, config: &ImporterConfig) -> Vec<String> {
+        config
+            .flatex_csv
+            .as_ref()
+            .map(|c| c.commodity_formatting_rules.clone())
+            .unwrap_or_default()
+    }
 }
 
 impl FlatexCsvImport {
@@ -68,13 +131,22 @@ impl Default for FlatexCsvImport {
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct FlatexCsvConfig {
     pub account: String,
+    /// number of lines before the CSV header that are skipped (e.g. account information), lines starting with `#` are always ignored
+    #[serde(default)]
+    pub skip_lines: usize,
+    /// skip a trailing summary line (a last row without valid booking date), e.g. with totals
+    #[serde(default)]
+    pub skip_summary: bool,
+    /// additional commodity formatting rules (`-c` of `hledger print`) for this importer
+    #[serde(default)]
+    pub commodity_formatting_rules: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct FlatexTransaction {
     #[serde(rename = "Buchungstag")]
     pub posting_date: String,
-    #[serde(rename = "Valuta")]
+    #[serde(rename = "Valuta", default)]
     pub valuation_date: String,
     #[serde(rename = "Empfänger")]
     pub recipient_name: String,
@@ -93,7 +165,7 @@ struct FlatexTransaction {
 impl FlatexTransaction {
     pub fn into_hledger(self, config: &ImporterConfig) -> Result<Transaction> {
         let date = self.posting_date()?;
-        let tags = self.tags()?;
+        let tags = self.tags();
         let postings = self.postings(config)?;
         let note = if !self.posting_text.is_empty() {
             Some(self.posting_text)
@@ -128,6 +200,8 @@ impl FlatexTransaction {
             amount: Some(amount),
             comment: None,
             tags: Vec::new(),
+            price: None,
+            balance_assertion: None,
         });
 
         let bank_transfer = self
@@ -138,8 +212,14 @@ impl FlatexTransaction {
         let other_account = if bank_transfer {
             Some(config.transfer_accounts.bank.clone())
         } else {
-            config
-                .match_mapping(&self.posting_text)?
+            self.recipient_bank_account
+                .split('/')
+                .find_map(|iban| config.match_partner_iban(iban))
+                .or(config.match_mapping_fields(
+                    &self.posting_text,
+                    &self.recipient_name,
+                    &self.posting_text,
+                )?)
                 .map(|rule| rule.account.clone())
                 .or(config.fallback().map(|fallback| fallback.account.clone()))
         };
@@ -150,26 +230,34 @@ impl FlatexTransaction {
                 amount: None,
                 comment: None,
                 tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
             });
         }
 
         Ok(postings)
     }
 
-    pub fn tags(&self) -> Result<Vec<Tag>> {
-        let valuation = self.valuation_date()?;
-        let valuation = valuation.format("%Y-%m-%d").to_string();
-
-        Ok(vec![
-            Tag {
+    /// the valuation tag is omitted, if the valuation date is missing or invalid
+    pub fn tags(&self) -> Vec<Tag> {
+        let mut tags = Vec::new();
+        match self.valuation_date() {
+            Ok(valuation) => tags.push(Tag {
                 name: "valuation".to_owned(),
-                value: Some(valuation),
-            },
-            Tag {
-                name: "partner_iban".to_owned(),
-                value: Some(self.recipient_bank_account.clone()),
-            },
-        ])
+                value: Some(valuation.format("%Y-%m-%d").to_string()),
+            }),
+            Err(_) if self.valuation_date.trim().is_empty() => {}
+            Err(e) => log::warn!(
+                "skipping valuation of transaction {}: {}",
+                &self.transaction_nr,
+                e
+            ),
+        }
+        tags.push(Tag {
+            name: "partner_iban".to_owned(),
+            value: Some(self.recipient_bank_account.clone()),
+        });
+        tags
     }
 
     pub fn amount(&self) -> Result<AmountAndCommodity> {
@@ -182,7 +270,7 @@ impl FlatexTransaction {
         };
 
         let amount = match BigDecimal::from_str(&amount.replace(',', "")) {
-            Ok(big_dec) => big_dec / ((10_u32).pow(decimals as u32)),
+            Ok(big_dec) => shift_decimal_point(big_dec, decimals as i64),
             Err(e) => return Err(ImportError::InputParse(e.to_string())),
         };
 
@@ -207,3 +295,88 @@ impl FlatexTransaction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::importers::csv_reader::{deserialize_records, deserialize_records_with_trailer};
+
+    use super::*;
+
+    #[test]
+    fn missing_valuation() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Equity:Unassigned\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [flatex_csv]
+        account = \"Assets:Flatex\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let csv = "Buchungstag;Valuta;Empfänger;Zahlungspfl.;TA.Nr.;Buchungsinformationen;Betrag;
+03.06.2024;04.06.2024;Someone;AT000000000000000000;TA1;Transfer;1.000,50;EUR
+05.06.2024;;Someone;AT000000000000000000;TA2;Transfer;-20,00;EUR
+";
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .double_quote(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+        let transactions: Vec<Transaction> =
+            deserialize_records::<_, FlatexTransaction>(&mut reader)
+                .unwrap()
+                .into_iter()
+                .map(|(_, t)| t.into_hledger(&config).unwrap())
+                .collect();
+
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(
+            transactions[0].tags[0],
+            Tag::new_val("valuation".to_owned(), "2024-06-04".to_owned())
+        );
+        assert!(!transactions[1].tags.iter().any(|t| t.name == "valuation"));
+        assert_eq!(
+            transactions[1].postings[0].amount,
+            Some(AmountAndCommodity::new(
+                BigDecimal::from_str("-20").unwrap(),
+                "EUR".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn trailing_summary_line() {
+        let csv = "Buchungstag;Valuta;Empfänger;Zahlungspfl.;TA.Nr.;Buchungsinformationen;Betrag;
+03.06.2024;04.06.2024;Someone;AT000000000000000000;TA1;Transfer;1.000,50;EUR
+05.06.2024;05.06.2024;Someone;AT000000000000000000;TA2;Transfer;-20,00;EUR
+Summe;;;;;;980,50
+";
+        let reader = || {
+            csv::ReaderBuilder::new()
+                .delimiter(b';')
+                .double_quote(false)
+                .flexible(true)
+                .from_reader(csv.as_bytes())
+        };
+
+        let result = deserialize_records::<_, FlatexTransaction>(&mut reader());
+        assert!(result.is_err());
+
+        let records =
+            deserialize_records_with_trailer::<_, FlatexTransaction>(&mut reader(), is_summary)
+                .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].0, 3);
+        assert_eq!(&records[1].1.transaction_nr, "TA2");
+    }
+}
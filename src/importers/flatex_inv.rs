@@ -1,8 +1,4 @@
-use std::{
-    fs::File,
-    io::{BufReader, Read},
-    str::FromStr,
-};
+use std::str::FromStr;
 
 use bigdecimal::{BigDecimal, Zero};
 use chrono::NaiveDate;
@@ -12,7 +8,8 @@ use serde::Deserialize;
 
 use crate::{config::ImporterConfig, error::*, hledger::output::Transaction};
 use crate::{
-    hledger::output::{AmountAndCommodity, Posting, TransactionState},
+    hledger::output::{shift_decimal_point, AmountAndCommodity, Posting, TransactionState},
+    input::is_empty_input,
     HledgerImporter,
 };
 
@@ -37,7 +34,14 @@ impl HledgerImporter for FlatexPdfInvoiceImporter {
         config: &crate::config::ImporterConfig,
         known_codes: &std::collections::HashSet<String>,
     ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
-        let texts = self.extract_text_from_pdf(input_file)?;
+        let pdf_content = match std::fs::read(input_file) {
+            Ok(content) => content,
+            Err(_) => return Err(ImportError::InputFileRead(input_file.to_owned())),
+        };
+        if is_empty_input(input_file, &pdf_content) {
+            return Ok(vec![]);
+        }
+        let texts = self.extract_text_from_pdf(&pdf_content)?;
 
         let transaction = self.try_into_hledger(config, &texts)?;
         let code = transaction.code.as_ref().unwrap();
@@ -52,6 +56,18 @@ impl HledgerImporter for FlatexPdfInvoiceImporter {
     fn output_title(&self) -> &'static str {
         "flatex import"
     }
+
+    fn slug(&self) -> &'static str {
+        "flatex"
+    }
+
+    fn commodity_rules(&self, config: &ImporterConfig) -> Vec<String> {
+        config
+            .flatex_pdf
+            .as_ref()
+            .map(|c| c.commodity_formatting_rules.clone())
+            .unwrap_or_default()
+    }
 }
 
 impl FlatexPdfInvoiceImporter {
@@ -93,6 +109,8 @@ impl FlatexPdfInvoiceImporter {
             amount: Some(total),
             comment: None,
             tags: vec![],
+            price: None,
+            balance_assertion: None,
         });
 
         for posting_rule in &flatex_conf.postings {
@@ -118,6 +136,8 @@ impl FlatexPdfInvoiceImporter {
                 amount: Some(amount),
                 comment: Some(posting_rule.description.clone()),
                 tags: vec![],
+                price: None,
+                balance_assertion: None,
             })
         }
 
@@ -148,12 +168,16 @@ impl FlatexPdfInvoiceImporter {
                 }),
                 comment: None,
                 tags: vec![],
+                price: None,
+                balance_assertion: None,
             });
             postings.push(Posting {
                 account: commodity.conversion_account.clone(),
                 amount: None,
                 comment: None,
                 tags: vec![],
+                price: None,
+                balance_assertion: None,
             });
         }
 
@@ -169,23 +193,10 @@ impl FlatexPdfInvoiceImporter {
         })
     }
 
-    fn extract_text_from_pdf(&self, input_file: &std::path::Path) -> Result<Vec<String>> {
+    fn extract_text_from_pdf(&self, pdf_content: &[u8]) -> Result<Vec<String>> {
         let mut texts: Vec<String> = Vec::new();
 
-        let file = match File::open(input_file) {
-            Ok(f) => f,
-            Err(_) => return Err(ImportError::InputFileRead(input_file.to_owned())),
-        };
-
-        let mut reader = BufReader::new(file);
-        let mut pdf_content = Vec::new();
-
-        match reader.read_to_end(&mut pdf_content) {
-            Ok(_) => {}
-            Err(_) => return Err(ImportError::InputFileRead(input_file.to_owned())),
-        };
-
-        let pdf_doc = Document::load_mem(&pdf_content)?;
+        let pdf_doc = Document::load_mem(pdf_content)?;
         for (_, page_id) in pdf_doc.get_pages() {
             let page_content = pdf_doc.get_page_content(page_id)?;
             let content = Content::decode(&page_content)?;
@@ -288,7 +299,7 @@ impl TryInto<AmountAndCommodity> for FlatexPdfRegexMatcher<'_> {
 
         let number = number.replace(',', "");
         let amount = match BigDecimal::from_str(&number) {
-            Ok(b) => b / ((10_u32).pow(decimal_len as u32)),
+            Ok(b) => shift_decimal_point(b, decimal_len as i64),
             Err(e) => return Err(ImportError::InputParse(e.to_string())),
         };
 
@@ -317,7 +328,7 @@ impl TryInto<BigDecimal> for FlatexPdfRegexMatcher<'_> {
 
         let number = value.replace(',', "");
         match BigDecimal::from_str(&number) {
-            Ok(b) => Ok(b / ((10_u32).pow(decimal_len as u32))),
+            Ok(b) => Ok(shift_decimal_point(b, decimal_len as i64)),
             Err(e) => Err(ImportError::InputParse(e.to_string())),
         }
     }
@@ -337,6 +348,9 @@ pub struct FlatexPdfConfig {
     pub postings: Vec<FlatexPostingConfig>,
     #[serde(default)]
     pub tags: Vec<FlatexTagConfig>,
+    /// additional commodity formatting rules (`-c` of `hledger print`) for this importer
+    #[serde(default)]
+    pub commodity_formatting_rules: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
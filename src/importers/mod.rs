@@ -1,3 +1,11 @@
+/// shared parsing of dates and timestamps in bank exports
+#[cfg(any(feature = "erste", feature = "revolut"))]
+pub mod dates;
+
+/// shared reading of CSV based bank exports
+#[cfg(any(feature = "flatex", feature = "revolut", feature = "paypal"))]
+pub mod csv_reader;
+
 /// hledger importer for the Erste Bank JSON files
 #[cfg(feature = "erste")]
 pub mod erste;
@@ -1,20 +1,31 @@
 use std::str::FromStr;
 
-use bigdecimal::{BigDecimal, Zero};
+use bigdecimal::{num_bigint::Sign, BigDecimal, Zero};
 use chrono::NaiveDate;
 
 use regex::Regex;
 use serde::Deserialize;
 
 use crate::{
+    config::OutputConfig,
     error::*,
     hledger::output::{Tag, Transaction},
+    importers::csv_reader::{
+        at_row, check_columns, deserialize_raw_records, read_input, skip_lines,
+    },
+    input::is_empty_input,
+    warnings::UnparsedRows,
 };
 use crate::{
     hledger::output::{AmountAndCommodity, Posting, TransactionState},
     HledgerImporter,
 };
 
+/// columns of the PayPal export used by the importer
+const COLUMNS: &[&str] = &[
+    "Datum", "Uhrzeit", "Zeitzone", "Name", "Typ", "Status", "Währung", "Brutto", "Gebühr", "Netto",
+];
+
 pub struct PaypalPdfImporter {}
 
 impl PaypalPdfImporter {
@@ -31,10 +42,25 @@ impl Default for PaypalPdfImporter {
 
 impl HledgerImporter for PaypalPdfImporter {
     fn parse(
+        &self,
+        input_file: &std::path::Path,
+        config: &crate::config::ImporterConfig,
+        known_codes: &std::collections::HashSet<String>,
+    ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
+        self.parse_rows(
+            input_file,
+            config,
+            known_codes,
+            &mut UnparsedRows::default(),
+        )
+    }
+
+    fn parse_rows(
         &self,
         input_file: &std::path::Path,
         config: &crate::config::ImporterConfig,
         _known_codes: &std::collections::HashSet<String>,
+        unparsed: &mut UnparsedRows,
     ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
         // prepare import configuration
         let paypal_config = match &config.paypal {
@@ -58,27 +84,41 @@ impl HledgerImporter for PaypalPdfImporter {
 
         // read in and parse the paypal transactions
         let mut transactions = Vec::new();
-        let mut reader = csv::ReaderBuilder::new()
-            .delimiter(b'\t')
-            .has_headers(true)
-            .double_quote(true)
-            .flexible(true)
-            .from_path(input_file)
-            .map_err(|e| ImportError::InputParse(e.to_string()))?;
-
-        for record in reader.deserialize::<PayPalTransaction>() {
-            let record = record.map_err(|e| ImportError::InputParse(e.to_string()))?;
+        let content = read_input(input_file, &config.input_encoding)?;
+        if is_empty_input(input_file, &content) {
+            return Ok(transactions);
+        }
+        let mut reader = tsv_reader(skip_lines(&content, paypal_config.skip_lines));
+        if config.strict {
+            // the PayPal export contains many more columns, only the ones used are checked
+            check_columns(&mut reader, COLUMNS, true)?;
+        }
 
+        for (row, record, raw) in
+            deserialize_raw_records::<_, PayPalTransaction>(&mut reader, |_| false, unparsed)?
+        {
             for rule in &rules {
                 if rule.matches(&record) {
                     let ignore = rule.rule.ignore.unwrap_or(false);
                     if !ignore {
                         let transaction = ConfiguredPaypalTransaction {
                             config: paypal_config,
+                            output: &config.output,
+                            state: config
+                                .state_rule(&record.status)
+                                .unwrap_or(TransactionState::Cleared),
                             transaction: &record,
                             rule: rule.rule,
                         };
-                        let transaction: Transaction = transaction.try_into()?;
+                        let transaction: Result<Transaction> =
+                            transaction.try_into().map_err(|e| at_row(row, e));
+                        let Some(mut transaction) = unparsed.check(row, Some(&raw), transaction)?
+                        else {
+                            break;
+                        };
+                        if config.output.tag_raw_record {
+                            transaction.tags.push(raw.clone());
+                        }
                         transactions.push(transaction);
                     }
                     break;
@@ -92,6 +132,38 @@ impl HledgerImporter for PaypalPdfImporter {
     fn output_title(&self) -> &'static str {
         "PayPal import"
     }
+
+    fn slug(&self) -> &'static str {
+        "paypal"
+    }
+
+    fn commodity_rules(&self, config: &crate::config::ImporterConfig) -> Vec<String> {
+        config
+            .paypal
+            .as_ref()
+            .map(|c| c.commodity_formatting_rules.clone())
+            .unwrap_or_default()
+    }
+
+    fn code_prefix(&self, config: &crate::config::ImporterConfig) -> String {
+        config
+            .paypal
+            .as_ref()
+            .and_then(|c| c.code_prefix.clone())
+            .unwrap_or(self.slug().to_owned())
+    }
+}
+
+/// Reader of the tab separated PayPal export. Quoted fields may contain tabs, newlines and
+/// (doubled) quotes, e.g. in names and subjects.
+fn tsv_reader(content: &[u8]) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(true)
+        .double_quote(true)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_reader(content)
 }
 
 #[derive(Debug, Deserialize)]
@@ -120,6 +192,8 @@ struct PayPalTransaction {
 
 struct ConfiguredPaypalTransaction<'a> {
     pub config: &'a PayPalConfig,
+    pub output: &'a OutputConfig,
+    pub state: TransactionState,
     pub rule: &'a PayPalMatchingRule,
     pub transaction: &'a PayPalTransaction,
 }
@@ -128,7 +202,17 @@ struct ConfiguredPaypalTransaction<'a> {
 pub struct PayPalConfig {
     pub asset_account: String,
     pub fees_account: String,
+    /// account for refunded fees (positive fee values), the `fees_account` is used if not set
+    pub fee_rebate_account: Option<String>,
     pub empty_payee: String,
+    /// number of lines before the CSV header that are skipped (e.g. account information), lines starting with `#` are always ignored
+    #[serde(default)]
+    pub skip_lines: usize,
+    /// prefix of generated transaction codes (default: `paypal`)
+    pub code_prefix: Option<String>,
+    /// additional commodity formatting rules (`-c` of `hledger print`) for this importer
+    #[serde(default)]
+    pub commodity_formatting_rules: Vec<String>,
     pub rules: Vec<PayPalMatchingRule>,
 }
 
@@ -140,6 +224,24 @@ pub struct PayPalMatchingRule {
     pub ignore: Option<bool>,
     #[serde[rename = "account"]]
     pub offset_account: Option<String>,
+    /// offset account of incoming payments (e.g. refunds), `account` is used if not set
+    #[serde[rename = "account_positive"]]
+    pub offset_account_positive: Option<String>,
+    /// offset account of outgoing payments, `account` is used if not set
+    #[serde[rename = "account_negative"]]
+    pub offset_account_negative: Option<String>,
+}
+
+impl PayPalMatchingRule {
+    /// the offset account depending on the sign of the gross amount
+    fn offset_account(&self, gross_amount: &BigDecimal) -> Option<&String> {
+        let signed = match gross_amount.sign() {
+            Sign::Plus => self.offset_account_positive.as_ref(),
+            Sign::Minus => self.offset_account_negative.as_ref(),
+            Sign::NoSign => None,
+        };
+        signed.or(self.offset_account.as_ref())
+    }
 }
 
 struct PayPalRegexRuleMatcher<'a> {
@@ -187,8 +289,15 @@ impl TryInto<Transaction> for ConfiguredPaypalTransaction<'_> {
         let date = NaiveDate::parse_from_str(&self.transaction.posting_date, "%d.%m.%Y")
             .map_err(|e| ImportError::InputParse(e.to_string()))?;
 
-        let payee = if !self.transaction.name.trim().is_empty() {
-            self.transaction.name.trim().to_string()
+        // names may span several lines or contain tabs
+        let name = self
+            .transaction
+            .name
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let payee = if !name.is_empty() {
+            name
         } else {
             self.config.empty_payee.to_string()
         };
@@ -197,39 +306,94 @@ impl TryInto<Transaction> for ConfiguredPaypalTransaction<'_> {
             BigDecimal::from_str(&self.transaction.gross_amount.trim().replace(",", "."))
                 .map_err(|e| ImportError::InputParse(e.to_string()))?;
 
+        let offset_account = self.rule.offset_account(&gross_amount);
         let gross_amount = AmountAndCommodity {
             amount: gross_amount,
             commodity: self.transaction.currency.clone(),
         };
 
-        let mut postings = vec![Posting {
-            account: self.config.asset_account.clone(),
-            amount: Some(gross_amount),
-            comment: None,
-            tags: Vec::new(),
-        }];
+        let posting_tags = if self.output.posting_time_tag {
+            vec![Tag::new_val(
+                "time".to_owned(),
+                self.transaction.posting_time.clone(),
+            )]
+        } else {
+            Vec::new()
+        };
 
         let fee_amount = BigDecimal::from_str(&self.transaction.fee.trim().replace(",", "."))
             .map_err(|e| ImportError::InputParse(e.to_string()))?;
+        let fee_comment = if fee_amount > BigDecimal::zero() {
+            "transaction fee rebate"
+        } else {
+            "transaction fee"
+        };
 
-        if !fee_amount.is_zero() {
+        // the fee is added to the asset posting (net amount) and only kept as comment
+        let (asset_amount, asset_comment) = if self.output.collapse_fees && !fee_amount.is_zero() {
+            let comment = format!(
+                "{} {} {}",
+                fee_comment,
+                fee_amount.abs(),
+                &gross_amount.commodity
+            );
+            let net_amount = AmountAndCommodity {
+                amount: &gross_amount.amount + &fee_amount,
+                commodity: gross_amount.commodity,
+            };
+            (net_amount, Some(comment))
+        } else {
+            (gross_amount, None)
+        };
+
+        let mut postings = vec![Posting {
+            account: self.config.asset_account.clone(),
+            amount: Some(asset_amount),
+            comment: asset_comment,
+            tags: posting_tags,
+            price: None,
+            balance_assertion: None,
+        }];
+
+        // PayPal lists charged fees as negative values, positive values are refunds of prior fees
+        if !self.output.collapse_fees && !fee_amount.is_zero() {
+            let account = if fee_amount > BigDecimal::zero() {
+                self.config
+                    .fee_rebate_account
+                    .as_ref()
+                    .unwrap_or(&self.config.fees_account)
+            } else {
+                &self.config.fees_account
+            };
             let fee_amount = AmountAndCommodity {
                 amount: fee_amount,
                 commodity: self.transaction.currency.clone(),
             };
+            // the fee posting can be traced back to the row of the export
             postings.push(Posting {
-                account: self.config.fees_account.clone(),
+                account: account.clone(),
                 amount: Some(fee_amount),
-                comment: Some("transaction fee".to_string()),
-                tags: Vec::new(),
+                comment: Some(fee_comment.to_string()),
+                tags: vec![Tag::new_val(
+                    "valuation".to_owned(),
+                    format!(
+                        "{} {}",
+                        date.format("%Y-%m-%d"),
+                        self.transaction.posting_time.trim()
+                    ),
+                )],
+                price: None,
+                balance_assertion: None,
             });
         }
 
         postings.push(Posting {
-            account: self.rule.offset_account.clone().unwrap_or("".to_string()),
+            account: offset_account.cloned().unwrap_or_default(),
             amount: None,
             comment: None,
             tags: Vec::new(),
+            price: None,
+            balance_assertion: None,
         });
 
         let t = Transaction {
@@ -238,7 +402,7 @@ impl TryInto<Transaction> for ConfiguredPaypalTransaction<'_> {
             payee,
             code: None,
             comment: None,
-            state: TransactionState::Cleared,
+            state: self.state,
             note: Some(self.transaction.transaction_type.clone()),
             tags: vec![
                 Tag {
@@ -262,3 +426,261 @@ impl TryInto<Transaction> for ConfiguredPaypalTransaction<'_> {
         Ok(t)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paypal_transaction(gross_amount: &str, fee: &str) -> PayPalTransaction {
+        PayPalTransaction {
+            posting_date: "03.06.2024".to_owned(),
+            posting_time: "10:15:00".to_owned(),
+            timezone: "Europe/Berlin".to_owned(),
+            name: "Web Shop".to_owned(),
+            transaction_type: "Zahlung".to_owned(),
+            status: "Abgeschlossen".to_owned(),
+            currency: "EUR".to_owned(),
+            gross_amount: gross_amount.to_owned(),
+            fee: fee.to_owned(),
+            net_amount: "".to_owned(),
+        }
+    }
+
+    /// export with a name containing a tab and a newline, followed by a regular row
+    const MULTI_LINE_NAME: &str = "Datum\tUhrzeit\tZeitzone\tName\tTyp\tStatus\tWährung\tBrutto\tGebühr\tNetto
+03.06.2024\t10:15:00\tEurope/Berlin\t\"Web\tShop \"\"Best\"\"\nBerlin\"\tZahlung\tAbgeschlossen\tEUR\t-20,00\t0,00\t-20,00
+04.06.2024\t11:00:00\tEurope/Berlin\tBook Store\tZahlung\tAusstehend\tEUR\t-5,00\t0,00\t-5,00
+";
+
+    #[test]
+    fn quoted_name_with_tab_and_newline() {
+        let records: Vec<PayPalTransaction> = tsv_reader(MULTI_LINE_NAME.as_bytes())
+            .deserialize()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].name, "Web\tShop \"Best\"\nBerlin");
+        assert_eq!(records[0].status, "Abgeschlossen");
+        assert_eq!(records[0].gross_amount, "-20,00");
+        assert_eq!(records[1].name, "Book Store");
+        assert_eq!(records[1].status, "Ausstehend");
+
+        let config = PayPalConfig {
+            asset_account: "Assets:PayPal".to_owned(),
+            fees_account: "Expenses:Fees".to_owned(),
+            fee_rebate_account: None,
+            empty_payee: "PayPal".to_owned(),
+            code_prefix: None,
+            skip_lines: 0,
+            commodity_formatting_rules: vec![],
+            rules: vec![],
+        };
+        let rule = PayPalMatchingRule {
+            name: None,
+            transaction_type: None,
+            ignore: None,
+            offset_account: Some("Expenses:Shopping".to_owned()),
+            offset_account_positive: None,
+            offset_account_negative: None,
+        };
+        let transaction: Transaction = ConfiguredPaypalTransaction {
+            config: &config,
+            output: &OutputConfig::default(),
+            state: TransactionState::Cleared,
+            rule: &rule,
+            transaction: &records[0],
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(transaction.payee, "Web Shop \"Best\" Berlin");
+    }
+
+    #[test]
+    fn fee_and_fee_rebate() {
+        let mut config = PayPalConfig {
+            asset_account: "Assets:PayPal".to_owned(),
+            fees_account: "Expenses:Fees".to_owned(),
+            fee_rebate_account: None,
+            empty_payee: "PayPal".to_owned(),
+            code_prefix: None,
+            skip_lines: 0,
+            commodity_formatting_rules: vec![],
+            rules: vec![],
+        };
+        let rule = PayPalMatchingRule {
+            name: None,
+            transaction_type: None,
+            ignore: None,
+            offset_account: Some("Income:Sales".to_owned()),
+            offset_account_positive: None,
+            offset_account_negative: None,
+        };
+        let output = OutputConfig::default();
+        let convert = |config: &PayPalConfig, transaction: &PayPalTransaction| -> Transaction {
+            ConfiguredPaypalTransaction {
+                config,
+                output: &output,
+                state: TransactionState::Cleared,
+                rule: &rule,
+                transaction,
+            }
+            .try_into()
+            .unwrap()
+        };
+
+        // charged fee
+        let transaction = convert(&config, &paypal_transaction("20,00", "-0,35"));
+        assert_eq!(transaction.postings.len(), 3);
+        assert_eq!(&transaction.postings[1].account, "Expenses:Fees");
+        assert_eq!(
+            transaction.postings[1].amount,
+            Some(AmountAndCommodity::new(
+                BigDecimal::from_str("-0.35").unwrap(),
+                "EUR".to_owned()
+            ))
+        );
+        assert_eq!(
+            transaction.postings[1].comment,
+            Some("transaction fee".to_owned())
+        );
+        assert_eq!(
+            transaction.postings[1].tags[0].to_string(),
+            "valuation: 2024-06-03 10:15:00"
+        );
+        assert!(transaction.postings[0].tags.is_empty());
+
+        // refunded fee without rebate account
+        let rebate = paypal_transaction("-20,00", "0,35");
+        let transaction = convert(&config, &rebate);
+        assert_eq!(&transaction.postings[1].account, "Expenses:Fees");
+        assert_eq!(
+            transaction.postings[1].comment,
+            Some("transaction fee rebate".to_owned())
+        );
+
+        // refunded fee with rebate account
+        config.fee_rebate_account = Some("Income:Fee Rebates".to_owned());
+        let transaction = convert(&config, &rebate);
+        assert_eq!(&transaction.postings[1].account, "Income:Fee Rebates");
+        assert_eq!(
+            transaction.postings[1].amount,
+            Some(AmountAndCommodity::new(
+                BigDecimal::from_str("0.35").unwrap(),
+                "EUR".to_owned()
+            ))
+        );
+
+        // no fee
+        let transaction = convert(&config, &paypal_transaction("20,00", "0,00"));
+        assert_eq!(transaction.postings.len(), 2);
+    }
+
+    #[test]
+    fn collapsed_fees() {
+        let config = PayPalConfig {
+            asset_account: "Assets:PayPal".to_owned(),
+            fees_account: "Expenses:Fees".to_owned(),
+            fee_rebate_account: None,
+            empty_payee: "PayPal".to_owned(),
+            code_prefix: None,
+            skip_lines: 0,
+            commodity_formatting_rules: vec![],
+            rules: vec![],
+        };
+        let rule = PayPalMatchingRule {
+            name: None,
+            transaction_type: None,
+            ignore: None,
+            offset_account: Some("Income:Sales".to_owned()),
+            offset_account_positive: None,
+            offset_account_negative: None,
+        };
+        let convert = |output: &OutputConfig, transaction: &PayPalTransaction| -> Transaction {
+            ConfiguredPaypalTransaction {
+                config: &config,
+                output,
+                state: TransactionState::Cleared,
+                rule: &rule,
+                transaction,
+            }
+            .try_into()
+            .unwrap()
+        };
+        let transaction = paypal_transaction("20,00", "-0,35");
+
+        let split = convert(&OutputConfig::default(), &transaction);
+        assert_eq!(split.postings.len(), 3);
+        assert_eq!(
+            split.postings[0].amount,
+            Some(AmountAndCommodity::new(
+                BigDecimal::from_str("20.00").unwrap(),
+                "EUR".to_owned()
+            ))
+        );
+
+        let output = OutputConfig {
+            collapse_fees: true,
+            ..Default::default()
+        };
+        let collapsed = convert(&output, &transaction);
+        assert_eq!(collapsed.postings.len(), 2);
+        assert_eq!(&collapsed.postings[0].account, "Assets:PayPal");
+        assert_eq!(
+            collapsed.postings[0].amount,
+            Some(AmountAndCommodity::new(
+                BigDecimal::from_str("19.65").unwrap(),
+                "EUR".to_owned()
+            ))
+        );
+        assert_eq!(
+            collapsed.postings[0].comment,
+            Some("transaction fee 0.35 EUR".to_owned())
+        );
+        assert_eq!(&collapsed.postings[1].account, "Income:Sales");
+
+        // without fee both are the same
+        let transaction = paypal_transaction("20,00", "0,00");
+        assert_eq!(
+            convert(&output, &transaction),
+            convert(&OutputConfig::default(), &transaction)
+        );
+    }
+
+    #[test]
+    fn offset_account_by_sign() {
+        let config = PayPalConfig {
+            asset_account: "Assets:PayPal".to_owned(),
+            fees_account: "Expenses:Fees".to_owned(),
+            fee_rebate_account: None,
+            empty_payee: "PayPal".to_owned(),
+            code_prefix: None,
+            skip_lines: 0,
+            commodity_formatting_rules: vec![],
+            rules: vec![],
+        };
+        let rule: PayPalMatchingRule = toml::from_str(
+            "name = \"Web Shop\"
+            account = \"Expenses:Shopping\"
+            account_positive = \"Income:Refunds\"",
+        )
+        .unwrap();
+        let output = OutputConfig::default();
+        let convert = |transaction: &PayPalTransaction| -> Transaction {
+            ConfiguredPaypalTransaction {
+                config: &config,
+                output: &output,
+                state: TransactionState::Cleared,
+                rule: &rule,
+                transaction,
+            }
+            .try_into()
+            .unwrap()
+        };
+
+        let transaction = convert(&paypal_transaction("-20,00", "0,00"));
+        assert_eq!(&transaction.postings[1].account, "Expenses:Shopping");
+
+        let transaction = convert(&paypal_transaction("20,00", "0,00"));
+        assert_eq!(&transaction.postings[1].account, "Income:Refunds");
+    }
+}
@@ -1,18 +1,28 @@
 use std::str::FromStr;
 
-use bigdecimal::{BigDecimal, Zero};
-use chrono::NaiveDate;
+use bigdecimal::{BigDecimal, Signed, Zero};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
 use serde::Deserialize;
 
 use crate::config::ImporterConfigTarget;
 use crate::error::Result;
-use crate::hledger::output::AmountAndCommodity;
+use crate::hledger::output::{shift_decimal_point, AmountAndCommodity};
+use crate::importers::csv_reader::{
+    at_row, check_columns, deserialize_raw_records, read_input, skip_lines,
+};
+use crate::importers::dates::parse_iso_date;
 use crate::{
     error::ImportError,
     hledger::output::{Posting, Tag, Transaction, TransactionState},
+    input::is_empty_input,
+    warnings::UnparsedRows,
     HledgerImporter,
 };
 
+/// hledger importer for the Revolut JSON (statement API) export
+pub mod json;
+
 pub struct RevolutCsvImporter {}
 
 impl RevolutCsvImporter {
@@ -27,51 +37,337 @@ impl Default for RevolutCsvImporter {
     }
 }
 
+/// columns of the Revolut CSV export
+const COLUMNS: &[&str] = &[
+    "Type",
+    "Product",
+    "Started Date",
+    "Completed Date",
+    "Description",
+    "Amount",
+    "Fee",
+    "Currency",
+    "State",
+    "Balance",
+];
+
+/// Revolut quotes fields that contain commas (e.g. `"Coffee, Tea"`) and escapes quotes by doubling them
+fn csv_reader<R: std::io::Read>(input: R) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .double_quote(true)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_reader(input)
+}
+
 impl HledgerImporter for RevolutCsvImporter {
     fn parse(
+        &self,
+        input_file: &std::path::Path,
+        config: &crate::config::ImporterConfig,
+        known_codes: &std::collections::HashSet<String>,
+    ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
+        self.parse_rows(
+            input_file,
+            config,
+            known_codes,
+            &mut UnparsedRows::default(),
+        )
+    }
+
+    fn parse_rows(
         &self,
         input_file: &std::path::Path,
         config: &crate::config::ImporterConfig,
         _known_codes: &std::collections::HashSet<String>,
+        unparsed: &mut UnparsedRows,
     ) -> crate::error::Result<Vec<crate::hledger::output::Transaction>> {
         let mut transactions = Vec::new();
-        let mut reader = csv::ReaderBuilder::new()
-            .delimiter(b',')
-            .has_headers(true)
-            .double_quote(false)
-            .flexible(true)
-            .from_path(input_file);
-        match &mut reader {
-            Ok(reader) => {
-                for record in reader.deserialize::<RevolutTransaction>() {
-                    match record {
-                        Ok(record) => transactions.push(record.into_hledger(config)?),
-                        Err(e) => return Err(ImportError::InputParse(e.to_string())),
-                    }
-                }
+        let content = read_input(input_file, &config.input_encoding)?;
+        if is_empty_input(input_file, &content) {
+            return Ok(transactions);
+        }
+        let skip = config.revolut.as_ref().map_or(0, |c| c.skip_lines);
+        let mut reader = csv_reader(skip_lines(&content, skip));
+        if config.strict {
+            check_columns(&mut reader, COLUMNS, false)?;
+        }
+        let (records, raw_tags): (Vec<_>, Vec<_>) =
+            deserialize_raw_records::<_, RevolutTransaction>(&mut reader, |_| false, unparsed)?
+                .into_iter()
+                .map(|(row, record, raw)| ((row, record), raw))
+                .unzip();
+        let closing_assertions = match &config.revolut {
+            Some(revolut_config) if revolut_config.emit_closing_assertion => {
+                closing_assertions(&records, config)?
             }
-            Err(e) => return Err(ImportError::InputParse(e.to_string())),
+            _ => Vec::new(),
+        };
+        for ((row, record), raw) in records.into_iter().zip(raw_tags) {
+            let transaction = record.into_hledger(config).map_err(|e| at_row(row, e));
+            let Some(mut transaction) = unparsed.check(row, Some(&raw), transaction)? else {
+                continue;
+            };
+            if config.output.tag_raw_record {
+                transaction.tags.push(raw);
+            }
+            transactions.push(transaction);
         }
+        if config.conversion_difference_account.is_some() {
+            transactions = pair_exchanges(transactions);
+        }
+        transactions.extend(closing_assertions);
         Ok(transactions)
     }
 
     fn output_title(&self) -> &'static str {
         "Revolut Import"
     }
+
+    fn slug(&self) -> &'static str {
+        "revolut"
+    }
+
+    fn commodity_rules(&self, config: &crate::config::ImporterConfig) -> Vec<String> {
+        config
+            .revolut
+            .as_ref()
+            .map(|c| c.commodity_formatting_rules.clone())
+            .unwrap_or_default()
+    }
+
+    fn code_prefix(&self, config: &crate::config::ImporterConfig) -> String {
+        config
+            .revolut
+            .as_ref()
+            .and_then(|c| c.code_prefix.clone())
+            .unwrap_or(self.slug().to_owned())
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct RevolutConfig {
     pub account: String,
     pub fee_account: Option<String>,
+    #[serde(default)]
+    pub fee_sign: RevolutFeeSign,
+    /// currency exchanges are exported as one row per currency,
+    /// each row is balanced against this account in its own currency
+    pub exchange_account: Option<String>,
+    /// transfers between your own pockets/vaults (`TRANSFER` rows without matching mapping) are posted to this account
+    pub vault_account: Option<String>,
+    /// interest, cashback and rewards (`INTEREST`, `CASHBACK` and `REWARD` rows) are posted to this account
+    pub income_account: Option<String>,
+    /// regular expression that extracts the sender of `TOPUP` rows from the description (first capture group),
+    /// e.g. `^Payment from (.+)$`
+    pub topup_payee_regex: Option<String>,
+    /// prefix of generated transaction codes, e.g. `revolut_business` for a second account (default: `revolut`)
+    pub code_prefix: Option<String>,
+    /// number of lines before the CSV header that are skipped (e.g. account information), lines starting with `#` are always ignored
+    #[serde(default)]
+    pub skip_lines: usize,
+    /// chrono format of the started and completed dates, e.g. `%d/%m/%Y %H:%M` (default: ISO 8601)
+    pub date_format: Option<String>,
+    /// emit a balance assertion with the closing balance (per currency) at the end of the statement
+    #[serde(default)]
+    pub emit_closing_assertion: bool,
+    /// additional commodity formatting rules (`-c` of `hledger print`) for this importer
+    #[serde(default)]
+    pub commodity_formatting_rules: Vec<String>,
+}
+
+/// transaction types of interest, cashback and rewards paid by Revolut
+const INCOME_TYPES: [&str; 3] = ["INTEREST", "CASHBACK", "REWARD"];
+
+/// sign convention of the `Fee` column in the Revolut export
+#[derive(Debug, Deserialize, PartialEq, Eq, Default, Clone, Copy)]
+pub enum RevolutFeeSign {
+    /// fees are reported as positive numbers that reduce the balance
+    #[default]
+    Positive,
+    /// fees are reported as negative numbers
+    Negative,
+}
+
+/// Parses a timestamp of the export in the configured `date_format`, timestamps without time are taken at midnight.
+fn parse_formatted(value: &str, format: &str) -> Result<NaiveDateTime> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, format)
+        .or_else(|_| NaiveDate::parse_from_str(value, format).map(|d| d.and_time(NaiveTime::MIN)))
+        .map_err(|e| {
+            ImportError::InputParse(format!(
+                "invalid date \"{}\" (expected format \"{}\"): {}",
+                value, format, e
+            ))
+        })
+}
+
+fn date_format(config: &crate::config::ImporterConfig) -> Option<&str> {
+    config
+        .revolut
+        .as_ref()
+        .and_then(|c| c.date_format.as_deref())
+}
+
+/// date of a timestamp of the export, ISO 8601 unless `date_format` is configured
+fn revolut_date(value: &str, config: &crate::config::ImporterConfig) -> Result<NaiveDate> {
+    match date_format(config) {
+        Some(format) => parse_formatted(value, format).map(|t| t.date()),
+        None => parse_iso_date(value, &config.timezone),
+    }
+}
+
+/// timestamp of the export in ISO 8601 form, as used for tags and to order the rows
+fn revolut_timestamp(value: &str, config: &crate::config::ImporterConfig) -> Result<String> {
+    match date_format(config) {
+        Some(format) => {
+            parse_formatted(value, format).map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        }
+        None => Ok(value.to_owned()),
+    }
+}
+
+/// Both legs of a currency exchange (`EXCHANGE` rows with the same started date in different currencies)
+/// are merged into one conversion transaction without offset postings, the incoming amount priced with the
+/// outgoing one. Legs without counterpart keep their offset posting.
+fn pair_exchanges(transactions: Vec<Transaction>) -> Vec<Transaction> {
+    let leg = |t: &Transaction| -> Option<(String, String)> {
+        let tag = |name: &str| {
+            t.tags
+                .iter()
+                .find(|tag| tag.name == name)
+                .and_then(|tag| tag.value.clone())
+        };
+        if tag("revolut_type").as_deref() != Some("EXCHANGE") {
+            return None;
+        }
+        let commodity = t.postings.first()?.amount.as_ref()?.commodity.clone();
+        Some((tag("valuation")?, commodity))
+    };
+
+    let mut paired: Vec<Transaction> = Vec::new();
+    // indices of legs in `paired` still waiting for their counterpart
+    let mut unpaired: Vec<usize> = Vec::new();
+    for mut transaction in transactions {
+        let Some((valuation, commodity)) = leg(&transaction) else {
+            paired.push(transaction);
+            continue;
+        };
+        let counterpart = unpaired.iter().position(
+            |&i| matches!(leg(&paired[i]), Some((v, c)) if v == valuation && c != commodity),
+        );
+        match counterpart {
+            Some(position) => {
+                let first = &mut paired[unpaired.remove(position)];
+                first.postings.retain(|p| p.amount.is_some());
+                transaction.postings.retain(|p| p.amount.is_some());
+                price_incoming_leg(&mut first.postings, &mut transaction.postings);
+                first.postings.extend(transaction.postings);
+            }
+            None => {
+                unpaired.push(paired.len());
+                paired.push(transaction);
+            }
+        }
+    }
+    paired
+}
+
+/// Prices the asset posting of the incoming leg with the outgoing amount (e.g. `108.12 USD @@ 100.00 EUR`),
+/// so that only the residual in the outgoing currency (e.g. a fee) is left for `conversion_difference_account`.
+fn price_incoming_leg(first: &mut [Posting], second: &mut [Posting]) {
+    let is_incoming = |postings: &[Posting]| {
+        postings
+            .first()
+            .and_then(|p| p.amount.as_ref())
+            .is_some_and(|a| a.amount.is_positive())
+    };
+    let (incoming, outgoing) = if is_incoming(second) {
+        (second, first)
+    } else if is_incoming(first) {
+        (first, second)
+    } else {
+        return;
+    };
+    let Some(cost) = outgoing.first().and_then(|p| p.amount.as_ref()) else {
+        return;
+    };
+    incoming[0].price = Some(AmountAndCommodity::new(
+        cost.amount.abs(),
+        cost.commodity.clone(),
+    ));
+}
+
+/// Balance assertions of the Revolut account with the balance of the latest completed row per currency.
+/// Rows of other products (e.g. savings) are not part of the account balance and therefore ignored.
+fn closing_assertions(
+    records: &[(u64, RevolutTransaction)],
+    config: &crate::config::ImporterConfig,
+) -> Result<Vec<Transaction>> {
+    let revolut_config = match &config.revolut {
+        Some(config) => config,
+        None => return Err(ImportError::MissingConfig("revolut".to_owned())),
+    };
+
+    let mut latest: Vec<(String, &(u64, RevolutTransaction))> = Vec::new();
+    for record in records.iter().filter(|(_, r)| {
+        r.state.to_uppercase() == "COMPLETED"
+            && !r.balance.trim().is_empty()
+            && (r.product.is_empty() || r.product.eq_ignore_ascii_case("Current"))
+    }) {
+        let completed =
+            revolut_timestamp(&record.1.completed_date, config).map_err(|e| at_row(record.0, e))?;
+        match latest
+            .iter_mut()
+            .find(|(_, (_, r))| r.currency == record.1.currency)
+        {
+            // ISO timestamps are ordered lexicographically, the later row of the same time wins
+            Some(entry) if entry.0 <= completed => *entry = (completed, record),
+            Some(_) => {}
+            None => latest.push((completed, record)),
+        }
+    }
+
+    latest
+        .into_iter()
+        .map(|(_, (row, record))| {
+            let balance = RevolutTransaction::amount_str_to_bigdecimal(record.balance.trim())
+                .map_err(|e| at_row(*row, e))?;
+            Ok(Transaction {
+                date: revolut_date(&record.completed_date, config).map_err(|e| at_row(*row, e))?,
+                code: None,
+                payee: "Revolut closing balance".to_owned(),
+                note: None,
+                state: TransactionState::Cleared,
+                comment: None,
+                tags: Vec::new(),
+                postings: vec![Posting {
+                    account: revolut_config.account.clone(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::zero(),
+                        record.currency.clone(),
+                    )),
+                    comment: None,
+                    tags: Vec::new(),
+                    price: None,
+                    balance_assertion: Some(AmountAndCommodity::new(
+                        balance,
+                        record.currency.clone(),
+                    )),
+                }],
+            })
+        })
+        .collect()
 }
 
 #[derive(Deserialize)]
 struct RevolutTransaction {
     #[serde(rename = "Type")]
     pub transaction_type: String,
-    // #[serde(rename = "Product")]
-    // pub product: String,
+    #[serde(rename = "Product", default)]
+    pub product: String,
     #[serde(rename = "Started Date")]
     pub started_date: String,
     #[serde(rename = "Completed Date")]
@@ -86,23 +382,24 @@ struct RevolutTransaction {
     pub currency: String,
     #[serde(rename = "State")]
     pub state: String,
-    // #[serde(rename = "Balance")]
-    // pub balance: String,
+    #[serde(rename = "Balance", default)]
+    pub balance: String,
+    /// merchant category code, only given by the JSON export
+    #[serde(skip)]
+    pub category: Option<String>,
 }
 
 impl RevolutTransaction {
     pub fn into_hledger(self, config: &crate::config::ImporterConfig) -> Result<Transaction> {
-        let state = self.state();
-        let tags = self.tags();
+        let state = config.state_rule(&self.state).unwrap_or(self.state());
+        let tags = self.tags(config)?;
         let postings = self.postings(config);
 
-        let date = match NaiveDate::parse_from_str(&self.completed_date[..10], "%Y-%m-%d") {
-            Ok(date) => date,
-            Err(e) => return Err(ImportError::InputParse(e.to_string())),
-        };
+        let date = revolut_date(&self.completed_date, config)?;
+        let payee = self.payee(config)?;
 
         Ok(Transaction {
-            payee: self.description,
+            payee,
             code: None,
             note: None,
             comment: None,
@@ -113,6 +410,24 @@ impl RevolutTransaction {
         })
     }
 
+    /// The description of the row, top-ups are reduced to the sender if `topup_payee_regex` matches.
+    pub fn payee(&self, config: &crate::config::ImporterConfig) -> Result<String> {
+        let regex = match config
+            .revolut
+            .as_ref()
+            .and_then(|c| c.topup_payee_regex.as_ref())
+        {
+            Some(regex) if self.transaction_type == "TOPUP" => Regex::new(regex)?,
+            _ => return Ok(self.description.clone()),
+        };
+        let sender = regex
+            .captures(&self.description)
+            .and_then(|captures| captures.get(1))
+            .map(|m| m.as_str().trim())
+            .filter(|sender| !sender.is_empty());
+        Ok(sender.unwrap_or(&self.description).to_owned())
+    }
+
     pub fn state(&self) -> TransactionState {
         if self.state.to_uppercase() == "COMPLETED" {
             TransactionState::Cleared
@@ -121,11 +436,11 @@ impl RevolutTransaction {
         }
     }
 
-    pub fn tags(&self) -> Vec<Tag> {
-        let valuation_str = self.started_date.clone();
+    pub fn tags(&self, config: &crate::config::ImporterConfig) -> Result<Vec<Tag>> {
+        let valuation_str = revolut_timestamp(&self.started_date, config)?;
         let type_str = self.transaction_type.clone();
 
-        vec![
+        Ok(vec![
             Tag {
                 name: "valuation".to_owned(),
                 value: Some(valuation_str),
@@ -134,44 +449,111 @@ impl RevolutTransaction {
                 name: "revolut_type".to_owned(),
                 value: Some(type_str),
             },
-        ]
+        ])
     }
 
     pub fn postings(&self, config: &crate::config::ImporterConfig) -> Result<Vec<Posting>> {
-        let revolut_account = match &config.revolut {
-            Some(config) => config.account.clone(),
+        let revolut_config = match &config.revolut {
+            Some(config) => config,
             None => return Err(ImportError::MissingConfig("revolut".to_owned())),
         };
+        let revolut_account = revolut_config.account.clone();
 
         let revolut_amount = AmountAndCommodity {
             amount: self.amount()?,
             commodity: self.currency.clone(),
         };
 
+        // the fee is normalized to a positive number representing the costs
         let fee_amount = AmountAndCommodity {
-            amount: self.fee()?,
+            amount: match revolut_config.fee_sign {
+                RevolutFeeSign::Positive => self.fee()?,
+                RevolutFeeSign::Negative => self.fee()? * (-1),
+            },
             commodity: self.currency.clone(),
         };
+        let fee_only = revolut_amount.amount.is_zero() && !fee_amount.amount.is_zero();
 
         let other_account = if &self.transaction_type == "TOPUP" {
             Some(ImporterConfigTarget {
                 account: config.transfer_accounts.bank.clone(),
                 note: None,
             })
+        } else if let Some(cash) = config.match_cash_withdrawal(&self.transaction_type)? {
+            Some(cash)
+        } else if let (true, Some(income_account)) = (
+            INCOME_TYPES.contains(&self.transaction_type.as_str()),
+            &revolut_config.income_account,
+        ) {
+            Some(ImporterConfigTarget {
+                account: income_account.clone(),
+                note: None,
+            })
+        } else if let (true, Some(exchange_account)) = (
+            &self.transaction_type == "EXCHANGE",
+            &revolut_config.exchange_account,
+        ) {
+            Some(ImporterConfigTarget {
+                account: exchange_account.clone(),
+                note: None,
+            })
+        } else if &self.transaction_type == "TRANSFER" {
+            config
+                .match_mapping(&self.description)?
+                .or(revolut_config
+                    .vault_account
+                    .as_ref()
+                    .map(|account| ImporterConfigTarget {
+                        account: account.clone(),
+                        note: None,
+                    }))
+                .or(config.fallback())
         } else {
             config
                 .match_mapping(&self.description)?
+                .or(self
+                    .category
+                    .as_deref()
+                    .and_then(|category| config.match_category(category)))
                 .or(config.fallback())
         };
 
-        let mut postings = vec![Posting {
-            account: revolut_account.clone(),
-            amount: Some(revolut_amount),
-            comment: None,
-            tags: Vec::new(),
-        }];
+        let mut postings = Vec::new();
+
+        // the fee is deducted from the amount and only kept as comment, unless the row only consists of a fee
+        let collapse_fee = config.output.collapse_fees && !fee_only && !fee_amount.amount.is_zero();
+
+        // rows that only consist of a fee do not need a (zero) posting of the amount
+        if collapse_fee {
+            postings.push(Posting {
+                account: revolut_account.clone(),
+                amount: Some(AmountAndCommodity {
+                    amount: revolut_amount.amount - &fee_amount.amount,
+                    commodity: revolut_amount.commodity,
+                }),
+                comment: Some(format!("fee {}", fee_amount)),
+                tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
+            });
+        } else if !fee_only {
+            postings.push(Posting {
+                account: revolut_account.clone(),
+                amount: Some(revolut_amount),
+                comment: None,
+                tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
+            });
+        }
+
+        if !collapse_fee && fee_amount.amount != BigDecimal::zero() {
+            // the fee postings can be traced back to the row of the export
+            let fee_tags = vec![Tag::new_val(
+                "valuation".to_owned(),
+                revolut_timestamp(&self.started_date, config)?,
+            )];
 
-        if fee_amount.amount != BigDecimal::zero() {
             postings.push(Posting {
                 account: revolut_account.clone(),
                 amount: Some(AmountAndCommodity {
@@ -179,17 +561,24 @@ impl RevolutTransaction {
                     commodity: fee_amount.commodity.clone(),
                 }),
                 comment: Some("fee".to_owned()),
-                tags: Vec::new(),
+                tags: fee_tags.clone(),
+                price: None,
+                balance_assertion: None,
             });
 
-            if let Some(config) = &config.revolut {
-                if let Some(fee_account) = &config.fee_account {
-                    postings.push(Posting {
-                        account: fee_account.clone(),
-                        amount: Some(fee_amount),
-                        comment: Some("fee".to_owned()),
-                        tags: Vec::new(),
-                    });
+            if let Some(fee_account) = &revolut_config.fee_account {
+                postings.push(Posting {
+                    account: fee_account.clone(),
+                    amount: Some(fee_amount),
+                    comment: Some("fee".to_owned()),
+                    tags: fee_tags,
+                    price: None,
+                    balance_assertion: None,
+                });
+
+                // the fee posting already balances a fee-only row
+                if fee_only {
+                    return Ok(postings);
                 }
             }
         }
@@ -200,6 +589,8 @@ impl RevolutTransaction {
                 amount: None,
                 comment: None,
                 tags: Vec::new(),
+                price: None,
+                balance_assertion: None,
             });
         }
         Ok(postings)
@@ -225,7 +616,7 @@ impl RevolutTransaction {
         let amount_filtered = amount_str.replace('.', "");
 
         let big_dec = match BigDecimal::from_str(&amount_filtered) {
-            Ok(b) => b / ((10_u32).pow(decimal_len as u32)),
+            Ok(b) => shift_decimal_point(b, decimal_len as i64),
             Err(e) => return Err(ImportError::InputParse(e.to_string())),
         };
 
@@ -236,10 +627,14 @@ impl RevolutTransaction {
 #[cfg(test)]
 mod tests {
     use bigdecimal::FromPrimitive;
+    use chrono::NaiveDate;
 
     use crate::config::{
-        HledgerConfig, ImporterConfig, SepaConfig, SimpleMapping, TransferAccounts,
+        CommodityConfig, DateBasis, HledgerConfig, ImporterConfig, OutputConfig, SepaConfig,
+        SimpleMapping, TransferAccounts,
     };
+    use crate::hledger::hasher::transaction_hash;
+    use crate::importers::csv_reader::deserialize_records;
 
     use super::*;
 
@@ -254,12 +649,7 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
 ";
 
         let mut transactions: Vec<Transaction> = Vec::new();
-        let mut reader = csv::ReaderBuilder::new()
-            .delimiter(b',')
-            .has_headers(true)
-            .double_quote(false)
-            .flexible(true)
-            .from_reader(csv.as_bytes());
+        let mut reader = csv_reader(csv.as_bytes());
 
         for record in reader.deserialize::<RevolutTransaction>() {
             let record = record.expect("Parsing CSV record failed");
@@ -299,12 +689,16 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
                     }),
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 },
                 Posting {
                     account: "Expenses:Donation".to_owned(),
                     amount: None,
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 },
             ],
         };
@@ -338,12 +732,16 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
                     }),
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 },
                 Posting {
                     account: "Expenses:Apples".to_owned(),
                     amount: None,
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 },
             ],
         };
@@ -377,12 +775,16 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
                     }),
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 },
                 Posting {
                     account: "Assets:Reconciliation:Bank".to_owned(),
                     amount: None,
                     comment: None,
                     tags: Vec::new(),
+                    price: None,
+                    balance_assertion: None,
                 },
             ],
         };
@@ -391,22 +793,221 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
         assert!(transactions.contains(&t3));
     }
 
+    #[test]
+    fn fee_rows() {
+        let mut config = test_config();
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.50,EUR,COMPLETED,100.00
+FEE,Current,2024-05-31 10:00:00,2024-05-31 10:00:00,Plan fee,0.00,7.99,EUR,COMPLETED,92.01
+";
+        let parse = |config: &ImporterConfig, csv: &str| {
+            let mut reader = csv_reader(csv.as_bytes());
+            reader
+                .deserialize::<RevolutTransaction>()
+                .map(|r| r.unwrap().postings(config).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let posting = |account: &str, amount: Option<i64>, comment: Option<&str>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| AmountAndCommodity {
+                amount: BigDecimal::from_i64(a).unwrap() / 100,
+                commodity: "EUR".to_owned(),
+            }),
+            comment: comment.map(|c| c.to_owned()),
+            tags: Vec::new(),
+            price: None,
+            balance_assertion: None,
+        };
+        // fee postings carry the start date of their row
+        let fee = |account: &str, amount: i64, valuation: &str| Posting {
+            tags: vec![Tag::new_val("valuation".to_owned(), valuation.to_owned())],
+            ..posting(account, Some(amount), Some("fee"))
+        };
+
+        let postings = parse(&config, csv);
+        assert_eq!(
+            postings[0],
+            vec![
+                posting("Assets:Revolut", Some(-2440), None),
+                fee("Assets:Revolut", -50, "2024-05-01 13:05:33"),
+                fee("Expenses:Fee", 50, "2024-05-01 13:05:33"),
+                posting("Expenses:Donation", None, None),
+            ]
+        );
+        assert_eq!(
+            postings[1],
+            vec![
+                fee("Assets:Revolut", -799, "2024-05-31 10:00:00"),
+                fee("Expenses:Fee", 799, "2024-05-31 10:00:00"),
+            ]
+        );
+
+        config.revolut.as_mut().unwrap().fee_sign = RevolutFeeSign::Negative;
+        let csv =
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+FEE,Current,2024-05-31 10:00:00,2024-05-31 10:00:00,Plan fee,0.00,-7.99,EUR,COMPLETED,92.01
+";
+        let postings = parse(&config, csv);
+        assert_eq!(
+            postings[0],
+            vec![
+                fee("Assets:Revolut", -799, "2024-05-31 10:00:00"),
+                fee("Expenses:Fee", 799, "2024-05-31 10:00:00"),
+            ]
+        );
+
+        config.revolut.as_mut().unwrap().fee_account = None;
+        let postings = parse(&config, csv);
+        assert_eq!(
+            postings[0],
+            vec![
+                fee("Assets:Revolut", -799, "2024-05-31 10:00:00"),
+                posting("Equity:Fallback", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn collapsed_fees() {
+        let mut config = test_config();
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.50,EUR,COMPLETED,100.00
+FEE,Current,2024-05-31 10:00:00,2024-05-31 10:00:00,Plan fee,0.00,7.99,EUR,COMPLETED,92.01
+";
+        let parse = |config: &ImporterConfig| {
+            let mut reader = csv_reader(csv.as_bytes());
+            reader
+                .deserialize::<RevolutTransaction>()
+                .map(|r| r.unwrap().postings(config).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let amount = |a: i64| {
+            Some(AmountAndCommodity {
+                amount: BigDecimal::from_i64(a).unwrap() / 100,
+                commodity: "EUR".to_owned(),
+            })
+        };
+
+        let split = parse(&config);
+        assert_eq!(split[0].len(), 4);
+
+        config.output.collapse_fees = true;
+        let collapsed = parse(&config);
+        assert_eq!(collapsed[0].len(), 2);
+        assert_eq!(&collapsed[0][0].account, "Assets:Revolut");
+        assert_eq!(collapsed[0][0].amount, amount(-2490));
+        assert_eq!(collapsed[0][0].comment, Some("fee 0.50 EUR".to_owned()));
+        assert!(collapsed[0][0].tags.is_empty());
+        assert_eq!(&collapsed[0][1].account, "Expenses:Donation");
+
+        // rows that only consist of a fee stay split
+        assert_eq!(collapsed[1], split[1]);
+    }
+
+    #[test]
+    fn exchange_rows() {
+        let config = test_config();
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+EXCHANGE,Current,2024-05-10 09:00:00,2024-05-10 09:00:00,Exchanged to USD,-100.00,0.00,EUR,COMPLETED,0.00
+EXCHANGE,Current,2024-05-10 09:00:00,2024-05-10 09:00:00,Exchanged to USD,108.12,0.00,USD,COMPLETED,108.12
+";
+        let mut reader = csv_reader(csv.as_bytes());
+        let transactions: Vec<Transaction> = reader
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .collect();
+
+        let postings: Vec<String> = transactions
+            .iter()
+            .flat_map(|t| t.postings.iter().map(|p| p.to_string()))
+            .collect();
+        assert_eq!(
+            postings,
+            vec![
+                "    Assets:Revolut     -100.00 EUR",
+                "    Equity:Conversion",
+                "    Assets:Revolut     108.12 USD",
+                "    Equity:Conversion",
+            ]
+        );
+    }
+
+    #[test]
+    fn exchange_conversion_difference() {
+        let mut config = test_config();
+        config.conversion_difference_account = Some("Equity:Conversion Differences".to_owned());
+        config.revolut.as_mut().unwrap().fee_account = None;
+
+        let path = std::env::temp_dir().join("hledger-import-revolut-exchange.csv");
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+EXCHANGE,Current,2024-05-10 09:00:00,2024-05-10 09:00:00,Exchanged to USD,-100.00,0.00,EUR,COMPLETED,0.00
+EXCHANGE,Current,2024-05-10 09:00:00,2024-05-10 09:00:01,Exchanged to USD,108.12,0.00,USD,COMPLETED,108.12
+EXCHANGE,Current,2024-05-11 09:00:00,2024-05-11 09:00:00,Exchanged to GBP,-20.00,0.00,EUR,COMPLETED,80.00
+EXCHANGE,Current,2024-05-12 09:00:00,2024-05-12 09:00:00,Exchanged to USD,-50.00,0.50,EUR,COMPLETED,29.50
+EXCHANGE,Current,2024-05-12 09:00:00,2024-05-12 09:00:01,Exchanged to USD,54.06,0.00,USD,COMPLETED,162.18
+",
+        )
+        .unwrap();
+        let transactions = crate::import(
+            crate::Importer::Revolut,
+            &path,
+            &config,
+            &std::collections::HashSet::new(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let postings: Vec<Vec<String>> = transactions
+            .iter()
+            .map(|t| t.postings.iter().map(|p| p.to_string()).collect())
+            .collect();
+        assert_eq!(
+            postings,
+            vec![
+                vec![
+                    "    Assets:Revolut     -100.00 EUR",
+                    "    Assets:Revolut     108.12 USD @@ 100.00 EUR",
+                ],
+                // the counterpart of this leg is not part of the export
+                vec!["    Assets:Revolut     -20.00 EUR", "    Equity:Conversion"],
+                // only the fee is left as residual without `fee_account`
+                vec![
+                    "    Assets:Revolut     -50.00 EUR",
+                    "    Assets:Revolut     -0.50 EUR\n    ; fee\n    ; valuation: 2024-05-12 09:00:00",
+                    "    Assets:Revolut     54.06 USD @@ 50.00 EUR",
+                    "    Equity:Conversion Differences     0.50 EUR\n    ; conversion difference",
+                ],
+            ]
+        );
+    }
+
     fn test_config() -> ImporterConfig {
         ImporterConfig {
             hledger: HledgerConfig::default(),
+            output: OutputConfig::default(),
             commodity_formatting_rules: None,
             ibans: Vec::new(),
             cards: Vec::new(),
             mapping: vec![
                 SimpleMapping {
                     search: "PATREON".to_owned(),
+                    search_payee: None,
+                    search_note: None,
                     account: "Expenses:Donation".to_owned(),
                     note: None,
+                    case_sensitive: false,
                 },
                 SimpleMapping {
                     search: "APPLE".to_owned(),
+                    search_payee: None,
+                    search_note: None,
                     account: "Expenses:Apples".to_owned(),
                     note: None,
+                    case_sensitive: false,
                 },
             ],
             categories: vec![],
@@ -415,15 +1016,49 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
                 creditors: Vec::new(),
                 mandates: Vec::new(),
             },
+            commodities: CommodityConfig::default(),
+            split: Vec::new(),
+            iban_mapping: Vec::new(),
+            state_rules: std::collections::HashMap::new(),
+            date_basis: DateBasis::default(),
+            importer_date_basis: std::collections::HashMap::new(),
+            importer_notes: std::collections::HashMap::new(),
+            override_commodity: std::collections::HashMap::new(),
+            sepa_purpose: std::collections::HashMap::new(),
+            loan_mapping: std::collections::HashMap::new(),
+            standing_order_mapping: std::collections::HashMap::new(),
             transfer_accounts: TransferAccounts {
                 bank: "Assets:Reconciliation:Bank".to_owned(),
                 cash: "Assets:Reconciliation:Cash".to_owned(),
             },
+            cash_withdrawals: Vec::new(),
             filter: crate::config::WordFilter::default(),
             fallback_account: Some("Equity:Fallback".to_owned()),
+            missing_asset_account: None,
+            conversion_difference_account: None,
+            min_abs_amount: None,
+            dust_account: None,
+            strict_commodities: Vec::new(),
+            canonical_accounts: Vec::new(),
+            prefer_original_currency: false,
+            empty_postings: crate::config::EmptyPostingsHandling::default(),
+            timezone: crate::config::TargetTimezone::default(),
+            input_encoding: None,
+            strict: false,
+            state_file: None,
             revolut: Some(RevolutConfig {
                 account: "Assets:Revolut".to_owned(),
                 fee_account: Some("Expenses:Fee".to_owned()),
+                fee_sign: RevolutFeeSign::Positive,
+                exchange_account: Some("Equity:Conversion".to_owned()),
+                vault_account: Some("Assets:Revolut:Vault".to_owned()),
+                code_prefix: None,
+                skip_lines: 0,
+                date_format: None,
+                topup_payee_regex: None,
+                income_account: None,
+                emit_closing_assertion: false,
+                commodity_formatting_rules: Vec::new(),
             }),
             #[cfg(feature = "flatex")]
             flatex_csv: None,
@@ -433,4 +1068,455 @@ TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,1
             paypal: None,
         }
     }
+
+    #[test]
+    fn quoted_description() {
+        let config = test_config();
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,\"Coffee, Tea\",-4.40,0.00,EUR,COMPLETED,\"1,095.60\"
+CARD_PAYMENT,Current,2024-05-02 13:05:33,2024-05-02 16:46:56,\"The \"\"Good\"\" Bakery\",-2.10,0.00,EUR,COMPLETED,\"1,093.50\"
+CARD_PAYMENT,Current,2024-05-03 15:04:58,2024-05-04 03:36:34,Apple,-1.99,0.00,EUR,COMPLETED,1091.51
+";
+        let transactions: Vec<Transaction> = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .collect();
+
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(&transactions[0].payee, "Coffee, Tea");
+        assert_eq!(
+            transactions[0].postings[0].amount,
+            Some(AmountAndCommodity {
+                amount: BigDecimal::from_str("-4.40").unwrap(),
+                commodity: "EUR".to_owned(),
+            })
+        );
+        assert_eq!(&transactions[1].payee, "The \"Good\" Bakery");
+        assert_eq!(&transactions[2].payee, "Apple");
+    }
+
+    #[test]
+    fn suppress_tags() {
+        let mut config = test_config();
+        config.output.suppress_tags = vec!["revolut_type".to_owned()];
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+";
+        let mut transaction = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .next()
+            .unwrap();
+        assert!(transaction.tags.iter().any(|t| t.name == "revolut_type"));
+
+        transaction.remove_tags(&config.output.suppress_tags);
+        let tags: Vec<&str> = transaction.tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(tags, vec!["valuation"]);
+    }
+
+    #[test]
+    fn configured_code_prefix() {
+        let mut config = test_config();
+        let importer = RevolutCsvImporter::new();
+        assert_eq!(importer.code_prefix(&config), "revolut");
+
+        config.revolut.as_mut().unwrap().code_prefix = Some("revolut_business".to_owned());
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+";
+        let transaction = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .next()
+            .unwrap();
+        let code = transaction_hash(&importer.code_prefix(&config), &transaction);
+        assert!(code.starts_with("revolut_business_"), "{}", code);
+    }
+
+    #[test]
+    fn closing_assertion() {
+        let mut config = test_config();
+        config.revolut.as_mut().unwrap().emit_closing_assertion = true;
+
+        let path = std::env::temp_dir().join("hledger-import-revolut-closing.csv");
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+TOPUP,Current,2024-05-03 09:00:00,2024-05-03 09:00:01,Top-Up,50.00,0.00,EUR,COMPLETED,150.00
+CARD_PAYMENT,Current,2024-05-04 10:00:00,2024-05-04 10:00:00,Pending Store,-5.00,0.00,EUR,PENDING,
+TRANSFER,Savings,2024-05-05 10:00:00,2024-05-05 10:00:00,To pocket,10.00,0.00,EUR,COMPLETED,1010.00
+CARD_PAYMENT,Current,2024-05-02 08:00:00,2024-05-02 08:00:10,Coffee,-3.00,0.00,USD,COMPLETED,12.50
+",
+        )
+        .unwrap();
+
+        let transactions = RevolutCsvImporter::new()
+            .parse(&path, &config, &std::collections::HashSet::new())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let assertions: Vec<String> = transactions
+            .iter()
+            .filter(|t| t.payee == "Revolut closing balance")
+            .map(|t| t.to_string())
+            .collect();
+        assert_eq!(
+            assertions,
+            vec![
+                "2024-05-03 * Revolut closing balance\n    Assets:Revolut     0 EUR = 150.00 EUR",
+                "2024-05-02 * Revolut closing balance\n    Assets:Revolut     0 USD = 12.50 USD",
+            ]
+        );
+
+        config.revolut.as_mut().unwrap().emit_closing_assertion = false;
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+",
+        )
+        .unwrap();
+        let transactions = RevolutCsvImporter::new()
+            .parse(&path, &config, &std::collections::HashSet::new())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(transactions.len(), 1);
+    }
+
+    #[test]
+    fn hash_codes_as_parsed() {
+        let mut config = test_config();
+        config.output.hash_codes = true;
+        let path = std::env::temp_dir().join("hledger-import-revolut-hash-codes.csv");
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-02 16:46:56,Patreon,-24.40,0.00,eur,COMPLETED,100.00
+",
+        )
+        .unwrap();
+        let codes = |config: &ImporterConfig| -> Vec<Option<String>> {
+            crate::import(
+                crate::Importer::Revolut,
+                &path,
+                config,
+                &std::collections::HashSet::new(),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|t| t.code)
+            .collect()
+        };
+        let codes_as_parsed = codes(&config);
+
+        // the processing after parsing does not change the codes
+        config.date_basis = crate::config::DateBasis::Value;
+        config.commodities.normalize = true;
+        config
+            .override_commodity
+            .insert("revolut".to_owned(), "USD".to_owned());
+        let processed = codes(&config);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(processed, codes_as_parsed);
+    }
+
+    #[test]
+    fn skip_unparsed_rows() {
+        let config = test_config();
+        let path = std::env::temp_dir().join("hledger-import-revolut-unparsed.csv");
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+CARD_PAYMENT,Current,2024-05-02 08:00:00,yesterday,Coffee,-3.00,0.00,EUR,COMPLETED,97.00
+CARD_PAYMENT,Current,2024-05-03 08:00:00,2024-05-03 08:00:00,Apple,-1.50,0.00,EUR,COMPLETED,95.50
+",
+        )
+        .unwrap();
+        let importer = RevolutCsvImporter::new();
+        let known_codes = std::collections::HashSet::new();
+
+        assert!(importer.parse(&path, &config, &known_codes).is_err());
+
+        let mut unparsed = UnparsedRows::collecting();
+        let transactions = importer
+            .parse_rows(&path, &config, &known_codes, &mut unparsed)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let payees: Vec<&str> = transactions.iter().map(|t| t.payee.as_str()).collect();
+        assert_eq!(payees, vec!["Patreon", "Apple"]);
+        assert_eq!(unparsed.warnings.len(), 1);
+        assert_eq!(unparsed.warnings[0].index, 3);
+        assert!(unparsed.warnings[0].fields["raw"].contains("|Coffee|"));
+    }
+
+    #[test]
+    fn strict_columns() {
+        let mut config = test_config();
+        config.strict = true;
+
+        let path = std::env::temp_dir().join("hledger-import-revolut-strict.csv");
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,Status,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+",
+        )
+        .unwrap();
+
+        let importer = RevolutCsvImporter::new();
+        match importer.parse(&path, &config, &std::collections::HashSet::new()) {
+            Err(ImportError::InputParse(msg)) => assert_eq!(
+                msg,
+                "unexpected CSV columns (missing: [State], unexpected: [Status])"
+            ),
+            _ => panic!("expected the renamed column to be rejected"),
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn raw_record_tag() {
+        let mut config = test_config();
+
+        let path = std::env::temp_dir().join("hledger-import-revolut-raw.csv");
+        std::fs::write(
+            &path,
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,\"Coffee, Tea\",-24.40,0.00,EUR,COMPLETED,100.00
+",
+        )
+        .unwrap();
+
+        let importer = RevolutCsvImporter::new();
+        let transactions = importer
+            .parse(&path, &config, &std::collections::HashSet::new())
+            .unwrap();
+        assert!(!transactions[0].tags.iter().any(|t| t.name == "raw"));
+
+        config.output.tag_raw_record = true;
+        let transactions = importer
+            .parse(&path, &config, &std::collections::HashSet::new())
+            .unwrap();
+        let raw = transactions[0]
+            .tags
+            .iter()
+            .find(|t| t.name == "raw")
+            .unwrap();
+        assert_eq!(
+            raw.value.as_deref(),
+            Some("CARD_PAYMENT|Current|2024-05-01 13:05:33|2024-05-01 16:46:56|Coffee Tea|-24.40|0.00|EUR|COMPLETED|100.00")
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn configured_date_format() {
+        let mut config = test_config();
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-02 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+";
+        let transaction = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 5, 2).unwrap()
+        );
+        assert_eq!(
+            transaction.tags[0].value.as_deref(),
+            Some("2024-05-01 13:05:33")
+        );
+
+        config.revolut.as_mut().unwrap().date_format = Some("%d/%m/%Y %H:%M".to_owned());
+        let csv =
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,01/05/2024 13:05,02/05/2024 16:46,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+";
+        let transaction = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 5, 2).unwrap()
+        );
+        assert_eq!(
+            transaction.tags[0].value.as_deref(),
+            Some("2024-05-01 13:05:00")
+        );
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-02 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+";
+        let result = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config))
+            .next()
+            .unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn preamble_before_header() {
+        let config = test_config();
+        let header = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+";
+        let parse = |content: &[u8]| -> Vec<Transaction> {
+            let mut reader = csv_reader(content);
+            deserialize_records::<_, RevolutTransaction>(&mut reader)
+                .unwrap()
+                .into_iter()
+                .map(|(_, r)| r.into_hledger(&config).unwrap())
+                .collect()
+        };
+
+        let commented = format!("# Revolut statement\n# Account: Current EUR\n{}", header);
+        let transactions = parse(commented.as_bytes());
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(&transactions[0].payee, "Patreon");
+
+        let preamble = format!("Revolut statement\nAccount: Current EUR\n{}", header);
+        let transactions = parse(skip_lines(preamble.as_bytes(), 2));
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(&transactions[0].payee, "Patreon");
+    }
+
+    #[test]
+    fn pocket_transfer() {
+        let mut config = test_config();
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+TRANSFER,Current,2024-05-10 09:00:00,2024-05-10 09:00:00,To pocket EUR Holidays,-50.00,0.00,EUR,COMPLETED,50.00
+";
+        let parse = |config: &ImporterConfig| {
+            csv_reader(csv.as_bytes())
+                .deserialize::<RevolutTransaction>()
+                .map(|r| r.unwrap().into_hledger(config).unwrap())
+                .next()
+                .unwrap()
+        };
+
+        let transaction = parse(&config);
+        assert_eq!(&transaction.payee, "To pocket EUR Holidays");
+        assert_eq!(&transaction.postings[0].account, "Assets:Revolut");
+        assert_eq!(&transaction.postings[1].account, "Assets:Revolut:Vault");
+
+        config.revolut.as_mut().unwrap().vault_account = None;
+        let transaction = parse(&config);
+        assert_eq!(&transaction.postings[1].account, "Equity:Fallback");
+    }
+
+    #[test]
+    fn topup_sender_as_payee() {
+        let mut config = test_config();
+        config.revolut.as_mut().unwrap().topup_payee_regex = Some("^Payment from (.+)$".to_owned());
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+TOPUP,Current,2024-05-19 10:02:45,2024-05-22 10:02:45,Payment from John Doe Jr,150.00,0.00,EUR,COMPLETED,150.00
+TOPUP,Current,2024-05-20 10:00:00,2024-05-20 10:00:00,Top-Up by *1234,50.00,0.00,EUR,COMPLETED,200.00
+CARD_PAYMENT,Current,2024-05-21 13:05:33,2024-05-21 16:46:56,Payment from John Doe Jr,-24.40,0.00,EUR,COMPLETED,175.60
+";
+        let transactions: Vec<_> = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .collect();
+
+        assert_eq!(&transactions[0].payee, "John Doe Jr");
+        assert_eq!(
+            &transactions[0].postings[1].account,
+            "Assets:Reconciliation:Bank"
+        );
+        // descriptions that do not match are kept
+        assert_eq!(&transactions[1].payee, "Top-Up by *1234");
+        // only top-ups are affected
+        assert_eq!(&transactions[2].payee, "Payment from John Doe Jr");
+    }
+
+    #[test]
+    fn cashback_to_income_account() {
+        let mut config = test_config();
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CASHBACK,Current,2024-05-10 09:00:00,2024-05-10 09:00:00,Metal Cashback,0.42,0.00,EUR,COMPLETED,100.42
+INTEREST,Savings,2024-05-11 09:00:00,2024-05-11 09:00:00,Interest earned,0.10,0.00,EUR,COMPLETED,500.10
+";
+        let parse = |config: &ImporterConfig| -> Vec<_> {
+            csv_reader(csv.as_bytes())
+                .deserialize::<RevolutTransaction>()
+                .map(|r| r.unwrap().into_hledger(config).unwrap())
+                .collect()
+        };
+
+        let transactions = parse(&config);
+        assert_eq!(&transactions[0].postings[1].account, "Equity:Fallback");
+
+        config.revolut.as_mut().unwrap().income_account = Some("Income:Revolut".to_owned());
+        let transactions = parse(&config);
+        assert_eq!(&transactions[0].postings[0].account, "Assets:Revolut");
+        assert_eq!(&transactions[0].postings[1].account, "Income:Revolut");
+        assert_eq!(&transactions[1].postings[1].account, "Income:Revolut");
+    }
+
+    #[test]
+    fn atm_withdrawal_to_cash_account() {
+        let mut config = test_config();
+        config.cash_withdrawals = vec!["^ATM$".to_owned()];
+
+        let csv = "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+ATM,Current,2024-05-10 09:00:00,2024-05-10 09:00:00,Cash at Bank Austria,-100.00,0.00,EUR,COMPLETED,0.00
+";
+        let transaction = csv_reader(csv.as_bytes())
+            .deserialize::<RevolutTransaction>()
+            .map(|r| r.unwrap().into_hledger(&config).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(&transaction.postings[0].account, "Assets:Revolut");
+        assert_eq!(
+            &transaction.postings[1].account,
+            "Assets:Reconciliation:Cash"
+        );
+    }
+
+    #[test]
+    fn malformed_dates() {
+        let config = test_config();
+        for date in ["ä2024-05-01 16:46:56", "202"] {
+            let csv = format!("Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-05-01 13:05:33,{},Patreon,-24.40,0.00,EUR,COMPLETED,100.00
+", date);
+            let result = csv_reader(csv.as_bytes())
+                .deserialize::<RevolutTransaction>()
+                .map(|r| r.unwrap().into_hledger(&config))
+                .next()
+                .unwrap();
+            assert!(
+                matches!(result, Err(ImportError::InputParse(_))),
+                "{}",
+                date
+            );
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        let path = std::env::temp_dir().join("hledger-import-revolut-empty.csv");
+        std::fs::write(&path, " \n\n").unwrap();
+        let transactions = RevolutCsvImporter::new()
+            .parse(&path, &test_config(), &std::collections::HashSet::new())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(transactions.is_empty());
+    }
 }
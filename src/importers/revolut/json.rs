@@ -0,0 +1,439 @@
+use std::collections::HashSet;
+
+use chrono::DateTime;
+use serde::Deserialize;
+
+use super::{closing_assertions, date_format, pair_exchanges, RevolutTransaction};
+use crate::config::ImporterConfig;
+use crate::error::{ImportError, Result};
+use crate::hledger::output::{Tag, Transaction};
+use crate::importers::dates::local_date_time;
+use crate::input::is_empty_input;
+use crate::HledgerImporter;
+
+/// Imports the JSON export of the Revolut statement API.
+/// Every leg of a transaction (one per currency) is converted like a row of the CSV export,
+/// so the `[revolut]` configuration applies to both formats.
+pub struct RevolutJsonImporter {}
+
+impl RevolutJsonImporter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for RevolutJsonImporter {
+    fn default() -> Self {
+        RevolutJsonImporter::new()
+    }
+}
+
+impl HledgerImporter for RevolutJsonImporter {
+    fn parse(
+        &self,
+        input_file: &std::path::Path,
+        config: &ImporterConfig,
+        known_codes: &HashSet<String>,
+    ) -> Result<Vec<Transaction>> {
+        let content = std::fs::read_to_string(input_file)
+            .map_err(|_| ImportError::InputFileRead(input_file.to_path_buf()))?;
+        if is_empty_input(input_file, content.as_bytes()) {
+            return Ok(Vec::new());
+        }
+        let document = serde_json::from_str::<RevolutJsonDocument>(&content)
+            .map_err(|e| ImportError::InputParse(e.to_string()))?;
+
+        let mut records = Vec::new();
+        let mut details = Vec::new();
+        for transaction in document.transactions() {
+            let legs = transaction
+                .records(config)
+                .map_err(|e| at_transaction(&transaction.id, e))?;
+            for (code, record) in legs {
+                details.push((code, transaction.note(), transaction.tags()));
+                records.push((records.len() as u64 + 1, record));
+            }
+        }
+
+        let closing_assertions = match &config.revolut {
+            Some(revolut_config) if revolut_config.emit_closing_assertion => {
+                closing_assertions(&records, config)?
+            }
+            _ => Vec::new(),
+        };
+
+        let mut transactions = Vec::new();
+        for ((_, record), (code, note, tags)) in records.into_iter().zip(details) {
+            if known_codes.contains(&code) {
+                continue;
+            }
+            let mut transaction = record
+                .into_hledger(config)
+                .map_err(|e| at_transaction(&code, e))?;
+            transaction.code = Some(code);
+            transaction.note = note;
+            transaction.tags.extend(tags);
+            transactions.push(transaction);
+        }
+        if config.conversion_difference_account.is_some() {
+            transactions = pair_exchanges(transactions);
+        }
+        transactions.extend(closing_assertions);
+        Ok(transactions)
+    }
+
+    fn output_title(&self) -> &'static str {
+        "Revolut Import"
+    }
+
+    fn slug(&self) -> &'static str {
+        "revolut"
+    }
+
+    fn commodity_rules(&self, config: &ImporterConfig) -> Vec<String> {
+        config
+            .revolut
+            .as_ref()
+            .map(|c| c.commodity_formatting_rules.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// adds the transaction ID to parse errors
+fn at_transaction(id: &str, error: ImportError) -> ImportError {
+    match error {
+        ImportError::InputParse(msg) => {
+            ImportError::InputParse(format!("transaction {}: {}", id, msg))
+        }
+        e => e,
+    }
+}
+
+/// The statement is either a list of transactions or an object with a list of `transactions`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RevolutJsonDocument {
+    Transactions(Vec<RevolutJsonTransaction>),
+    Statement {
+        transactions: Vec<RevolutJsonTransaction>,
+    },
+}
+
+impl RevolutJsonDocument {
+    fn transactions(self) -> Vec<RevolutJsonTransaction> {
+        match self {
+            RevolutJsonDocument::Transactions(transactions) => transactions,
+            RevolutJsonDocument::Statement { transactions } => transactions,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RevolutJsonTransaction {
+    pub id: String,
+    /// e.g. `card_payment`, `transfer` or `topup` (the `Type` of the CSV export in lowercase)
+    #[serde(rename = "type")]
+    pub transaction_type: String,
+    pub state: String,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+    pub reference: Option<String>,
+    pub merchant: Option<RevolutJsonMerchant>,
+    /// one leg per account and currency, e.g. two legs for a currency exchange
+    pub legs: Vec<RevolutJsonLeg>,
+}
+
+#[derive(Deserialize)]
+struct RevolutJsonMerchant {
+    pub name: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+    /// merchant category code (MCC), e.g. `5814` for fast food restaurants
+    pub category_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RevolutJsonLeg {
+    pub amount: serde_json::Number,
+    pub fee: Option<serde_json::Number>,
+    pub currency: String,
+    pub description: Option<String>,
+    pub balance: Option<serde_json::Number>,
+}
+
+impl RevolutJsonTransaction {
+    /// The legs as rows of the CSV export, together with their transaction code
+    /// (the transaction ID, with the number of the leg for transactions with several legs).
+    fn records(&self, config: &ImporterConfig) -> Result<Vec<(String, RevolutTransaction)>> {
+        let started_date = self.timestamp(&self.created_at, config)?;
+        let completed_date = self.timestamp(
+            self.completed_at.as_ref().unwrap_or(&self.created_at),
+            config,
+        )?;
+        let merchant_name = self
+            .merchant
+            .as_ref()
+            .and_then(|m| m.name.clone())
+            .filter(|name| !name.is_empty());
+
+        self.legs
+            .iter()
+            .enumerate()
+            .map(|(i, leg)| {
+                let code = match self.legs.len() {
+                    1 => self.id.clone(),
+                    _ => format!("{}/{}", self.id, i + 1),
+                };
+                let description = merchant_name
+                    .clone()
+                    .or(leg.description.clone())
+                    .unwrap_or_default();
+                let record = RevolutTransaction {
+                    transaction_type: self.transaction_type.to_uppercase(),
+                    product: String::new(),
+                    started_date: started_date.clone(),
+                    completed_date: completed_date.clone(),
+                    description,
+                    amount: leg.amount.to_string(),
+                    fee: leg
+                        .fee
+                        .as_ref()
+                        .map_or("0".to_owned(), |fee| fee.to_string()),
+                    currency: leg.currency.clone(),
+                    state: self.state.to_uppercase(),
+                    balance: leg
+                        .balance
+                        .as_ref()
+                        .map(|balance| balance.to_string())
+                        .unwrap_or_default(),
+                    category: self.category_code(),
+                };
+                Ok((code, record))
+            })
+            .collect()
+    }
+
+    /// The timestamp in the target timezone, formatted like the CSV export (or the configured `date_format`).
+    fn timestamp(&self, value: &str, config: &ImporterConfig) -> Result<String> {
+        let date_time = DateTime::parse_from_rfc3339(value.trim()).map_err(|e| {
+            ImportError::InputParse(format!("invalid timestamp \"{}\": {}", value, e))
+        })?;
+        let format = date_format(config).unwrap_or("%Y-%m-%d %H:%M:%S");
+        Ok(local_date_time(&date_time, &config.timezone)
+            .format(format)
+            .to_string())
+    }
+
+    fn category_code(&self) -> Option<String> {
+        self.merchant
+            .as_ref()
+            .and_then(|m| m.category_code.clone())
+            .filter(|code| !code.is_empty())
+    }
+
+    /// the reference of transfers
+    fn note(&self) -> Option<String> {
+        self.reference.clone().filter(|r| !r.trim().is_empty())
+    }
+
+    fn tags(&self) -> Vec<Tag> {
+        let mut tags = Vec::new();
+        if let Some(category) = self.category_code() {
+            tags.push(Tag::new_val("merchant_category".to_owned(), category));
+        }
+        // hledger ends tag values at a comma, city and country are separated by a space
+        let location = self
+            .merchant
+            .iter()
+            .flat_map(|m| [&m.city, &m.country])
+            .flatten()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !location.is_empty() {
+            tags.push(Tag::new_val("location".to_owned(), location));
+        }
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, TransactionState};
+
+    use super::*;
+
+    /// statement with a card payment abroad and a transfer (shape of the Revolut statement API)
+    const STATEMENT: &str = r#"[
+  {
+    "id": "663a1c2e-4b3f-a1d2-9c51-3f0e8d2b7a10",
+    "type": "card_payment",
+    "state": "completed",
+    "created_at": "2024-05-01T21:45:12.000Z",
+    "completed_at": "2024-05-02T08:10:00.000Z",
+    "merchant": {
+      "name": "Burger Place",
+      "city": "Prague",
+      "country": "CZE",
+      "category_code": "5814"
+    },
+    "legs": [
+      {
+        "leg_id": "663a1c2e-4b3f-a1d2-9c51-3f0e8d2b7a11",
+        "amount": -12.4,
+        "fee": 0.5,
+        "currency": "EUR",
+        "description": "Burger Place",
+        "balance": 87.1
+      }
+    ]
+  },
+  {
+    "id": "663b2d3f-5c40-b2e3-ad62-4f1f9e3c8b20",
+    "type": "transfer",
+    "state": "pending",
+    "created_at": "2024-05-03T09:00:00.000Z",
+    "reference": "Rent May",
+    "legs": [
+      {
+        "leg_id": "663b2d3f-5c40-b2e3-ad62-4f1f9e3c8b21",
+        "amount": -650,
+        "currency": "EUR",
+        "description": "To Jane Landlord",
+        "balance": 12.1
+      }
+    ]
+  }
+]"#;
+
+    fn test_config(revolut: &str) -> ImporterConfig {
+        let config_str = format!(
+            "ibans = []
+        cards = []
+        mapping = [{{ search = \"Landlord\", account = \"Expenses:Rent\" }}]
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Expenses:Unknown\"
+        timezone = \"+02:00\"
+
+        [[categories]]
+        pattern = \"5814\"
+        account = \"Expenses:Restaurants\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+
+        [revolut]
+        account = \"Assets:Revolut\"
+        fee_account = \"Expenses:Fees\"
+        {}
+        ",
+            revolut
+        );
+        toml::from_str::<ImporterConfig>(&config_str).expect("TOML parsing failed")
+    }
+
+    /// imports the statement from a temporary file named after the test
+    fn import(
+        test: &str,
+        config: &ImporterConfig,
+        known_codes: &HashSet<String>,
+    ) -> Vec<Transaction> {
+        let path = std::env::temp_dir().join(format!("hledger-import-revolut-json-{}.json", test));
+        std::fs::write(&path, STATEMENT).unwrap();
+        let transactions = RevolutJsonImporter::new()
+            .parse(&path, config, known_codes)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        transactions
+    }
+
+    fn eur(amount: &str) -> Option<AmountAndCommodity> {
+        Some(AmountAndCommodity::new(
+            amount.parse::<BigDecimal>().unwrap(),
+            "EUR".to_owned(),
+        ))
+    }
+
+    #[test]
+    fn card_payment() {
+        let config = test_config("");
+        let transactions = import("card_payment", &config, &HashSet::new());
+        assert_eq!(transactions.len(), 2);
+
+        let transaction = &transactions[0];
+        // completed in the morning, started in the evening before (in the target timezone)
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 5, 2).unwrap()
+        );
+        assert_eq!(
+            transaction.code,
+            Some("663a1c2e-4b3f-a1d2-9c51-3f0e8d2b7a10".to_owned())
+        );
+        assert_eq!(transaction.payee, "Burger Place");
+        assert_eq!(transaction.state, TransactionState::Cleared);
+        let tags = transaction
+            .tags
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![
+                "valuation: 2024-05-01 23:45:12",
+                "revolut_type: CARD_PAYMENT",
+                "merchant_category: 5814",
+                "location: Prague CZE",
+            ]
+        );
+
+        let postings = &transaction.postings;
+        assert_eq!(postings.len(), 4);
+        assert_eq!(&postings[0].account, "Assets:Revolut");
+        assert_eq!(postings[0].amount, eur("-12.4"));
+        assert_eq!(&postings[1].account, "Assets:Revolut");
+        assert_eq!(postings[1].amount, eur("-0.5"));
+        assert_eq!(&postings[2].account, "Expenses:Fees");
+        assert_eq!(postings[2].amount, eur("0.5"));
+        // the merchant category is mapped through `categories`
+        assert_eq!(&postings[3].account, "Expenses:Restaurants");
+        assert_eq!(postings[3].amount, None);
+    }
+
+    #[test]
+    fn transfer() {
+        let config = test_config("");
+        let transactions = import("transfer", &config, &HashSet::new());
+
+        let transaction = &transactions[1];
+        assert_eq!(
+            transaction.date,
+            NaiveDate::from_ymd_opt(2024, 5, 3).unwrap()
+        );
+        assert_eq!(transaction.payee, "To Jane Landlord");
+        assert_eq!(transaction.note, Some("Rent May".to_owned()));
+        assert_eq!(transaction.state, TransactionState::Pending);
+        assert!(!transaction.tags.iter().any(|t| t.name == "location"));
+        assert_eq!(transaction.postings.len(), 2);
+        assert_eq!(&transaction.postings[0].account, "Assets:Revolut");
+        assert_eq!(transaction.postings[0].amount, eur("-650"));
+        assert_eq!(&transaction.postings[1].account, "Expenses:Rent");
+
+        // known transactions are skipped, the closing balance is taken from the completed card payment
+        let config = test_config("emit_closing_assertion = true");
+        let known_codes = HashSet::from(["663b2d3f-5c40-b2e3-ad62-4f1f9e3c8b20".to_owned()]);
+        let transactions = import("transfer", &config, &known_codes);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[1].payee, "Revolut closing balance");
+        assert_eq!(transactions[1].postings[0].balance_assertion, eur("87.1"));
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{ImportError, Result};
+
+/// Resolves the input path to the list of files to be imported.
+///
+/// A file is imported as is, a glob pattern is rejected then. For a directory, all files (of the directory
+/// and its subdirectories) whose path relative to the directory matches the glob pattern are imported in
+/// filename order. Without a pattern, all files directly within the directory are imported.
+/// Hidden files and directories (e.g. `.DS_Store` or lock files like `.~lock.export.csv#`) are skipped.
+pub fn input_files(input: &Path, glob: &Option<String>) -> Result<Vec<PathBuf>> {
+    if !input.is_dir() {
+        if glob.is_some() {
+            return Err(ImportError::GlobWithFile(input.to_path_buf()));
+        }
+        return Ok(vec![input.to_path_buf()]);
+    }
+
+    let pattern = glob.as_deref().unwrap_or("*");
+    let mut files = Vec::new();
+    collect_files(input, &mut files)?;
+    let total = files.len();
+
+    let mut files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|f| {
+            f.strip_prefix(input)
+                .map(|relative| glob_match(pattern, &relative_str(relative)))
+                .unwrap_or(false)
+        })
+        .collect();
+    files.sort();
+
+    log::info!(
+        "importing {} of {} file(s) in \"{}\" matching \"{}\"",
+        files.len(),
+        total,
+        input.display(),
+        pattern
+    );
+    Ok(files)
+}
+
+/// Input files without any content (or only whitespace) contain no transactions.
+/// Instead of failing to parse them, the importers warn and return no transactions.
+pub fn is_empty_input(input_file: &Path, content: &[u8]) -> bool {
+    let empty = content.iter().all(u8::is_ascii_whitespace);
+    if empty {
+        log::warn!(
+            "input file \"{}\" is empty, no transactions imported",
+            input_file.display()
+        );
+    }
+    empty
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).map_err(|_| ImportError::InputFileRead(dir.into()))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|_| ImportError::InputFileRead(dir.into()))?
+            .path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// relative path with `/` as separator, independent of the platform
+fn relative_str(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Matches a path against a glob pattern.
+/// `*` matches any sequence of characters except `/`, `?` matches a single character except `/`.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+
+    let (mut p, mut s) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while s < path.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, s));
+                p += 1;
+            }
+            Some('?') if path[s] != '/' => {
+                p += 1;
+                s += 1;
+            }
+            Some(c) if *c == path[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` consume one more character (but never a `/`)
+                Some((star_p, star_s)) if path[star_s] != '/' => {
+                    backtrack = Some((star_p, star_s + 1));
+                    p = star_p + 1;
+                    s = star_s + 1;
+                }
+                _ => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.csv", "2024-01.csv"));
+        assert!(!glob_match("*.csv", "2024-01.json"));
+        assert!(!glob_match("*.csv", "2024/01.csv"));
+        assert!(glob_match("2024/*.csv", "2024/01.csv"));
+        assert!(glob_match("2024/??.csv", "2024/01.csv"));
+        assert!(!glob_match("2024/?.csv", "2024/01.csv"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("*-*.csv", "a-b-c.csv"));
+    }
+
+    #[test]
+    fn empty_input() {
+        let path = Path::new("export.csv");
+        assert!(is_empty_input(path, b""));
+        assert!(is_empty_input(path, b" \r\n\t\n"));
+        assert!(!is_empty_input(path, b"Type,Product\n"));
+    }
+
+    #[test]
+    fn files_in_directory() {
+        let dir = std::env::temp_dir().join("hledger-import-input-files-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("2024")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        for file in [
+            "2024/02.csv",
+            "2024/01.csv",
+            "2024/notes.txt",
+            "2024/.~lock.01.csv#",
+            "2024/.DS_Store",
+            ".git/export.csv",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let files = input_files(&dir, &Some("2024/*.csv".to_owned())).unwrap();
+        assert_eq!(
+            files,
+            vec![dir.join("2024/01.csv"), dir.join("2024/02.csv")]
+        );
+
+        let files = input_files(&dir.join("2024"), &None).unwrap();
+        assert_eq!(files.len(), 3);
+
+        let file = dir.join("2024/01.csv");
+        assert_eq!(input_files(&file, &None).unwrap(), vec![file.clone()]);
+        assert!(matches!(
+            input_files(&file, &Some("*.csv".to_owned())),
+            Err(ImportError::GlobWithFile(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::{BufRead, Write};
+
+use crate::config::ImporterConfig;
+use crate::error::{ImportError, Result};
+use crate::hledger::output::{Posting, Transaction};
+use crate::validation::is_unclassified;
+
+/// Asks the user for the offset account of every transaction that could not be classified
+/// (e.g. posted to the fallback account). An empty answer keeps the transaction as it is.
+/// Returns mapping rules for the payees the user wants to remember, in configuration file syntax.
+pub fn classify_interactively<R: BufRead, W: Write>(
+    transactions: &mut [Transaction],
+    config: &ImporterConfig,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Vec<String>> {
+    let mut rules = Vec::new();
+    for transaction in transactions
+        .iter_mut()
+        .filter(|t| !t.postings.is_empty() && is_unclassified(t, config))
+    {
+        let amount = transaction
+            .postings
+            .iter()
+            .find_map(|p| p.amount.as_ref())
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        write!(
+            output,
+            "{} {} {}\naccount (empty to skip): ",
+            transaction.date.format("%Y-%m-%d"),
+            &transaction.payee,
+            amount
+        )
+        .map_err(prompt_error)?;
+        output.flush().map_err(prompt_error)?;
+
+        let account = read_answer(input)?;
+        if account.is_empty() {
+            continue;
+        }
+        set_offset_account(transaction, &account, config);
+
+        write!(
+            output,
+            "add mapping rule for \"{}\"? [y/N] ",
+            &transaction.payee
+        )
+        .map_err(prompt_error)?;
+        output.flush().map_err(prompt_error)?;
+        if read_answer(input)?.eq_ignore_ascii_case("y") {
+            rules.push(mapping_rule(&regex::escape(&transaction.payee), &account));
+        }
+    }
+    Ok(rules)
+}
+
+/// mapping rule as inline table, the values are quoted and escaped as TOML strings
+fn mapping_rule(search: &str, account: &str) -> String {
+    format!(
+        "{{ search = {}, account = {} }},",
+        toml::Value::String(search.to_owned()),
+        toml::Value::String(account.to_owned())
+    )
+}
+
+/// replaces the fallback (or empty) offset posting, or adds an offset posting if there is none
+fn set_offset_account(transaction: &mut Transaction, account: &str, config: &ImporterConfig) {
+    let offset = transaction
+        .postings
+        .iter_mut()
+        .find(|p| p.account.is_empty() || config.fallback_account.as_ref() == Some(&p.account));
+    match offset {
+        Some(posting) => posting.account = account.to_owned(),
+        None => transaction.postings.push(Posting {
+            account: account.to_owned(),
+            amount: None,
+            comment: None,
+            tags: Vec::new(),
+            price: None,
+            balance_assertion: None,
+        }),
+    }
+}
+
+fn read_answer<R: BufRead>(input: &mut R) -> Result<String> {
+    let mut line = String::new();
+    input.read_line(&mut line).map_err(prompt_error)?;
+    Ok(line.trim().to_owned())
+}
+
+fn prompt_error(error: std::io::Error) -> ImportError {
+    ImportError::Prompt(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, TransactionState};
+
+    use super::*;
+
+    #[test]
+    fn scripted_classification() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Equity:Unassigned\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let posting = |account: &str, amount: Option<i32>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| AmountAndCommodity::new(BigDecimal::from(a), "EUR".to_owned())),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let transaction = |payee: &str, postings: Vec<Posting>| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings,
+        };
+        let mut transactions = vec![
+            transaction(
+                "Store",
+                vec![
+                    posting("Assets:Bank", Some(-10)),
+                    posting("Expenses:Groceries", None),
+                ],
+            ),
+            transaction(
+                "Bakery (Main St.)",
+                vec![
+                    posting("Assets:Bank", Some(-3)),
+                    posting("Equity:Unassigned", None),
+                ],
+            ),
+            transaction("Kiosk", vec![posting("Assets:Bank", Some(-2))]),
+            transaction(
+                "Unknown",
+                vec![
+                    posting("Assets:Bank", Some(-1)),
+                    posting("Equity:Unassigned", None),
+                ],
+            ),
+        ];
+
+        let mut input = "Expenses:Bakery\ny\nExpenses:Newspapers\nn\n\n".as_bytes();
+        let mut output = Vec::new();
+        let rules =
+            classify_interactively(&mut transactions, &config, &mut input, &mut output).unwrap();
+
+        let accounts: Vec<Vec<&str>> = transactions
+            .iter()
+            .map(|t| t.postings.iter().map(|p| p.account.as_str()).collect())
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                vec!["Assets:Bank", "Expenses:Groceries"],
+                vec!["Assets:Bank", "Expenses:Bakery"],
+                vec!["Assets:Bank", "Expenses:Newspapers"],
+                vec!["Assets:Bank", "Equity:Unassigned"],
+            ]
+        );
+        assert_eq!(
+            rules,
+            vec!["{ search = 'Bakery \\(Main St\\.\\)', account = \"Expenses:Bakery\" },"]
+        );
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("2024-05-10 Bakery (Main St.) -3 EUR\naccount"));
+        assert!(!output.contains("Store"));
+    }
+
+    #[test]
+    fn mapping_rule_with_quotes() {
+        let search = regex::escape("Joe's \"Bar\" \\ Grill");
+        let account = "Expenses:\"Dining\"";
+        let rule = mapping_rule(&search, account);
+        let parsed: toml::Table =
+            toml::from_str(&format!("mapping = [{}]", rule.trim_end_matches(','))).unwrap();
+        let mapping = &parsed["mapping"][0];
+        assert_eq!(mapping["search"].as_str(), Some(search.as_str()));
+        assert_eq!(mapping["account"].as_str(), Some(account));
+    }
+}
@@ -0,0 +1,270 @@
+//! Import of bank and credit card exports into hledger transactions.
+//!
+//! The command line program `hledger-import` is built on top of this library, other programs
+//! (e.g. graphical frontends) can use [`import`] to convert an export file into [`Transaction`]s.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use clap::ValueEnum;
+use config::ImporterConfig;
+use error::Result;
+use hledger::{
+    accounts::canonicalize_accounts,
+    currency::{normalize_commodities, override_commodity},
+    hasher::assign_hash_codes,
+};
+use validation::{check_commodities, drop_dust};
+use warnings::UnparsedRows;
+
+pub use hledger::output::Transaction;
+
+pub mod config;
+pub mod error;
+pub mod hledger;
+pub mod importers;
+pub mod input;
+pub mod interactive;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod split;
+pub mod state;
+pub mod transfers;
+pub mod validation;
+pub mod warnings;
+
+pub trait HledgerImporter {
+    fn parse(
+        &self,
+        input_file: &std::path::Path,
+        config: &ImporterConfig,
+        known_codes: &HashSet<String>,
+    ) -> Result<Vec<Transaction>>;
+
+    /// Like [`HledgerImporter::parse`], but rows of the input file that can not be parsed are skipped,
+    /// if `unparsed` collects them. Importers without row-level parsing abort like `parse`.
+    fn parse_rows(
+        &self,
+        input_file: &std::path::Path,
+        config: &ImporterConfig,
+        known_codes: &HashSet<String>,
+        _unparsed: &mut UnparsedRows,
+    ) -> Result<Vec<Transaction>> {
+        self.parse(input_file, config, known_codes)
+    }
+
+    fn output_title(&self) -> &'static str;
+
+    /// short and stable name of the importer (e.g. for tagging transactions)
+    fn slug(&self) -> &'static str;
+
+    /// prefix of generated transaction codes (see `output.hash_codes`), the slug unless configured otherwise
+    fn code_prefix(&self, _config: &ImporterConfig) -> String {
+        self.slug().to_owned()
+    }
+
+    /// commodity formatting rules specific to this importer, in addition to the global rules
+    fn commodity_rules(&self, _config: &ImporterConfig) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The importers available in this build (depending on the enabled features)
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Importer {
+    /// Erste Bank JSON export file
+    #[cfg(feature = "erste")]
+    Erste,
+
+    /// Revolut CSV export file
+    #[cfg(feature = "revolut")]
+    Revolut,
+
+    /// Revolut JSON (statement API) export file
+    #[cfg(feature = "revolut")]
+    RevolutJson,
+
+    /// Cardcomplete XML export file
+    #[cfg(feature = "cardcomplete")]
+    Cardcomplete,
+
+    /// Flatex CSV export file (of settlement accounts)
+    #[cfg(feature = "flatex")]
+    FlatexCSV,
+
+    /// Flatex PDF invoice (of stock exchange transactions)
+    #[cfg(feature = "flatex")]
+    FlatexPDF,
+
+    /// PayPal TXT (tab-separated) transaction list
+    #[cfg(feature = "paypal")]
+    Paypal,
+}
+
+impl From<Importer> for Box<dyn HledgerImporter> {
+    fn from(val: Importer) -> Self {
+        match val {
+            #[cfg(feature = "erste")]
+            Importer::Erste => Box::new(importers::erste::HledgerErsteJsonImporter::new()),
+            #[cfg(feature = "revolut")]
+            Importer::Revolut => Box::new(importers::revolut::RevolutCsvImporter::new()),
+            #[cfg(feature = "revolut")]
+            Importer::RevolutJson => Box::new(importers::revolut::json::RevolutJsonImporter::new()),
+            #[cfg(feature = "cardcomplete")]
+            Importer::Cardcomplete => {
+                Box::new(importers::cardcomplete::CardcompleteXmlImporter::new())
+            }
+            #[cfg(feature = "flatex")]
+            Importer::FlatexCSV => Box::new(importers::flatex_csv::FlatexCsvImport::new()),
+            #[cfg(feature = "flatex")]
+            Importer::FlatexPDF => Box::new(importers::flatex_inv::FlatexPdfInvoiceImporter::new()),
+            #[cfg(feature = "paypal")]
+            Importer::Paypal => Box::new(importers::paypal::PaypalPdfImporter::new()),
+        }
+    }
+}
+
+impl Importer {
+    /// configuration section the importer requires (in addition to the common settings)
+    pub fn config_section(&self) -> &'static str {
+        // dereferenced, so that the match is exhaustive in builds without any importer as well
+        match *self {
+            #[cfg(feature = "erste")]
+            Importer::Erste => "ibans, cards",
+            #[cfg(feature = "revolut")]
+            Importer::Revolut => "[revolut]",
+            #[cfg(feature = "revolut")]
+            Importer::RevolutJson => "[revolut]",
+            #[cfg(feature = "cardcomplete")]
+            Importer::Cardcomplete => "cards",
+            #[cfg(feature = "flatex")]
+            Importer::FlatexCSV => "[flatex_csv]",
+            #[cfg(feature = "flatex")]
+            Importer::FlatexPDF => "[flatex_pdf]",
+            #[cfg(feature = "paypal")]
+            Importer::Paypal => "[paypal]",
+        }
+    }
+}
+
+/// Parses the input file with the given importer into hledger transactions.
+///
+/// Besides parsing, the per-file steps of the command line program are applied: commodities are
+/// normalized, the configured date basis is applied and (if `output.hash_codes` is set) generated
+/// codes are assigned. Transactions with a code in `known_codes` are skipped.
+///
+/// ```
+/// # #[cfg(feature = "revolut")]
+/// # {
+/// use std::collections::HashSet;
+/// use hledger_import::{config::ImporterConfig, import, Importer};
+///
+/// let dir = std::env::temp_dir().join("hledger-import-doctest");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(
+///     dir.join("config.toml"),
+///     r#"
+///     ibans = []
+///     cards = []
+///     mapping = [ { search = "Patreon", account = "Expenses:Donations" } ]
+///     creditor_and_debitor_mapping = []
+///
+///     [sepa]
+///     creditors = []
+///     mandates = []
+///
+///     [transfer_accounts]
+///     bank = "Assets:Transfers"
+///     cash = "Assets:Cash"
+///
+///     [revolut]
+///     account = "Assets:Revolut"
+///     "#,
+/// )
+/// .unwrap();
+/// std::fs::write(
+///     dir.join("revolut.csv"),
+///     "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance\n\
+///      CARD_PAYMENT,Current,2024-05-01 13:05:33,2024-05-01 16:46:56,Patreon,-24.40,0.00,EUR,COMPLETED,100.00\n",
+/// )
+/// .unwrap();
+///
+/// let config = ImporterConfig::load_from(&dir.join("config.toml")).unwrap();
+/// let transactions = import(
+///     Importer::Revolut,
+///     &dir.join("revolut.csv"),
+///     &config,
+///     &HashSet::new(),
+/// )
+/// .unwrap();
+///
+/// assert_eq!(transactions.len(), 1);
+/// assert_eq!(transactions[0].postings[1].account, "Expenses:Donations");
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// ```
+pub fn import(
+    importer: Importer,
+    input_file: &Path,
+    config: &ImporterConfig,
+    known_codes: &HashSet<String>,
+) -> Result<Vec<Transaction>> {
+    import_rows(
+        importer,
+        input_file,
+        config,
+        known_codes,
+        &mut UnparsedRows::default(),
+    )
+}
+
+/// Imports the input file like [`import`], rows that can not be parsed are skipped if `unparsed` collects them.
+pub fn import_rows(
+    importer: Importer,
+    input_file: &Path,
+    config: &ImporterConfig,
+    known_codes: &HashSet<String>,
+    unparsed: &mut UnparsedRows,
+) -> Result<Vec<Transaction>> {
+    let importer: Box<dyn HledgerImporter> = importer.into();
+    let skipped = unparsed.warnings.len();
+    let mut transactions = importer.parse_rows(input_file, config, known_codes, unparsed)?;
+    for warning in &mut unparsed.warnings[skipped..] {
+        warning
+            .fields
+            .insert("file".to_owned(), input_file.display().to_string());
+    }
+    // hashed as parsed, so that the codes of known transactions do not depend on the processing below
+    // (e.g. the date basis, commodity normalization or conversion differences)
+    if config.output.hash_codes {
+        assign_hash_codes(&mut transactions, &importer.code_prefix(config));
+        transactions.retain(|t| {
+            t.code
+                .as_ref()
+                .is_none_or(|code| !known_codes.contains(code))
+        });
+    }
+    let mut transactions = drop_dust(transactions, config);
+    if let Some(commodity) = config.override_commodity.get(importer.slug()) {
+        override_commodity(&mut transactions, commodity);
+    }
+    normalize_commodities(&mut transactions, &config.commodities);
+    check_commodities(&transactions, config)?;
+    canonicalize_accounts(&mut transactions, &config.canonical_accounts);
+    if let Some(account) = &config.conversion_difference_account {
+        transactions
+            .iter_mut()
+            .for_each(|t| t.book_conversion_difference(account));
+    }
+    let date_basis = config.date_basis(importer.slug());
+    transactions
+        .iter_mut()
+        .for_each(|t| t.apply_date_basis(date_basis));
+    // after the hash codes, so that changing the affixes keeps the codes of known transactions
+    if config.importer_notes.contains_key(importer.slug()) {
+        transactions
+            .iter_mut()
+            .for_each(|t| t.note = config.importer_note(importer.slug(), t.note.take()));
+    }
+    Ok(transactions)
+}
@@ -1,140 +1,1044 @@
-use std::collections::HashSet;
-
-use crate::hledger::deduplication::get_hledger_codes;
-use crate::hledger::output::Transaction;
-use clap::{command, Parser, ValueEnum};
-use config::ImporterConfig;
-use error::Result;
-use hledger::{format::hledger_format, output::HeaderComment};
-
-pub mod config;
-pub mod error;
-pub mod hledger;
-pub mod importers;
-
-pub trait HledgerImporter {
-    fn parse(
-        &self,
-        input_file: &std::path::Path,
-        config: &ImporterConfig,
-        known_codes: &HashSet<String>,
-    ) -> Result<Vec<Transaction>>;
-
-    fn output_title(&self) -> &'static str;
-}
-
-#[derive(Debug, Clone, ValueEnum)]
-enum Importer {
-    /// Erste Bank JSON export file
-    #[cfg(feature = "erste")]
-    Erste,
-
-    /// Revolut CSV export file
-    #[cfg(feature = "revolut")]
-    Revolut,
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-    /// Cardcomplete XML export file
-    #[cfg(feature = "cardcomplete")]
-    Cardcomplete,
+use clap::{Parser, ValueEnum};
+use hledger_import::config::{ImporterConfig, OutputConfig, WordFilter};
+use hledger_import::error::{ImportError, Result};
+use hledger_import::hledger::{
+    accounts::{account_tree, accounts},
+    deduplication::{drop_near_duplicates, get_hledger_codes, read_known_codes},
+    format::{commodity_directives, hledger_format, merge_commodity_rules, post_process},
+    output::{HeaderComment, Tag},
+    rules::RulesExport,
+};
+use hledger_import::input::input_files;
+use hledger_import::interactive::classify_interactively;
+use hledger_import::split::apply_splits;
+use hledger_import::state::ImportState;
+use hledger_import::transfers::merge_transfers;
+use hledger_import::validation::{check_empty_postings, check_unclassified, split_unclassified};
+use hledger_import::warnings::{collect_warnings, write_warnings, UnparsedRows};
+use hledger_import::{import, import_rows, HledgerImporter, Importer, Transaction};
 
-    /// Flatex CSV export file (of settlement accounts)
-    #[cfg(feature = "flatex")]
-    FlatexCSV,
+/// lists the importers that are enabled in this build, their input file type and required configuration
+fn list_importers() -> String {
+    Importer::value_variants()
+        .iter()
+        .filter_map(|importer| {
+            importer.to_possible_value().map(|value| {
+                format!(
+                    "{:<14}{:<54}config: {}\n",
+                    value.get_name(),
+                    value.get_help().map(|h| h.to_string()).unwrap_or_default(),
+                    importer.config_section()
+                )
+            })
+        })
+        .collect()
+}
 
-    /// Flatex PDF invoice (of stock exchange transactions)
-    #[cfg(feature = "flatex")]
-    FlatexPDF,
-
-    /// PayPal TXT (tab-separated) transaction list
-    #[cfg(feature = "paypal")]
-    Paypal,
-}
-
-impl From<Importer> for Box<dyn HledgerImporter> {
-    fn from(val: Importer) -> Self {
-        match val {
-            #[cfg(feature = "erste")]
-            Importer::Erste => Box::new(importers::erste::HledgerErsteJsonImporter::new()),
-            #[cfg(feature = "revolut")]
-            Importer::Revolut => Box::new(importers::revolut::RevolutCsvImporter::new()),
-            #[cfg(feature = "cardcomplete")]
-            Importer::Cardcomplete => {
-                Box::new(importers::cardcomplete::CardcompleteXmlImporter::new())
-            }
-            #[cfg(feature = "flatex")]
-            Importer::FlatexCSV => Box::new(importers::flatex_csv::FlatexCsvImport::new()),
-            #[cfg(feature = "flatex")]
-            Importer::FlatexPDF => Box::new(importers::flatex_inv::FlatexPdfInvoiceImporter::new()),
-            #[cfg(feature = "paypal")]
-            Importer::Paypal => Box::new(importers::paypal::PaypalPdfImporter::new()),
-        }
-    }
+/// the importer and optional features (cargo features) this build was compiled with
+fn enabled_features() -> Vec<&'static str> {
+    [
+        ("cardcomplete", cfg!(feature = "cardcomplete")),
+        ("erste", cfg!(feature = "erste")),
+        ("flatex", cfg!(feature = "flatex")),
+        ("paypal", cfg!(feature = "paypal")),
+        ("revolut", cfg!(feature = "revolut")),
+        ("remote", cfg!(feature = "remote")),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| feature)
+    .collect()
+}
+
+/// reports the version and the enabled features of this build
+fn features_report() -> String {
+    format!(
+        "{} {}\nfeatures: {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        enabled_features().join(", ")
+    )
+}
+
+/// reports the path of the configuration file that would be loaded and whether it exists
+fn config_path_report() -> Result<String> {
+    let path = ImporterConfig::path()?;
+    let status = if path.is_file() {
+        "exists"
+    } else {
+        "not found"
+    };
+    Ok(format!("{} ({})\n", path.display(), status))
 }
 
 /// bank data and credit card import programm for hledger accounting
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct ImporterArgs {
-    /// path to the input file to be imported to hledger
-    #[arg(short, long)]
-    input_file: std::path::PathBuf,
+    /// path to the input file (or a directory of input files) to be imported to hledger,
+    /// or an http(s) URL of the input file (with the `remote` feature)
+    #[arg(short, long, required_unless_present_any = ["list_importers", "print_config_path", "features"])]
+    input_file: Option<std::path::PathBuf>,
+
+    /// import all files of the input directory that match this pattern (e.g. '2024/*.csv'), in filename order;
+    /// hidden files are skipped, a file as input can not be combined with a pattern
+    #[arg(long)]
+    glob: Option<String>,
 
     /// file type of given input file
-    #[arg(short = 't', long)]
-    file_type: Importer,
+    #[arg(short = 't', long, required_unless_present_any = ["list_importers", "print_config_path", "features"])]
+    file_type: Option<Importer>,
+
+    /// print the importers available in this build, their file types and required configuration sections
+    #[arg(long, default_value_t = false)]
+    list_importers: bool,
+
+    /// print the version and the importer features enabled in this build
+    #[arg(long, default_value_t = false)]
+    features: bool,
+
+    /// print the duration of the import phases (configuration, deduplication query, parsing, processing, formatting) to stderr
+    #[arg(long, default_value_t = false)]
+    profile: bool,
+
+    /// path to the configuration file, overrides the environment variable HLEDGER_IMPORT_CONFIG
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// print the path of the configuration file that would be used and whether it exists
+    #[arg(long, default_value_t = false)]
+    print_config_path: bool,
 
     /// try to avoid duplicate imports by reading in the known codes from hledger
     #[arg(short, long, default_value_t = false)]
     deduplicate: bool,
+
+    /// skip transactions whose payee and amount match a transaction of the journal at most this many days apart
+    /// (e.g. re-issued under another reference), queries hledger
+    #[arg(long, value_name = "DAYS")]
+    fuzzy_deduplicate: Option<u64>,
+
+    /// skip transactions whose code is listed in this file (one code per line, e.g. saved `hledger codes` output), combined with --deduplicate
+    #[arg(long)]
+    known_codes_file: Option<std::path::PathBuf>,
+
+    /// only print how many transactions would be imported (in total and after deduplication, including --fuzzy-deduplicate,
+    /// --since-last-import, --merge-transfers and --review-file, which are not written) instead of the journal
+    #[arg(long, default_value_t = false, conflicts_with_all = ["emit_rules", "account_tree", "split_by_asset_account", "interactive", "warnings_json"])]
+    count_only: bool,
+
+    /// write transactions that could not be classified (e.g. posted to the fallback account) to this file instead
+    #[arg(long)]
+    review_file: Option<std::path::PathBuf>,
+
+    /// exit with a non-zero exit code if any transaction could not be classified (the output is still written)
+    #[arg(long, default_value_t = false)]
+    fail_on_fallback: bool,
+
+    /// character encoding of CSV input files (e.g. latin1, windows-1252, utf-8), overrides the configuration
+    #[arg(long)]
+    input_encoding: Option<String>,
+
+    /// reject CSV input files with missing or unexpected columns, overrides the configuration
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// strip trailing reference noise (as configured in filter.payee_trailing) from payees and keep it as payee_detail tag
+    #[arg(long, default_value_t = false)]
+    payee_only: bool,
+
+    /// write the transactions to this CSV file together with a rules file for `hledger import` (instead of printing the journal)
+    #[arg(long)]
+    emit_rules: Option<std::path::PathBuf>,
+
+    /// only print the tree of accounts the import would post to (instead of the journal)
+    #[arg(long, default_value_t = false)]
+    account_tree: bool,
+
+    /// ask for the account of every transaction that could not be classified (prompts on stderr)
+    #[arg(long, default_value_t = false)]
+    interactive: bool,
+
+    /// only import transactions dated on or after the day of the latest transaction of the previous import
+    /// (of the same file type); combine with --deduplicate, since that day is imported again
+    #[arg(long, default_value_t = false)]
+    since_last_import: bool,
+
+    /// do not print the header comment, only the transactions (e.g. to concatenate the output)
+    #[arg(long, default_value_t = false)]
+    no_header: bool,
+
+    /// write the issues of the import (e.g. transactions posted to the fallback account) as JSON to this file,
+    /// rows of CSV files that can not be parsed are skipped and reported instead of aborting the import
+    #[arg(long)]
+    warnings_json: Option<std::path::PathBuf>,
+
+    /// write the transactions to one journal per asset account (e.g. assets-revolut.journal) in this directory,
+    /// existing journals are not replaced without --overwrite
+    #[arg(long, conflicts_with_all = ["emit_rules", "account_tree"])]
+    split_by_asset_account: Option<std::path::PathBuf>,
+
+    /// replace existing journals written with --split-by-asset-account
+    #[arg(long, default_value_t = false, requires = "split_by_asset_account")]
+    overwrite: bool,
+
+    /// merge transfers between your own accounts that appear in several input files into one transaction
+    #[arg(long, default_value_t = false)]
+    merge_transfers: bool,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = ImporterArgs::parse();
-
-    let config = match ImporterConfig::load() {
-        Ok(config) => config,
+    // the library reports through `log`, the messages are printed like the ones of the binary
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+    match run(args) {
+        Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             eprintln!("[ERROR] {}", e);
-            return;
+            ExitCode::FAILURE
         }
-    };
+    }
+}
 
-    let codes = if args.deduplicate {
-        match get_hledger_codes(&config.hledger) {
-            Ok(codes) => codes,
-            Err(e) => {
-                eprintln!("[ERROR] {}", e);
-                return;
-            }
+// without any importer feature, `Importer` has no variants and the import itself is unreachable
+#[cfg_attr(
+    not(any(
+        feature = "erste",
+        feature = "revolut",
+        feature = "cardcomplete",
+        feature = "flatex",
+        feature = "paypal"
+    )),
+    allow(unreachable_code, unused_variables, unused_mut)
+)]
+fn run(args: ImporterArgs) -> Result<()> {
+    if args.list_importers {
+        print!("{}", list_importers());
+        return Ok(());
+    }
+    if args.features {
+        print!("{}", features_report());
+        return Ok(());
+    }
+    if let Some(config) = &args.config {
+        // the state file is located relative to the configuration file as well
+        std::env::set_var("HLEDGER_IMPORT_CONFIG", config);
+    }
+    if args.print_config_path {
+        print!("{}", config_path_report()?);
+        return Ok(());
+    }
+    let (Some(input_file), Some(file_type)) = (&args.input_file, &args.file_type) else {
+        unreachable!("input file and file type are required by the argument parser");
+    };
+    // the downloaded file is removed at the end of the import
+    #[cfg(feature = "remote")]
+    let download = match input_file.to_str() {
+        Some(url) if hledger_import::remote::is_url(input_file) => {
+            Some(hledger_import::remote::download(url)?)
         }
+        _ => None,
+    };
+    #[cfg(feature = "remote")]
+    let input_file = &download
+        .as_ref()
+        .map_or(input_file.clone(), |d| d.path().to_path_buf());
+
+    let mut profile = Profile::new(args.profile);
+    let mut config = ImporterConfig::load()?;
+    if args.input_encoding.is_some() {
+        config.input_encoding.clone_from(&args.input_encoding);
+    }
+    config.strict |= args.strict;
+    profile.lap("configuration");
+
+    let mut codes = if args.deduplicate {
+        get_hledger_codes(&config.hledger, config.output.suppress_code)?
     } else {
         HashSet::new()
     };
+    if let Some(codes_file) = &args.known_codes_file {
+        codes.extend(read_known_codes(codes_file)?);
+    }
+    profile.lap("deduplication query");
 
-    let importer: Box<dyn HledgerImporter> = args.file_type.into();
-    match importer.parse(&args.input_file, &config, &codes) {
-        Ok(transactions) => {
-            let transactions: Vec<String> = transactions.iter().map(|t| t.to_string()).collect();
-            let transactions = transactions.join("\n");
-
-            let transactions = match hledger_format(
-                &config.hledger,
-                &transactions,
-                &config.commodity_formatting_rules,
-            ) {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("[ERROR] {}", e);
-                    return;
-                }
-            };
-
-            println!("{}", HeaderComment::new(importer.output_title()));
-            println!("{}", transactions);
-            println!();
+    let importer_name = file_type
+        .to_possible_value()
+        .map(|v| v.get_name().to_owned())
+        .unwrap_or_default();
+    let importer: Box<dyn HledgerImporter> = file_type.clone().into();
+    // rows that can not be parsed are reported as warnings instead of aborting the import
+    let mut unparsed = match args.warnings_json {
+        Some(_) => UnparsedRows::collecting(),
+        None => UnparsedRows::default(),
+    };
+    let mut transactions = Vec::new();
+    // the number of parsed transactions before deduplication, with --count-only
+    let mut total = None;
+    if args.count_only {
+        let files = input_files(input_file, &args.glob)?;
+        let (count, new) = count_transactions(file_type, &files, &config, codes)?;
+        total = Some(count);
+        transactions = new;
+    } else {
+        for input_file in input_files(input_file, &args.glob)? {
+            let parsed = import_rows(
+                file_type.clone(),
+                &input_file,
+                &config,
+                &codes,
+                &mut unparsed,
+            )?;
+            // overlapping exports must not produce duplicates
+            codes.extend(parsed.iter().filter_map(|t| t.code.clone()));
+            transactions.extend(parsed);
         }
-        Err(e) => {
-            eprintln!("[ERROR] {}", e);
+    }
+    if let Some(days) = args.fuzzy_deduplicate {
+        transactions = drop_near_duplicates(&config.hledger, transactions, days)?;
+    }
+    profile.lap("read and parse");
+    if let Some(warnings_file) = &args.warnings_json {
+        let mut warnings = unparsed.warnings;
+        warnings.extend(collect_warnings(&transactions, &config));
+        write_warnings(warnings_file, &warnings)?;
+    }
+
+    let state = if args.since_last_import {
+        let path = ImportState::path(&config)?;
+        let mut state = ImportState::load(&path)?;
+        let all_transactions = transactions;
+        transactions = state.since_last_import(&importer_name, all_transactions.clone());
+        state.update(&importer_name, &all_transactions);
+        Some((path, state))
+    } else {
+        None
+    };
+    if args.merge_transfers {
+        transactions = merge_transfers(transactions, &config.transfer_accounts);
+    }
+    apply_splits(&mut transactions, &config.split)?;
+    normalize_amounts(&mut transactions, &config.output);
+    if config.output.tag_importer {
+        tag_importer(&mut transactions, importer.as_ref());
+    }
+    if args.payee_only {
+        normalize_payees(&mut transactions, &config.filter)?;
+    }
+    if !config.output.suppress_tags.is_empty() {
+        transactions
+            .iter_mut()
+            .for_each(|t| t.remove_tags(&config.output.suppress_tags));
+    }
+    let mut transactions = check_empty_postings(transactions, config.empty_postings)?;
+    if args.interactive {
+        let rules = classify_interactively(
+            &mut transactions,
+            &config,
+            &mut std::io::stdin().lock(),
+            &mut std::io::stderr(),
+        )?;
+        if !rules.is_empty() {
+            eprintln!("[INFO] add the following rules to \"mapping\" in your configuration file:");
+            rules.iter().for_each(|rule| eprintln!("  {}", rule));
+        }
+    }
+    if args.account_tree {
+        print!("{}", account_tree(&accounts(&transactions)));
+        return Ok(());
+    }
+    let unclassified = check_unclassified(&transactions, &config);
+    profile.lap("processing");
+
+    let transactions = match &args.review_file {
+        Some(review_file) => {
+            let (classified, unclassified) = split_unclassified(transactions, &config);
+            if total.is_none() {
+                let review = render_journal(&config, importer.as_ref(), &unclassified, true)?;
+                std::fs::write(review_file, review)
+                    .map_err(|_| ImportError::OutputFileWrite(review_file.clone()))?;
+            }
+            classified
+        }
+        None => transactions,
+    };
+    if let Some(total) = total {
+        println!("total: {}\nnew: {}", total, transactions.len());
+        return Ok(());
+    }
+
+    match (&args.emit_rules, &args.split_by_asset_account) {
+        (Some(csv_file), _) => RulesExport::new(&transactions).write(csv_file)?,
+        (None, Some(dir)) => {
+            for (file_name, transactions) in group_by_asset_account(transactions)? {
+                let journal =
+                    render_journal(&config, importer.as_ref(), &transactions, !args.no_header)?;
+                write_journal(&dir.join(file_name), &journal, args.overwrite)?;
+            }
+        }
+        (None, None) => print!(
+            "{}",
+            render_journal(&config, importer.as_ref(), &transactions, !args.no_header)?
+        ),
+    }
+    profile.lap("format and output");
+    if let Some(report) = profile.report() {
+        eprint!("{}", report);
+    }
+
+    if let Some((path, state)) = state {
+        state.save(&path)?;
+    }
+
+    if args.fail_on_fallback {
+        unclassified?;
+    }
+    Ok(())
+}
+
+/// Prints the messages of the library to stderr, prefixed with their level (e.g. `[WARN]`).
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Durations of the phases of an import, measured from one lap to the next (if enabled).
+struct Profile {
+    enabled: bool,
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// records the time since the previous lap as duration of the given phase
+    fn lap(&mut self, phase: &'static str) {
+        if self.enabled {
+            let now = Instant::now();
+            self.phases.push((phase, now - self.last));
+            self.last = now;
         }
+    }
+
+    /// one line per phase and the total, `None` if profiling is not enabled
+    fn report(&self) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        let mut report = String::new();
+        for (phase, duration) in &self.phases {
+            report.push_str(&format!(
+                "[PROFILE] {:<20}{:>10.3} ms\n",
+                phase,
+                duration.as_secs_f64() * 1000.0
+            ));
+        }
+        let total: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        report.push_str(&format!(
+            "[PROFILE] {:<20}{:>10.3} ms\n",
+            "total",
+            total.as_secs_f64() * 1000.0
+        ));
+        Some(report)
+    }
+}
+
+/// Counts the transactions of the input files and returns the ones without known codes
+/// (including codes of previous input files, like the import itself).
+#[cfg_attr(
+    not(any(
+        feature = "erste",
+        feature = "revolut",
+        feature = "cardcomplete",
+        feature = "flatex",
+        feature = "paypal"
+    )),
+    allow(unreachable_code, unused_variables, unused_mut)
+)]
+fn count_transactions(
+    importer: &Importer,
+    input_files: &[std::path::PathBuf],
+    config: &ImporterConfig,
+    mut codes: HashSet<String>,
+) -> Result<(usize, Vec<Transaction>)> {
+    let (mut total, mut new) = (0, Vec::new());
+    for input_file in input_files {
+        let parsed = import(importer.clone(), input_file, config, &HashSet::new())?;
+        total += parsed.len();
+        let parsed: Vec<Transaction> = parsed
+            .into_iter()
+            .filter(|t| t.code.as_ref().is_none_or(|code| !codes.contains(code)))
+            .collect();
+        codes.extend(parsed.iter().filter_map(|t| t.code.clone()));
+        new.extend(parsed);
+    }
+    Ok((total, new))
+}
+
+/// rounds amounts (if configured) and removes trailing zeros, unless the precision of the input should be kept
+fn normalize_amounts(transactions: &mut [Transaction], output: &OutputConfig) {
+    if let Some(decimals) = output.amount_decimals {
+        let mode = output.rounding_mode.into();
+        transactions
+            .iter_mut()
+            .for_each(|t| t.round_amounts(decimals, mode));
+    }
+    if !output.keep_amount_precision {
+        transactions.iter_mut().for_each(|t| t.normalize_amounts());
+    }
+}
+
+/// records the importer that produced the transactions as `bank` tag
+fn tag_importer(transactions: &mut [Transaction], importer: &dyn HledgerImporter) {
+    for transaction in transactions {
+        transaction
+            .tags
+            .push(Tag::new_val("bank".to_owned(), importer.slug().to_owned()));
+    }
+}
+
+/// strips trailing reference noise from the payees and keeps it as `payee_detail` tag
+fn normalize_payees(transactions: &mut [Transaction], filter: &WordFilter) -> Result<()> {
+    for transaction in transactions {
+        let (payee, detail) = filter.strip_payee_trailing(&transaction.payee)?;
+        if let Some(detail) = detail {
+            transaction.payee = payee;
+            transaction
+                .tags
+                .push(Tag::new_val("payee_detail".to_owned(), detail));
+        }
+    }
+    Ok(())
+}
+
+/// Groups the transactions by their asset account (the first posting, as produced by the importers).
+/// The journal file of an account is named after the account, e.g. `assets-revolut.journal`.
+fn group_by_asset_account(
+    transactions: Vec<Transaction>,
+) -> Result<BTreeMap<String, Vec<Transaction>>> {
+    let mut files: BTreeMap<String, Vec<Transaction>> = BTreeMap::new();
+    // the account of each file, different accounts must not end up in the same file
+    let mut accounts: BTreeMap<String, String> = BTreeMap::new();
+    for transaction in transactions {
+        let account = transaction
+            .postings
+            .first()
+            .map(|p| p.account.clone())
+            .unwrap_or("unknown".to_owned());
+        let file_name = format!(
+            "{}.journal",
+            account
+                .to_lowercase()
+                .split(':')
+                .map(|part| part.split_whitespace().collect::<Vec<_>>().join("_"))
+                .collect::<Vec<_>>()
+                .join("-")
+                .replace(['/', '\\'], "_")
+        );
+        match accounts.get(&file_name) {
+            Some(other) if *other != account => {
+                return Err(ImportError::OutputFileCollision(
+                    other.clone(),
+                    account,
+                    file_name,
+                ));
+            }
+            Some(_) => {}
+            None => {
+                accounts.insert(file_name.clone(), account);
+            }
+        }
+        files.entry(file_name).or_default().push(transaction);
+    }
+    Ok(files)
+}
+
+/// writes the journal to a new file, existing files are only replaced with `overwrite`
+fn write_journal(path: &std::path::Path, journal: &str, overwrite: bool) -> Result<()> {
+    let file = if overwrite {
+        std::fs::File::create(path)
+    } else {
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+    };
+    let mut file = file.map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => ImportError::OutputFileExists(path.to_owned()),
+        _ => ImportError::OutputFileWrite(path.to_owned()),
+    })?;
+    file.write_all(journal.as_bytes())
+        .map_err(|_| ImportError::OutputFileWrite(path.to_owned()))
+}
+
+/// renders the transactions as hledger journal (including the header comment, if requested)
+fn render_journal(
+    config: &ImporterConfig,
+    importer: &dyn HledgerImporter,
+    transactions: &[Transaction],
+    header: bool,
+) -> Result<String> {
+    // sorted when rendered, so that the asset account (the first posting) is still known when grouping
+    let transactions: Vec<String> = transactions
+        .iter()
+        .map(|t| {
+            if config.output.sort_postings {
+                let mut t = t.clone();
+                t.sort_postings();
+                t.render(&config.output)
+            } else {
+                t.render(&config.output)
+            }
+        })
+        .collect();
+    let transactions = transactions.join("\n");
+
+    let commodity_rules = merge_commodity_rules(
+        &config.commodity_formatting_rules,
+        importer.commodity_rules(config),
+    );
+    let transactions = hledger_format(&config.hledger, &transactions, &commodity_rules)?;
+
+    let transactions = match &config.output.post_process_command {
+        Some(command) => post_process(command, &transactions)?,
+        None => transactions,
+    };
+
+    let transactions = if config.output.commodity_directives {
+        with_commodity_directives(&commodity_rules, &transactions)
+    } else {
+        transactions
     };
+
+    let title = header.then(|| importer.output_title());
+    Ok(with_header(title, &transactions))
+}
+
+/// prepends the `commodity` directives of the commodity formatting rules (if any) to the formatted transactions
+fn with_commodity_directives(commodity_rules: &Option<Vec<String>>, transactions: &str) -> String {
+    let directives = commodity_directives(commodity_rules);
+    if directives.is_empty() {
+        transactions.to_owned()
+    } else {
+        format!("{}\n{}", directives, transactions)
+    }
+}
+
+/// prepends the header comment with the given title (if any) to the formatted transactions
+fn with_header(title: Option<&str>, transactions: &str) -> String {
+    match title {
+        Some(title) => format!("{}\n{}\n\n", HeaderComment::new(title), transactions),
+        None => format!("{}\n\n", transactions),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_amounts_unless_configured() {
+        use bigdecimal::BigDecimal;
+        use hledger_import::hledger::output::{AmountAndCommodity, Posting, TransactionState};
+        use std::str::FromStr;
+
+        let transaction = Transaction {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![Posting {
+                account: "Assets:Cash".to_owned(),
+                amount: Some(AmountAndCommodity::new(
+                    BigDecimal::from_str("150.00").unwrap(),
+                    "EUR".to_owned(),
+                )),
+                comment: None,
+                tags: vec![],
+                price: None,
+                balance_assertion: None,
+            }],
+        };
+        let amount = |t: &[Transaction]| t[0].postings[0].amount.as_ref().unwrap().to_string();
+
+        let mut transactions = vec![transaction.clone()];
+        normalize_amounts(&mut transactions, &OutputConfig::default());
+        assert_eq!(amount(&transactions), "150 EUR");
+
+        let mut transactions = vec![transaction];
+        let output = OutputConfig {
+            keep_amount_precision: true,
+            ..Default::default()
+        };
+        normalize_amounts(&mut transactions, &output);
+        assert_eq!(amount(&transactions), "150.00 EUR");
+    }
+
+    #[cfg(feature = "revolut")]
+    #[test]
+    fn tag_revolut_import() {
+        use hledger_import::hledger::output::TransactionState;
+
+        let mut transactions = vec![Transaction {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Test".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![],
+        }];
+        let importer: Box<dyn HledgerImporter> = Importer::Revolut.into();
+        tag_importer(&mut transactions, importer.as_ref());
+        assert_eq!(transactions[0].tags.len(), 1);
+        assert_eq!(transactions[0].tags[0].to_string(), "bank: revolut");
+    }
+
+    #[test]
+    fn list_enabled_importers() {
+        let list = list_importers();
+        assert_eq!(list.lines().count(), Importer::value_variants().len());
+        #[cfg(feature = "revolut")]
+        assert!(list
+            .lines()
+            .any(|l| l.starts_with("revolut ") && l.contains("CSV") && l.ends_with("[revolut]")));
+        #[cfg(feature = "erste")]
+        assert!(list.lines().any(|l| l.starts_with("erste ")));
+        #[cfg(not(feature = "paypal"))]
+        assert!(!list.contains("paypal"));
+    }
+
+    #[test]
+    fn profile_report() {
+        let mut profile = Profile::new(false);
+        profile.lap("read and parse");
+        assert_eq!(profile.report(), None);
+
+        let mut profile = Profile::new(true);
+        profile.lap("read and parse");
+        profile.lap("format and output");
+        let report = profile.report().unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("[PROFILE] read and parse "));
+        assert!(lines[1].starts_with("[PROFILE] format and output "));
+        assert!(lines[2].starts_with("[PROFILE] total "));
+        assert!(lines.iter().all(|l| l.ends_with(" ms")));
+    }
+
+    #[test]
+    fn report_enabled_features() {
+        let features = enabled_features();
+        assert_eq!(features.contains(&"erste"), cfg!(feature = "erste"));
+        assert_eq!(features.contains(&"paypal"), cfg!(feature = "paypal"));
+        assert_eq!(features.contains(&"remote"), cfg!(feature = "remote"));
+        #[cfg(all(
+            feature = "cardcomplete",
+            feature = "erste",
+            feature = "flatex",
+            feature = "paypal",
+            feature = "revolut",
+            not(feature = "remote")
+        ))]
+        assert!(features_report()
+            .ends_with("\nfeatures: cardcomplete, erste, flatex, paypal, revolut\n"));
+    }
+
+    #[test]
+    fn commodity_directives_before_transactions() {
+        let transactions =
+            "2024-05-10 * Test\n    Assets:Cash           -10,00 EUR\n    Expenses:Test";
+        let rules = merge_commodity_rules(
+            &Some(vec!["1.000,00 EUR".to_owned()]),
+            vec!["1.000,00 EUR".to_owned(), "$1,000.00".to_owned()],
+        );
+
+        let journal = with_header(
+            Some("Revolut Import"),
+            &with_commodity_directives(&rules, transactions),
+        );
+        let lines: Vec<&str> = journal.lines().skip_while(|l| l.starts_with(';')).collect();
+        assert_eq!(
+            &lines[..4],
+            [
+                "commodity 1.000,00 EUR",
+                "commodity $1,000.00",
+                "",
+                "2024-05-10 * Test"
+            ]
+        );
+
+        assert_eq!(with_commodity_directives(&None, transactions), transactions);
+    }
+
+    #[test]
+    fn journal_without_header() {
+        let transactions =
+            "2024-05-10 * Test\n    Assets:Cash           -10 EUR\n    Expenses:Test";
+
+        let journal = with_header(None, transactions);
+        assert!(journal.starts_with("2024-05-10 * Test\n"));
+
+        let journal = with_header(Some("Revolut Import"), transactions);
+        assert!(journal.starts_with("; ****"));
+        assert!(journal
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("; Revolut Import"));
+        assert!(journal.contains("\n2024-05-10 * Test\n"));
+    }
+
+    #[test]
+    fn print_env_config_path() {
+        let path = std::env::temp_dir().join("hledger-import-missing-config.toml");
+        std::env::set_var("HLEDGER_IMPORT_CONFIG", &path);
+        assert_eq!(
+            config_path_report().unwrap(),
+            format!("{} (not found)\n", path.display())
+        );
+
+        let path = std::env::temp_dir().join("hledger-import-print-config.toml");
+        std::fs::write(&path, "").unwrap();
+        std::env::set_var("HLEDGER_IMPORT_CONFIG", &path);
+        assert_eq!(
+            config_path_report().unwrap(),
+            format!("{} (exists)\n", path.display())
+        );
+        std::env::remove_var("HLEDGER_IMPORT_CONFIG");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn split_by_asset_account() {
+        use hledger_import::hledger::output::{Posting, TransactionState};
+
+        let transaction = |payee: &str, account: &str| Transaction {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![Posting {
+                account: account.to_owned(),
+                amount: None,
+                comment: None,
+                tags: vec![],
+                price: None,
+                balance_assertion: None,
+            }],
+        };
+        let transactions = vec![
+            transaction("Coffee", "Assets:Revolut"),
+            transaction("Groceries", "Assets:Cash Box"),
+            transaction("Books", "Assets:Revolut"),
+        ];
+
+        let files = group_by_asset_account(transactions.clone()).unwrap();
+        let names: Vec<&str> = files.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            vec!["assets-cash_box.journal", "assets-revolut.journal"]
+        );
+        let payees: Vec<&str> = files["assets-revolut.journal"]
+            .iter()
+            .map(|t| t.payee.as_str())
+            .collect();
+        assert_eq!(payees, vec!["Coffee", "Books"]);
+
+        // accounts that only differ in spacing must not share a file
+        let mut transactions = transactions;
+        transactions.push(transaction("Rent", "Assets:Cash_Box"));
+        let error = group_by_asset_account(transactions).unwrap_err();
+        assert!(matches!(
+            error,
+            ImportError::OutputFileCollision(first, second, file)
+                if first == "Assets:Cash Box" && second == "Assets:Cash_Box" && file == "assets-cash_box.journal"
+        ));
+    }
+
+    #[test]
+    fn write_journal_without_overwrite() {
+        let path = std::env::temp_dir().join("hledger-import-write-journal.journal");
+        let _ = std::fs::remove_file(&path);
+        write_journal(&path, "first\n", false).unwrap();
+        assert!(matches!(
+            write_journal(&path, "second\n", false),
+            Err(ImportError::OutputFileExists(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\n");
+        write_journal(&path, "second\n", true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "flatex")]
+    #[test]
+    fn count_with_known_duplicates() {
+        let dir = std::env::temp_dir().join("hledger-import-count-only");
+        std::fs::create_dir_all(&dir).unwrap();
+        let header =
+            "Buchungstag;Valuta;Empfänger;Zahlungspfl.;TA.Nr.;Buchungsinformationen;Betrag;\n";
+        let first = dir.join("1.csv");
+        std::fs::write(
+            &first,
+            format!(
+                "{}03.06.2024;04.06.2024;Someone;AT000000000000000000;TA1;Transfer;1.000,50;EUR
+05.06.2024;05.06.2024;Someone;AT000000000000000000;TA2;Transfer;-20,00;EUR
+",
+                header
+            ),
+        )
+        .unwrap();
+        // overlapping export
+        let second = dir.join("2.csv");
+        std::fs::write(
+            &second,
+            format!(
+                "{}05.06.2024;05.06.2024;Someone;AT000000000000000000;TA2;Transfer;-20,00;EUR
+06.06.2024;06.06.2024;Someone;AT000000000000000000;TA3;Transfer;-5,00;EUR
+",
+                header
+            ),
+        )
+        .unwrap();
+        let config = toml::from_str::<ImporterConfig>(
+            "ibans = []
+            cards = []
+            mapping = []
+            creditor_and_debitor_mapping = []
+
+            [sepa]
+            creditors = []
+            mandates = []
+
+            [transfer_accounts]
+            bank = \"Assets:Bank\"
+            cash = \"Assets:Cash\"
+
+            [flatex_csv]
+            account = \"Assets:Flatex\"
+            ",
+        )
+        .unwrap();
+
+        let known = HashSet::from(["TA1".to_owned()]);
+        let (total, new) =
+            count_transactions(&Importer::FlatexCSV, &[first, second], &config, known).unwrap();
+        assert_eq!(total, 4);
+        let codes: Vec<_> = new.iter().filter_map(|t| t.code.as_deref()).collect();
+        assert_eq!(codes, vec!["TA2", "TA3"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "revolut")]
+    #[test]
+    fn split_by_asset_account_with_sorted_postings() {
+        use bigdecimal::BigDecimal;
+        use hledger_import::hledger::output::{AmountAndCommodity, Posting, TransactionState};
+        use std::str::FromStr;
+
+        let posting = |account: &str, amount: &str| Posting {
+            account: account.to_owned(),
+            amount: Some(AmountAndCommodity::new(
+                BigDecimal::from_str(amount).unwrap(),
+                "EUR".to_owned(),
+            )),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        // the asset account sorts after the expense accounts
+        let transaction = Transaction {
+            date: chrono::NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: "Grocery".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                posting("Liabilities:Card", "-30.00"),
+                posting("Expenses:Household", "10.00"),
+                posting("Expenses:Food", "20.00"),
+            ],
+        };
+        let config = toml::from_str::<ImporterConfig>(
+            "ibans = []
+            cards = []
+            mapping = []
+            creditor_and_debitor_mapping = []
+
+            [hledger]
+            path = \"hledger-import-missing-hledger\"
+            format_fallback = \"raw\"
+
+            [output]
+            sort_postings = true
+
+            [sepa]
+            creditors = []
+            mandates = []
+
+            [transfer_accounts]
+            bank = \"Assets:Bank\"
+            cash = \"Assets:Cash\"
+            ",
+        )
+        .unwrap();
+        let importer: Box<dyn HledgerImporter> = Importer::Revolut.into();
+
+        let files = group_by_asset_account(vec![transaction]).unwrap();
+        let names: Vec<&str> = files.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["liabilities-card.journal"]);
+
+        let journal = render_journal(
+            &config,
+            importer.as_ref(),
+            &files["liabilities-card.journal"],
+            false,
+        )
+        .unwrap();
+        let accounts: Vec<&str> = journal
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .collect();
+        assert_eq!(
+            accounts,
+            vec!["Expenses:Food", "Expenses:Household", "Liabilities:Card"]
+        );
+    }
 }
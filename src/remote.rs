@@ -0,0 +1,200 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::error::{ImportError, Result};
+
+/// environment variable holding the value of the `Authorization` header of downloads, e.g. `Bearer <token>`
+pub const AUTHORIZATION_ENV: &str = "HLEDGER_IMPORT_AUTHORIZATION";
+
+/// An input file downloaded to a temporary file in a private directory, which is removed when dropped.
+pub struct Download {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Download {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Creates a new directory only accessible by the current user in the temporary directory.
+/// Existing directories are never reused, so that other users can not prepare (e.g. symlink) the download path.
+fn private_temp_dir() -> std::io::Result<PathBuf> {
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    for attempt in 0..100 {
+        let dir = std::env::temp_dir().join(format!(
+            "hledger-import-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            attempt
+        ));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "no unused temporary directory",
+    ))
+}
+
+/// input given as `http://` or `https://` URL instead of a path
+pub fn is_url(input: &Path) -> bool {
+    input
+        .to_str()
+        .is_some_and(|input| input.starts_with("https://") || input.starts_with("http://"))
+}
+
+/// Downloads the URL with `curl` to a temporary file (keeping the file name of the URL) in a private directory.
+/// The `Authorization` header is taken from the environment variable `HLEDGER_IMPORT_AUTHORIZATION`
+/// and passed to curl on standard input, so that it does not show up in the process list.
+pub fn download(url: &str) -> Result<Download> {
+    let file_name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit('/').next())
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+        .unwrap_or("download");
+    let dir =
+        private_temp_dir().map_err(|e| ImportError::Download(url.to_owned(), e.to_string()))?;
+    let download = Download {
+        path: dir.join(file_name),
+        dir,
+    };
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(download.path())
+        .map_err(|e| ImportError::Download(url.to_owned(), e.to_string()))?;
+
+    let mut process = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--header", "@-", "--output"])
+        .arg(download.path())
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error(url, e))?;
+    if let Some(mut stdin) = process.stdin.take() {
+        if let Ok(authorization) = std::env::var(AUTHORIZATION_ENV) {
+            writeln!(stdin, "Authorization: {}", authorization.trim())
+                .map_err(|e| ImportError::Download(url.to_owned(), e.to_string()))?;
+        }
+    }
+    let output = process
+        .wait_with_output()
+        .map_err(|e| ImportError::Download(url.to_owned(), e.to_string()))?;
+    if !output.status.success() {
+        return Err(ImportError::Download(
+            url.to_owned(),
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    Ok(download)
+}
+
+fn spawn_error(url: &str, e: std::io::Error) -> ImportError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        ImportError::Download(
+            url.to_owned(),
+            "curl not found on PATH, it is required for URL inputs (feature \"remote\")".to_owned(),
+        )
+    } else {
+        ImportError::Download(url.to_owned(), e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn download_from_mock_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/exports/revolut.csv?month=5",
+            listener.local_addr().unwrap()
+        );
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                request.push(line.trim().to_owned());
+            }
+            let body = "Type,Product\nTOPUP,Current\n";
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            request
+        });
+
+        std::env::set_var(AUTHORIZATION_ENV, "Bearer secret");
+        let download = download(&url).unwrap();
+        std::env::remove_var(AUTHORIZATION_ENV);
+
+        let request = server.join().unwrap();
+        assert_eq!(&request[0], "GET /exports/revolut.csv?month=5 HTTP/1.1");
+        assert!(request.contains(&"Authorization: Bearer secret".to_owned()));
+        assert_eq!(download.path().file_name().unwrap(), "revolut.csv");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = download.path().parent().unwrap();
+            let mode = std::fs::metadata(dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        assert_eq!(
+            std::fs::read_to_string(download.path()).unwrap(),
+            "Type,Product\nTOPUP,Current\n"
+        );
+
+        let dir = download.path().parent().unwrap().to_path_buf();
+        drop(download);
+        assert!(!dir.exists());
+
+        assert!(is_url(Path::new("https://example.org/export.csv")));
+        assert!(!is_url(Path::new("export.csv")));
+    }
+
+    #[test]
+    fn missing_curl() {
+        let url = "https://example.org/export.csv";
+        let e = Command::new("hledger-import-missing-curl")
+            .spawn()
+            .expect_err("program should not exist");
+        let message = spawn_error(url, e).to_string();
+        assert!(message.contains(url));
+        assert!(message.contains("curl not found on PATH"));
+    }
+}
@@ -0,0 +1,177 @@
+use bigdecimal::{BigDecimal, RoundingMode};
+
+use crate::config::SplitRule;
+use crate::error::{ImportError, Result};
+use crate::hledger::output::{AmountAndCommodity, Posting, Transaction};
+
+/// Replaces the offset posting (the posting without amount) of transactions matching a split rule
+/// by one posting per split account. The amounts are rounded to the precision of the transaction
+/// (at least cents), the rounding remainder is posted to the last account.
+pub fn apply_splits(transactions: &mut [Transaction], rules: &[SplitRule]) -> Result<()> {
+    for rule in rules {
+        let total: BigDecimal = rule.accounts.iter().map(|a| &a.percentage).sum();
+        if rule.accounts.is_empty() || total != BigDecimal::from(100) {
+            return Err(ImportError::InvalidConfig(format!(
+                "the percentages of split \"{}\" must sum up to 100",
+                &rule.search
+            )));
+        }
+    }
+
+    for transaction in transactions.iter_mut() {
+        for rule in rules {
+            if rule.matches(&transaction.payee)? {
+                split(transaction, rule);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn split(transaction: &mut Transaction, rule: &SplitRule) {
+    let residual = transaction.residual();
+    let plugs: Vec<usize> = transaction
+        .postings
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.amount.is_none())
+        .map(|(i, _)| i)
+        .collect();
+    // only transactions with a single offset posting in a single commodity can be split
+    let (Some((commodity, amount)), [plug]) = (residual.iter().next(), plugs.as_slice()) else {
+        return;
+    };
+    if residual.len() != 1 {
+        return;
+    }
+
+    let offset_amount = -amount;
+    let decimals = offset_amount.fractional_digit_count().max(2);
+    let mut remainder = offset_amount.clone();
+    let mut postings = Vec::with_capacity(rule.accounts.len());
+    for (i, target) in rule.accounts.iter().enumerate() {
+        let amount = if i + 1 == rule.accounts.len() {
+            remainder.clone()
+        } else {
+            (&offset_amount * &target.percentage / BigDecimal::from(100))
+                .with_scale_round(decimals, RoundingMode::HalfEven)
+        };
+        remainder -= &amount;
+        postings.push(Posting {
+            account: target.account.clone(),
+            amount: Some(AmountAndCommodity::new(amount, commodity.clone())),
+            comment: None,
+            tags: Vec::new(),
+            price: None,
+            balance_assertion: None,
+        });
+    }
+
+    let plug = *plug;
+    transaction.postings.splice(plug..plug + 1, postings);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::NaiveDate;
+
+    use crate::config::SplitTarget;
+    use crate::hledger::output::TransactionState;
+
+    use super::*;
+
+    fn transaction(payee: &str, amount: &str) -> Transaction {
+        Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                Posting {
+                    account: "Assets:Bank".to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::from_str(amount).unwrap(),
+                        "EUR".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+                Posting {
+                    account: "Expenses:Utilities".to_owned(),
+                    amount: None,
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+            ],
+        }
+    }
+
+    fn rule(percentages: &[(&str, &str)]) -> SplitRule {
+        SplitRule {
+            search: "Energy".to_owned(),
+            accounts: percentages
+                .iter()
+                .map(|(account, percentage)| SplitTarget {
+                    account: account.to_string(),
+                    percentage: BigDecimal::from_str(percentage).unwrap(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn split_odd_cent_amount() {
+        let rules = vec![rule(&[
+            ("Expenses:Electricity", "60"),
+            ("Expenses:Gas", "40"),
+        ])];
+        let mut transactions = vec![
+            transaction("City Energy", "-100.01"),
+            transaction("Grocery Store", "-20.00"),
+        ];
+
+        apply_splits(&mut transactions, &rules).unwrap();
+
+        let postings: Vec<String> = transactions[0]
+            .postings
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            postings,
+            vec![
+                "    Assets:Bank     -100.01 EUR",
+                "    Expenses:Electricity     60.01 EUR",
+                "    Expenses:Gas     40.00 EUR",
+            ]
+        );
+        assert!(transactions[0].balanced());
+        assert!(transactions[0].residual().is_empty());
+
+        // not matching transactions are left untouched
+        assert_eq!(transactions[1], transaction("Grocery Store", "-20.00"));
+    }
+
+    #[test]
+    fn split_percentages_must_sum_up_to_100() {
+        let rules = vec![rule(&[
+            ("Expenses:Electricity", "60"),
+            ("Expenses:Gas", "30"),
+        ])];
+        let mut transactions = vec![transaction("City Energy", "-100.01")];
+        assert!(matches!(
+            apply_splits(&mut transactions, &rules),
+            Err(ImportError::InvalidConfig(_))
+        ));
+    }
+}
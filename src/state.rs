@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ImporterConfig;
+use crate::error::{ImportError, Result};
+use crate::hledger::output::Transaction;
+
+/// State that is kept between two runs of the importer (e.g. the date of the last imported transaction)
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImportState {
+    /// date of the latest imported transaction, per importer
+    #[serde(default)]
+    pub last_import: BTreeMap<String, NaiveDate>,
+}
+
+impl ImportState {
+    /// The state file is located next to the configuration file, unless configured otherwise.
+    pub fn path(config: &ImporterConfig) -> Result<PathBuf> {
+        match &config.state_file {
+            Some(path) => Ok(path.clone()),
+            None => {
+                let config_path = ImporterConfig::path()?;
+                let dir = config_path.parent().unwrap_or(Path::new("."));
+                Ok(dir.join("state.toml"))
+            }
+        }
+    }
+
+    /// loads the state, a missing state file results in an empty state
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            std::fs::read_to_string(path).map_err(|_| ImportError::StateFile(path.to_owned()))?;
+        toml::from_str(&content).map_err(|_| ImportError::StateFile(path.to_owned()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string(self).map_err(|_| ImportError::StateFile(path.to_owned()))?;
+        std::fs::write(path, content).map_err(|_| ImportError::StateFile(path.to_owned()))
+    }
+
+    /// Keeps only the transactions that are dated on or after the last import of the given importer.
+    /// The day of the last import is kept, since it may contain later bookings that were not part of
+    /// the previous export; the transactions already imported on that day are left to deduplication by code.
+    pub fn since_last_import(
+        &self,
+        importer: &str,
+        transactions: Vec<Transaction>,
+    ) -> Vec<Transaction> {
+        match self.last_import.get(importer) {
+            Some(last_import) => transactions
+                .into_iter()
+                .filter(|t| t.date >= *last_import)
+                .collect(),
+            None => transactions,
+        }
+    }
+
+    /// remembers the date of the latest given transaction as last import of the given importer
+    pub fn update(&mut self, importer: &str, transactions: &[Transaction]) {
+        let latest = transactions
+            .iter()
+            .map(|t| t.date)
+            .chain(self.last_import.get(importer).copied())
+            .max();
+        if let Some(latest) = latest {
+            self.last_import.insert(importer.to_owned(), latest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hledger::output::TransactionState;
+
+    use super::*;
+
+    fn transaction(day: u32) -> Transaction {
+        Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, day).unwrap(),
+            code: None,
+            payee: format!("Payee {}", day),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![],
+        }
+    }
+
+    #[test]
+    fn second_run_yields_last_day_only() {
+        let export = vec![transaction(1), transaction(3), transaction(2)];
+        let mut state = ImportState::default();
+
+        let first_run = state.since_last_import("revolut", export.clone());
+        assert_eq!(first_run.len(), 3);
+        state.update("revolut", &export);
+        assert_eq!(
+            state.last_import.get("revolut"),
+            Some(&NaiveDate::from_ymd_opt(2024, 5, 3).unwrap())
+        );
+
+        // later bookings of the last day are not lost
+        let second_run = state.since_last_import("revolut", export.clone());
+        assert_eq!(second_run, vec![transaction(3)]);
+        state.update("revolut", &second_run);
+
+        // the watermark is kept per importer
+        let other_importer = state.since_last_import("erste", export.clone());
+        assert_eq!(other_importer.len(), 3);
+
+        let mut extended_export = export;
+        extended_export.push(transaction(4));
+        let third_run = state.since_last_import("revolut", extended_export);
+        assert_eq!(third_run, vec![transaction(3), transaction(4)]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join("hledger-import-state-test.toml");
+        let _ = std::fs::remove_file(&path);
+
+        let state = ImportState::load(&path).unwrap();
+        assert_eq!(state, ImportState::default());
+
+        let mut state = ImportState::default();
+        state.update("erste", &[transaction(12)]);
+        state.save(&path).unwrap();
+
+        let loaded = ImportState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::TransferAccounts;
+use crate::hledger::output::{AmountAndCommodity, Posting, Tag, Transaction};
+
+/// Merges transfers between your own accounts, that appear in the exports of both accounts.
+///
+/// Two transactions are merged, if both are posted to a transfer account, their amounts cancel out
+/// and they are at most one day apart. The merged transaction keeps the outgoing transaction
+/// (date, payee, code) and posts directly between both accounts, the code of the incoming
+/// transaction is kept as `transfer_code` tag.
+pub fn merge_transfers(
+    transactions: Vec<Transaction>,
+    transfer_accounts: &TransferAccounts,
+) -> Vec<Transaction> {
+    let accounts = [&transfer_accounts.bank, &transfer_accounts.cash];
+    let candidates: Vec<Option<&Posting>> = transactions
+        .iter()
+        .map(|t| own_posting(t, &accounts))
+        .collect();
+
+    // index of the incoming transaction merged into the outgoing transaction
+    let mut merged_into: Vec<Option<usize>> = vec![None; transactions.len()];
+    let mut matched = vec![false; transactions.len()];
+    for (i, outgoing) in candidates.iter().enumerate() {
+        let Some(outgoing) = outgoing.filter(|p| is_outgoing(p)) else {
+            continue;
+        };
+        let incoming = candidates.iter().enumerate().position(|(j, incoming)| {
+            !matched[j]
+                && incoming.is_some_and(|incoming| {
+                    incoming.account != outgoing.account
+                        && cancel_out(outgoing, incoming)
+                        && (transactions[i].date - transactions[j].date)
+                            .num_days()
+                            .abs()
+                            <= 1
+                })
+        });
+        if let Some(j) = incoming {
+            matched[i] = true;
+            matched[j] = true;
+            merged_into[i] = Some(j);
+        }
+    }
+
+    let mut incoming_postings: Vec<Option<(Posting, Option<String>)>> = transactions
+        .iter()
+        .zip(candidates.iter())
+        .map(|(t, p)| p.map(|p| (p.clone(), t.code.clone())))
+        .collect();
+    let count = merged_into.iter().filter(|m| m.is_some()).count();
+    let mut result = Vec::with_capacity(transactions.len() - count);
+    for (i, mut transaction) in transactions.into_iter().enumerate() {
+        match merged_into[i] {
+            Some(j) => {
+                let (incoming, code) = incoming_postings[j].take().expect("matched only once");
+                transaction
+                    .postings
+                    .retain(|p| !accounts.contains(&&p.account));
+                transaction.postings.push(incoming);
+                if let Some(code) = code {
+                    transaction
+                        .tags
+                        .push(Tag::new_val("transfer_code".to_owned(), code));
+                }
+                result.push(transaction);
+            }
+            None if matched[i] => {}
+            None => result.push(transaction),
+        }
+    }
+
+    if count > 0 {
+        log::info!("merged {} transfer(s) between your accounts", count);
+    }
+    result
+}
+
+/// The posting of your own account of a transfer, i.e. of a transaction with exactly two postings,
+/// one of them to a transfer account without amount or with the opposite amount.
+fn own_posting<'a>(
+    transaction: &'a Transaction,
+    transfer_accounts: &[&String],
+) -> Option<&'a Posting> {
+    let [first, second] = transaction.postings.as_slice() else {
+        return None;
+    };
+    let (own, transfer) = if transfer_accounts.contains(&&second.account) {
+        (first, second)
+    } else if transfer_accounts.contains(&&first.account) {
+        (second, first)
+    } else {
+        return None;
+    };
+    let amount = own.amount.as_ref()?;
+    match &transfer.amount {
+        None => Some(own),
+        Some(transfer_amount) if cancels(amount, transfer_amount) => Some(own),
+        Some(_) => None,
+    }
+}
+
+fn is_outgoing(posting: &Posting) -> bool {
+    posting
+        .amount
+        .as_ref()
+        .is_some_and(|a| a.amount < bigdecimal::BigDecimal::from(0))
+}
+
+fn cancel_out(a: &Posting, b: &Posting) -> bool {
+    match (&a.amount, &b.amount) {
+        (Some(a), Some(b)) => cancels(a, b),
+        _ => false,
+    }
+}
+
+fn cancels(a: &AmountAndCommodity, b: &AmountAndCommodity) -> bool {
+    a.commodity == b.commodity && a.amount == -b.amount.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::TransactionState;
+
+    use super::*;
+
+    fn transfer(day: u32, code: &str, account: &str, amount: &str) -> Transaction {
+        Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 6, day).unwrap(),
+            code: Some(code.to_owned()),
+            payee: "Transfer".to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings: vec![
+                Posting {
+                    account: account.to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::from_str(amount).unwrap(),
+                        "EUR".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+                Posting {
+                    account: "Assets:Transfers".to_owned(),
+                    amount: None,
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn merge_transfer_pair_of_two_exports() {
+        let transfer_accounts = TransferAccounts {
+            bank: "Assets:Transfers".to_owned(),
+            cash: "Assets:Cash".to_owned(),
+        };
+        // the incoming side of the transfer is booked a day later by the other bank
+        let transactions = vec![
+            transfer(3, "IN1", "Assets:Flatex", "500.00"),
+            transfer(5, "IN2", "Assets:Flatex", "20.00"),
+            transfer(2, "OUT1", "Assets:Erste:Checking", "-500.00"),
+            transfer(1, "OUT2", "Assets:Erste:Checking", "-20.00"),
+        ];
+
+        let result = merge_transfers(transactions, &transfer_accounts);
+
+        assert_eq!(result.len(), 3);
+        let merged = &result[1];
+        assert_eq!(merged.code, Some("OUT1".to_owned()));
+        assert_eq!(merged.date, NaiveDate::from_ymd_opt(2024, 6, 2).unwrap());
+        let accounts: Vec<&str> = merged.postings.iter().map(|p| p.account.as_str()).collect();
+        assert_eq!(accounts, vec!["Assets:Erste:Checking", "Assets:Flatex"]);
+        assert!(merged.balanced());
+        assert_eq!(
+            merged.tags,
+            vec![Tag::new_val("transfer_code".to_owned(), "IN1".to_owned())]
+        );
+
+        // four days apart, not merged
+        assert_eq!(result[0].code, Some("IN2".to_owned()));
+        assert_eq!(result[2].code, Some("OUT2".to_owned()));
+    }
+}
@@ -0,0 +1,378 @@
+use crate::config::{EmptyPostingsHandling, ImporterConfig};
+use crate::error::{ImportError, Result};
+use crate::hledger::output::Transaction;
+
+/// Checks the transactions produced by an importer for transactions without any postings.
+/// Such transactions are usually caused by missing IBAN/card mappings and would either be rejected
+/// by hledger or silently disappear.
+pub fn check_empty_postings(
+    transactions: Vec<Transaction>,
+    handling: EmptyPostingsHandling,
+) -> Result<Vec<Transaction>> {
+    let mut result = Vec::with_capacity(transactions.len());
+    for transaction in transactions {
+        if !transaction.postings.is_empty() {
+            result.push(transaction);
+            continue;
+        }
+
+        let description = describe(&transaction);
+        match handling {
+            EmptyPostingsHandling::Warn => {
+                log::warn!(
+                    "skipping transaction \"{}\" without postings, please check your account mappings",
+                    description
+                );
+            }
+            EmptyPostingsHandling::Error => {
+                return Err(ImportError::EmptyPostings(description));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// A transaction is considered unclassified, if it was posted to the fallback account
+/// or if it lacks an offset posting (e.g. because no rule matched).
+/// Balance assertions (transactions that only consist of postings with balance assertion) are not checked.
+pub fn is_unclassified(transaction: &Transaction, config: &ImporterConfig) -> bool {
+    if !transaction.postings.is_empty()
+        && transaction
+            .postings
+            .iter()
+            .all(|p| p.balance_assertion.is_some())
+    {
+        return false;
+    }
+    transaction.postings.len() < 2
+        || transaction
+            .postings
+            .iter()
+            .any(|p| p.account.is_empty() || config.fallback_account.as_ref() == Some(&p.account))
+}
+
+/// Fails with a list of all unclassified transactions, if there are any.
+pub fn check_unclassified(transactions: &[Transaction], config: &ImporterConfig) -> Result<()> {
+    let unclassified: Vec<String> = transactions
+        .iter()
+        .filter(|t| is_unclassified(t, config))
+        .map(describe)
+        .collect();
+    if unclassified.is_empty() {
+        Ok(())
+    } else {
+        Err(ImportError::Unclassified(unclassified))
+    }
+}
+
+/// Drops transactions whose primary (first) amount is smaller than `min_abs_amount` in absolute value,
+/// or posts their offset to the `dust_account` if one is configured. Balance assertions are kept.
+pub fn drop_dust(transactions: Vec<Transaction>, config: &ImporterConfig) -> Vec<Transaction> {
+    let Some(min_abs_amount) = &config.min_abs_amount else {
+        return transactions;
+    };
+    let is_dust = |transaction: &Transaction| {
+        transaction
+            .postings
+            .iter()
+            .all(|p| p.balance_assertion.is_none())
+            && transaction
+                .postings
+                .iter()
+                .find_map(|p| p.amount.as_ref())
+                .is_some_and(|a| a.amount.abs() < *min_abs_amount)
+    };
+
+    let mut result = Vec::with_capacity(transactions.len());
+    for mut transaction in transactions {
+        if !is_dust(&transaction) {
+            result.push(transaction);
+            continue;
+        }
+        match &config.dust_account {
+            Some(dust_account) => {
+                transaction
+                    .postings
+                    .iter_mut()
+                    .filter(|p| p.amount.is_none())
+                    .for_each(|p| p.account.clone_from(dust_account));
+                result.push(transaction);
+            }
+            None => log::info!(
+                "dropping transaction \"{}\" below min_abs_amount",
+                describe(&transaction)
+            ),
+        }
+    }
+    result
+}
+
+/// Fails for the first transaction with a commodity (of an amount, price or balance assertion)
+/// that is not listed in `strict_commodities`, unless the list is empty.
+pub fn check_commodities(transactions: &[Transaction], config: &ImporterConfig) -> Result<()> {
+    if config.strict_commodities.is_empty() {
+        return Ok(());
+    }
+    for transaction in transactions {
+        let unknown = transaction
+            .postings
+            .iter()
+            .flat_map(|p| [&p.amount, &p.price, &p.balance_assertion])
+            .flatten()
+            .find(|a| !config.strict_commodities.contains(&a.commodity));
+        if let Some(amount) = unknown {
+            return Err(ImportError::UnknownCommodity(
+                describe(transaction),
+                amount.commodity.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// short description of a transaction for warnings and error messages
+fn describe(transaction: &Transaction) -> String {
+    format!(
+        "{} {}",
+        transaction.date.format("%Y-%m-%d"),
+        &transaction.payee
+    )
+}
+
+/// splits the transactions into fully classified and unclassified transactions (in this order)
+pub fn split_unclassified(
+    transactions: Vec<Transaction>,
+    config: &ImporterConfig,
+) -> (Vec<Transaction>, Vec<Transaction>) {
+    transactions
+        .into_iter()
+        .partition(|t| !is_unclassified(t, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, Posting, TransactionState};
+
+    use super::*;
+
+    fn transaction(payee: &str, postings: Vec<Posting>) -> Transaction {
+        Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 11, 22).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![],
+            postings,
+        }
+    }
+
+    #[test]
+    fn skip_transaction_without_asset_account() {
+        let transactions = vec![
+            transaction(
+                "Store",
+                vec![Posting {
+                    account: "Assets:Cash".to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::from(-10),
+                        "EUR".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                }],
+            ),
+            transaction("Unknown Card", vec![]),
+        ];
+
+        let result = check_empty_postings(transactions.clone(), EmptyPostingsHandling::Warn)
+            .expect("empty postings must not cause an error");
+        assert_eq!(result.len(), 1);
+        assert_eq!(&result[0].payee, "Store");
+
+        let result = check_empty_postings(transactions, EmptyPostingsHandling::Error);
+        match result {
+            Err(ImportError::EmptyPostings(description)) => {
+                assert_eq!(description, "2024-11-22 Unknown Card")
+            }
+            _ => panic!("expected an error for a transaction without postings"),
+        }
+    }
+
+    #[test]
+    fn split_classified_and_review_transactions() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Equity:Unassigned\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let posting = |account: &str, amount: Option<i32>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| AmountAndCommodity::new(BigDecimal::from(a), "EUR".to_owned())),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+
+        let transactions = vec![
+            transaction(
+                "Store",
+                vec![
+                    posting("Assets:Cash", Some(-10)),
+                    posting("Expenses:Groceries", None),
+                ],
+            ),
+            transaction(
+                "Unknown",
+                vec![
+                    posting("Assets:Cash", Some(-20)),
+                    posting("Equity:Unassigned", None),
+                ],
+            ),
+            transaction("No Offset", vec![posting("Assets:Cash", Some(-30))]),
+            transaction(
+                "PayPal",
+                vec![posting("Assets:PayPal", Some(-40)), posting("", None)],
+            ),
+        ];
+
+        match check_unclassified(&transactions, &config) {
+            Err(ImportError::Unclassified(list)) => assert_eq!(
+                list,
+                vec![
+                    "2024-11-22 Unknown",
+                    "2024-11-22 No Offset",
+                    "2024-11-22 PayPal"
+                ]
+            ),
+            _ => panic!("expected unclassified transactions to be reported"),
+        }
+        assert!(check_unclassified(&transactions[..1], &config).is_ok());
+
+        let (classified, review) = split_unclassified(transactions, &config);
+        let payees = |t: &Vec<Transaction>| t.iter().map(|t| t.payee.clone()).collect::<Vec<_>>();
+        assert_eq!(payees(&classified), vec!["Store"]);
+        assert_eq!(payees(&review), vec!["Unknown", "No Offset", "PayPal"]);
+    }
+
+    #[test]
+    fn drop_dust_transactions() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+        min_abs_amount = 0.01
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let posting = |account: &str, amount: Option<&str>| Posting {
+            account: account.to_owned(),
+            amount: amount.map(|a| {
+                AmountAndCommodity::new(a.parse::<BigDecimal>().unwrap(), "EUR".to_owned())
+            }),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let transactions = vec![
+            transaction(
+                "Interest",
+                vec![
+                    posting("Assets:Bank", Some("0.001")),
+                    posting("Income:Interest", None),
+                ],
+            ),
+            transaction(
+                "Interest",
+                vec![
+                    posting("Assets:Bank", Some("-0.01")),
+                    posting("Income:Interest", None),
+                ],
+            ),
+        ];
+
+        let result = drop_dust(transactions.clone(), &config);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0], transactions[1]);
+
+        config.dust_account = Some("Income:Dust".to_owned());
+        let result = drop_dust(transactions.clone(), &config);
+        assert_eq!(result.len(), 2);
+        assert_eq!(&result[0].postings[1].account, "Income:Dust");
+        assert_eq!(result[1], transactions[1]);
+    }
+
+    #[test]
+    fn reject_unknown_commodity() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+        strict_commodities = [\"EUR\", \"USD\"]
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let mut config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let posting = |commodity: &str| Posting {
+            account: "Assets:Bank".to_owned(),
+            amount: Some(AmountAndCommodity::new(
+                BigDecimal::from(10),
+                commodity.to_owned(),
+            )),
+            comment: None,
+            tags: vec![],
+            price: None,
+            balance_assertion: None,
+        };
+        let transactions = vec![
+            transaction("Store", vec![posting("EUR")]),
+            transaction("Typo", vec![posting("EURO")]),
+        ];
+
+        assert!(check_commodities(&transactions[..1], &config).is_ok());
+        match check_commodities(&transactions, &config) {
+            Err(ImportError::UnknownCommodity(description, commodity)) => {
+                assert_eq!(description, "2024-11-22 Typo");
+                assert_eq!(commodity, "EURO");
+            }
+            _ => panic!("expected an error for a commodity that is not allowed"),
+        }
+
+        config.strict_commodities.clear();
+        assert!(check_commodities(&transactions, &config).is_ok());
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::config::ImporterConfig;
+use crate::error::{ImportError, Result};
+use crate::hledger::output::{Tag, Transaction};
+use crate::validation::is_unclassified;
+
+/// kind of an issue found in the imported transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// the transaction has no postings at all (e.g. missing IBAN or card mapping)
+    EmptyPostings,
+    /// the transaction was posted to the fallback account or lacks an offset account
+    Fallback,
+    /// the transaction has no valuation date, unlike other transactions of the same import
+    MissingValuation,
+    /// a row of the input file could not be parsed and was skipped
+    UnparsedRow,
+}
+
+/// An issue of a single imported transaction, with enough context to be shown by other tools.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportWarning {
+    pub kind: WarningKind,
+    /// position of the transaction in the import (starting at 0), the line of the input file for unparsed rows
+    pub index: usize,
+    pub date: String,
+    pub payee: String,
+    /// code, postings (`posting_1`, ...) and tags (`tag_<name>`) of the transaction,
+    /// `error`, `raw` (the fields of the row) and `file` of unparsed rows
+    pub fields: BTreeMap<String, String>,
+}
+
+impl ImportWarning {
+    fn new(kind: WarningKind, index: usize, transaction: &Transaction) -> Self {
+        let mut fields = BTreeMap::new();
+        if let Some(code) = &transaction.code {
+            fields.insert("code".to_owned(), code.clone());
+        }
+        for (i, posting) in transaction.postings.iter().enumerate() {
+            let value = match &posting.amount {
+                Some(amount) => format!("{} {}", posting.account, amount),
+                None => posting.account.clone(),
+            };
+            fields.insert(format!("posting_{}", i + 1), value);
+        }
+        for tag in &transaction.tags {
+            fields.insert(
+                format!("tag_{}", tag.name),
+                tag.value.clone().unwrap_or_default(),
+            );
+        }
+        Self {
+            kind,
+            index,
+            date: transaction.date.format("%Y-%m-%d").to_string(),
+            payee: transaction.payee.clone(),
+            fields,
+        }
+    }
+}
+
+/// Rows of the input file that can not be parsed abort the import, unless they are collected as warnings
+/// (see [`crate::HledgerImporter::parse_rows`]).
+#[derive(Debug, Default)]
+pub struct UnparsedRows {
+    collect: bool,
+    pub warnings: Vec<ImportWarning>,
+}
+
+impl UnparsedRows {
+    /// skips rows that can not be parsed and keeps them as warnings
+    pub fn collecting() -> Self {
+        Self {
+            collect: true,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The parsed value of a row, `None` if the row is skipped.
+    /// Errors are returned unless the rows are collected.
+    pub fn check<T>(
+        &mut self,
+        row: u64,
+        raw: Option<&Tag>,
+        result: Result<T>,
+    ) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self.collect => {
+                log::warn!("{}, row skipped", e);
+                let mut fields = BTreeMap::new();
+                fields.insert("error".to_owned(), e.to_string());
+                if let Some(raw) = raw.and_then(|tag| tag.value.clone()) {
+                    fields.insert("raw".to_owned(), raw);
+                }
+                self.warnings.push(ImportWarning {
+                    kind: WarningKind::UnparsedRow,
+                    index: row as usize,
+                    date: String::new(),
+                    payee: String::new(),
+                    fields,
+                });
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Collects the issues of the imported transactions: transactions without postings,
+/// transactions routed to the fallback account and transactions missing a valuation date.
+/// A valuation date is only expected, if other transactions of the import carry one.
+pub fn collect_warnings(
+    transactions: &[Transaction],
+    config: &ImporterConfig,
+) -> Vec<ImportWarning> {
+    // with date_basis = "value" the valuation date is moved to the date and kept as booking tag
+    let has_valuation = |t: &Transaction| {
+        t.tags
+            .iter()
+            .any(|tag| tag.name == "valuation" || tag.name == "booking")
+    };
+    let valuation_expected = transactions.iter().any(has_valuation);
+
+    let mut warnings = Vec::new();
+    for (index, transaction) in transactions.iter().enumerate() {
+        if transaction.postings.is_empty() {
+            warnings.push(ImportWarning::new(
+                WarningKind::EmptyPostings,
+                index,
+                transaction,
+            ));
+        } else if is_unclassified(transaction, config) {
+            warnings.push(ImportWarning::new(
+                WarningKind::Fallback,
+                index,
+                transaction,
+            ));
+        }
+        if valuation_expected && !has_valuation(transaction) {
+            warnings.push(ImportWarning::new(
+                WarningKind::MissingValuation,
+                index,
+                transaction,
+            ));
+        }
+    }
+    warnings
+}
+
+/// writes the warnings as JSON array
+pub fn write_warnings(path: &Path, warnings: &[ImportWarning]) -> Result<()> {
+    let json = serde_json::to_string_pretty(warnings)
+        .map_err(|_| ImportError::OutputFileWrite(path.to_owned()))?;
+    std::fs::write(path, json).map_err(|_| ImportError::OutputFileWrite(path.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::hledger::output::{AmountAndCommodity, Posting, Tag, TransactionState};
+
+    use super::*;
+
+    #[test]
+    fn fallback_row_as_json() {
+        let config_str = "ibans = []
+        cards = []
+        mapping = []
+        creditor_and_debitor_mapping = []
+        fallback_account = \"Equity:Unassigned\"
+
+        [sepa]
+        creditors = []
+        mandates = []
+
+        [transfer_accounts]
+        bank = \"Assets:Bank\"
+        cash = \"Assets:Cash\"
+        ";
+        let config = toml::from_str::<ImporterConfig>(config_str).expect("TOML parsing failed");
+
+        let transaction = |payee: &str, offset: &str| Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            code: None,
+            payee: payee.to_owned(),
+            note: None,
+            state: TransactionState::Cleared,
+            comment: None,
+            tags: vec![Tag::new_val(
+                "valuation".to_owned(),
+                "2024-04-30".to_owned(),
+            )],
+            postings: vec![
+                Posting {
+                    account: "Assets:Bank".to_owned(),
+                    amount: Some(AmountAndCommodity::new(
+                        BigDecimal::from(-10),
+                        "EUR".to_owned(),
+                    )),
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+                Posting {
+                    account: offset.to_owned(),
+                    amount: None,
+                    comment: None,
+                    tags: vec![],
+                    price: None,
+                    balance_assertion: None,
+                },
+            ],
+        };
+        let transactions = vec![
+            transaction("Store", "Expenses:Groceries"),
+            transaction("Unknown", "Equity:Unassigned"),
+        ];
+
+        let warnings = collect_warnings(&transactions, &config);
+        let json = serde_json::to_value(&warnings).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "kind": "fallback",
+                "index": 1,
+                "date": "2024-05-01",
+                "payee": "Unknown",
+                "fields": {
+                    "posting_1": "Assets:Bank -10 EUR",
+                    "posting_2": "Equity:Unassigned",
+                    "tag_valuation": "2024-04-30",
+                },
+            }])
+        );
+    }
+}